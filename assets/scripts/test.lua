@@ -109,50 +109,20 @@ stuff2 = {
   }
 }
 
-stuff3 = {
+-- The same as stuff2, just further left and noisier.
+prefab("stuff3", {
+  extends = "stuff2",
   transform = {
     position = {
-      x = -0.71,
-      y = 0.5,
-      z = 0.0
+      x = -0.71
     }
-  }, 
-  velocity = {
-    x = 0.0,
-    y = 0.0,
-    z = 0.0
   },
   collider = {
-    shape_type = "aabb",
-    shape = {
-      min_x = 0.0,
-      min_y = 0.0,
-      min_z = 0.0,
-      max_x = 0.1,
-      max_y = 0.1,
-      max_z = 0.1
-    },
-    sweep = false,
     on_collide = function(world, this, other)
       print(string.format("%s collided with %s", this:id(), other:id()))
     end
-  },
-  sprite = {
-    bounds = {
-      min_x = 0.0,
-      min_y = 0.0,
-      max_x = 0.1,
-      max_y = 0.1
-    },
-    uv = {
-      min_x = 0.0,
-      min_y = 0.0,
-      max_x = 1.0,
-      max_y = 1.0
-    },
-    image_index = 0,
   }
-}
+})
 
 stuff_map = {
   transform = {
@@ -0,0 +1,72 @@
+extern crate tally_ho;
+extern crate fern;
+extern crate log;
+extern crate specs;
+
+use tally_ho::game;
+use tally_ho::component as comp;
+use tally_ho::resource as res;
+
+use std::env;
+use std::process;
+
+// Loads scripts (and the tile maps they reference) and runs the game logic for a number of ticks, without a GPU.
+// * Usage: headless <ticks> <script> [entity...]
+fn main() {
+    init_logging().unwrap();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    if args.len() < 2 {
+        eprintln!("Usage: headless <ticks> <script> [entity...]");
+        process::exit(1);
+    }
+
+    let ticks: u32 = args[0].parse().unwrap_or_else(|_| {
+        eprintln!("Couldn't parse the tick count '{}'", args[0]);
+        process::exit(1);
+    });
+
+    let builder = args[2..].iter().fold(
+        game::GameBuilder::new(1.0/60.0)
+            .with_core_systems()
+            // Nothing is ever pressed, but scripts can still check.
+            .with_resource(res::InputList::new())
+            .with_script(&args[1]),
+        |builder, lua_name| builder.with_entity(lua_name)
+    );
+
+    let mut game = builder.build().unwrap_or_else(|err| {
+        eprintln!("Couldn't build game: {:?}", err);
+        process::exit(1);
+    });
+
+    for _ in 0..ticks {
+        game.tick();
+    }
+
+    // Print where everything ended up.
+    game.world.exec(|(ents, tran): (specs::Entities, specs::ReadStorage<comp::Transform>)| {
+        use specs::Join;
+
+        for (ent, tran) in (&*ents, &tran).join() {
+            println!("{}: {:?}", ent.id(), tran.pos);
+        }
+    });
+}
+
+fn init_logging() -> Result<(), fern::InitError> {
+    fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "{}: {}",
+                record.level(),
+                message
+            ))
+        })
+        .level(log::LevelFilter::Info)
+        .chain(std::io::stdout())
+        .apply()?;
+
+    Ok(())
+}
@@ -1,12 +1,20 @@
 use ::utility::{Rect2, Rect3};
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+#[cfg(feature = "lua")]
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields};
+#[cfg(feature = "lua")]
+use ::script::parse::{no_field, read_only, vector3_from_lua, callback_to_lua, set_callback};
+#[cfg(feature = "lua")]
+use ::script::types::Vector3f;
+use ::component::Transform;
 
+use std::borrow::Cow;
 use std::ops::Range;
 use std::sync::Arc;
 use std::fmt;
 
+#[cfg(feature = "lua")]
 use rlua::{Value as LuaValue, Result as LuaResult, Error as LuaError, Function as LuaFunction, UserData, UserDataMethods, RegistryKey, Table, Lua};
-use cgmath::{Zero, Vector2, Vector3};
+use cgmath::{Zero, InnerSpace, Vector2, Vector3};
 use specs;
 
 /* NOTE:
@@ -15,7 +23,7 @@ use specs;
     plane: it has no thickness.
 */
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Shape {
     AABB(Rect3<f32>),
     Circle {
@@ -28,6 +36,19 @@ pub enum Shape {
         radius: f32,
         depth: Range<f32>,
     },
+    // A slope filling the rect below the line going from its left edge to its right edge.
+    Slope {
+        rect: Rect3<f32>,
+        // The slope's height at the left and right edges, as fractions of the rect's height (measured up from its 
+        // bottom, towards -y).
+        left: f32,
+        right: f32,
+    },
+    // A convex polygon, the shape other shapes become when they're rotated (or flipped, for slopes).
+    Polygon {
+        points: Vec<Vector2<f32>>,
+        depth: Range<f32>,
+    },
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -50,80 +71,212 @@ impl Shape {
                     pos + o.extend(d.end) + Vector3::new(r*2.0, r*2.0, 0.0)
                 ),
             },
+            &Shape::Slope { rect: r, .. } => Bound {
+                rect: Rect3::new(
+                    pos + r.min,
+                    pos + r.max,
+                ),
+            },
+            &Shape::Polygon { ref points, depth: ref d } => {
+                let min = points.iter().fold(points[0], |min, p| Vector2::new(min.x.min(p.x), min.y.min(p.y)));
+                let max = points.iter().fold(points[0], |max, p| Vector2::new(max.x.max(p.x), max.y.max(p.y)));
+
+                Bound {
+                    rect: Rect3::new(
+                        pos + min.extend(d.start),
+                        pos + max.extend(d.end),
+                    ),
+                }
+            },
+        }
+    }
+
+    // The shape after the transform's rotation and scale are applied to it (relative to its position).
+    pub fn transformed(&self, tran: &Transform) -> Cow<Shape> {
+        if tran.is_translation() {
+            return Cow::Borrowed(self);
+        }
+
+        match self {
+            // Without rotation, AABBs stay AABBs.
+            &Shape::AABB(r) if tran.rotation == 0.0 => {
+                let (min, max) = (tran.rotate_scale(r.min.truncate()), tran.rotate_scale(r.max.truncate()));
+
+                Cow::Owned(Shape::AABB(Rect3::new(
+                    Vector3::new(min.x.min(max.x), min.y.min(max.y), r.min.z),
+                    Vector3::new(min.x.max(max.x), min.y.max(max.y), r.max.z),
+                )))
+            },
+            &Shape::AABB(r) => Cow::Owned(Shape::Polygon {
+                points: vec![
+                    Vector2::new(r.min.x, r.min.y),
+                    Vector2::new(r.max.x, r.min.y),
+                    Vector2::new(r.max.x, r.max.y),
+                    Vector2::new(r.min.x, r.max.y),
+                ].into_iter().map(|x| tran.rotate_scale(x)).collect(),
+                depth: r.min.z .. r.max.z,
+            }),
+            /* NOTE:
+                Circles can't be stretched, so they're scaled by the largest axis of the scale. Rotation only moves 
+                their origin around the entity's position.
+            */
+            &Shape::Circle { offset, radius, ref depth } => {
+                let scale = tran.scale.x.abs().max(tran.scale.y.abs());
+                let origin = tran.rotate_scale(offset + Vector2::new(radius, radius));
+
+                Cow::Owned(Shape::Circle {
+                    offset: origin - Vector2::new(radius * scale, radius * scale),
+                    radius: radius * scale,
+                    depth: depth.clone(),
+                })
+            },
+            &Shape::Slope { .. } | &Shape::Polygon { .. } => {
+                let (points, depth) = self.polygon().unwrap();
+
+                Cow::Owned(Shape::Polygon {
+                    points: points.into_iter().map(|x| tran.rotate_scale(x)).collect(),
+                    depth: depth.0 .. depth.1,
+                })
+            },
+        }
+    }
+
+    // The shape as a convex polygon (relative to its position) and its depth range, if it isn't an AABB or circle.
+    pub fn polygon(&self) -> Option<(Vec<Vector2<f32>>, (f32, f32))> {
+        match self {
+            &Shape::Slope { rect: r, left, right } => {
+                let height = r.max.y - r.min.y;
+
+                Some((
+                    vec![
+                        Vector2::new(r.min.x, r.max.y),
+                        Vector2::new(r.max.x, r.max.y),
+                        Vector2::new(r.max.x, r.max.y - right * height),
+                        Vector2::new(r.min.x, r.max.y - left * height),
+                    ],
+                    (r.min.z, r.max.z)
+                ))
+            },
+            &Shape::Polygon { ref points, ref depth } => Some((points.clone(), (depth.start, depth.end))),
+            _ => None,
+        }
+    }
+}
+
+// Overridden normals any shorter than this are treated as having no direction.
+const MIN_NORMAL_MAGNITUDE2: f32 = 1e-12;
+
+// What to do with a contact, decided by a pre-solve hook before the contact is resolved.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PreSolve {
+    // Resolve the contact as usual.
+    Resolve,
+    // Don't resolve the contact, letting the two colliders pass through eachother.
+    Ignore,
+    // Resolve the contact along this normal instead of the computed one.
+    Normal(Vector3<f32>),
+}
+
+impl PreSolve {
+    /* NOTE:
+        A Lua pre-solve function returns false to ignore the contact, a vec3f to override the normal, or anything
+        else (including nothing) to resolve it as usual. A zero vector has no direction to resolve along, so it's an
+        error (and the contact is resolved as usual).
+    */
+    #[cfg(feature = "lua")]
+    pub fn from_lua(v: LuaValue) -> LuaResult<Self> {
+        match v {
+            LuaValue::Boolean(false) => Ok(PreSolve::Ignore),
+            LuaValue::UserData(ref ud) if ud.is::<Vector3f>()? => {
+                let n = ud.borrow::<Vector3f>()?.0;
+                if n.magnitude2() < MIN_NORMAL_MAGNITUDE2 {
+                    return Err(LuaError::RuntimeError("A pre-solve normal can't be a zero vector".into()));
+                }
+
+                Ok(PreSolve::Normal(n))
+            },
+            _ => Ok(PreSolve::Resolve),
+        }
+    }
+
+    // The overridden normal, if it has a direction (a native hook is able to return a zero one).
+    fn direction(&self) -> Option<Vector3<f32>> {
+        match *self {
+            PreSolve::Normal(n) if n.magnitude2() >= MIN_NORMAL_MAGNITUDE2 => Some(n.normalize()),
+            _ => None,
+        }
+    }
+
+    // The normal to resolve along, given the computed one.
+    pub fn normal(&self, norm: Vector3<f32>) -> Vector3<f32> {
+        self.direction().unwrap_or(norm)
+    }
+
+    // The displacement to resolve with, given the computed one. An overridden normal keeps the displacement's
+    // magnitude, but not its direction.
+    pub fn displacement(&self, disp: Vector3<f32>) -> Vector3<f32> {
+        match self.direction() {
+            Some(n) => n * disp.magnitude(),
+            None => disp,
         }
     }
 }
 
+// Called with (this, other, normal), where the normal points in the direction 'this' would be pushed out of 'other'.
+pub enum PreSolveHook {
+    Native(Box<Fn(specs::Entity, specs::Entity, Vector3<f32>) -> PreSolve + Send + Sync>),
+    #[cfg(feature = "lua")]
+    Script(RegistryKey),
+}
+
 pub struct Collider {
     pub shape: Shape,
 
     pub sweep: bool,
 
+    // Like the Lua pre-solve hook, dropped with the collider (and freed by the game's next update).
+    #[cfg(feature = "lua")]
     pub on_collide: Option<RegistryKey>,
+
+    // Runs on each contact before it's resolved, able to cancel or modify the resolution.
+    pub pre_solve: Option<PreSolveHook>,
+
+    // The direction other colliders are able to pass through this one (making it a one-way platform).
+    pub one_way: Option<Vector3<f32>>,
+    // Passes through one-way colliders (for this tick only).
+    pub drop_through: bool,
     
     // Broad phase index.
     pub index: Option<usize>,
 }
 
 impl Collider {
-    pub fn new(shape: Shape, sweep: bool, on_collide: Option<RegistryKey>) -> Collider {
+    pub fn new(shape: Shape, sweep: bool) -> Collider {
         Collider {
             shape,
             sweep,
-            on_collide,
+            #[cfg(feature = "lua")]
+            on_collide: None,
+            pre_solve: None,
+            one_way: None,
+            drop_through: false,
             index: None,
         }
     }
 }
 
 impl specs::Component for Collider {
-    type Storage = specs::storage::BTreeStorage<Self>;
+    type Storage = specs::FlaggedStorage<Self, specs::storage::BTreeStorage<Self>>;
 }
 
+#[cfg(feature = "lua")]
 impl ComponentParser for Collider { 
     fn parse(v: LuaValue, lua: &Lua) -> ScriptResult<Self> {
         match v {
             LuaValue::Table(t) => {
                 let shape_type: String = t.get("shape_type")?;
 
-                let shape = match shape_type.as_ref() {
-                    "aabb" => {
-                        let t: Table = t.get("shape")?;
-
-                        Shape::AABB(
-                            Rect3::new(
-                                Vector3::new(
-                                    t.get("min_x")?, 
-                                    t.get("min_y")?, 
-                                    t.get("min_z")?, 
-                                ),
-                                Vector3::new(
-                                    t.get("max_x")?, 
-                                    t.get("max_y")?, 
-                                    t.get("max_z")?, 
-                                )
-                            )
-                        )
-                    },
-                    "circle" => {
-                        let t: Table = t.get("shape")?;
-
-                        Shape::Circle {
-                            offset: {
-                                let t: Table = t.get("offset")?;
-                                Vector2::new(
-                                    t.get("x")?,
-                                    t.get("y")?,
-                                )
-                            },
-                            radius: t.get("radius")?,
-                            depth:
-                                t.get("min_z")?
-                                .. t.get("max_z")?
-                        }
-                    },
-                    _ => panic!("Type is not a valid shape")
-                };
+                let shape = shape_from_lua(&shape_type, t.get("shape")?)?;
 
 
                 let key = {
@@ -131,11 +284,29 @@ impl ComponentParser for Collider {
                     func.map(|x| lua.create_registry_value(x).unwrap())
                 };
 
-                Ok(Collider::new(
+                let pre_solve = {
+                    let func: Option<LuaFunction> = t.get("pre_solve").ok();
+                    func.map(|x| PreSolveHook::Script(lua.create_registry_value(x).unwrap()))
+                };
+
+                let one_way = match t.get::<_, Option<Table>>("one_way")? {
+                    Some(t) => Some(Vector3::new(
+                        t.get("x")?, 
+                        t.get("y")?, 
+                        t.get("z")?, 
+                    )),
+                    None => None,
+                };
+
+                let mut coll = Collider::new(
                     shape,
-                    t.get("sweep")?,
-                    key
-                ))
+                    t.get("sweep")?
+                );
+                coll.on_collide = key;
+                coll.pre_solve = pre_solve;
+                coll.one_way = one_way;
+
+                Ok(coll)
             },
             LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
             _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
@@ -147,6 +318,180 @@ impl ComponentParser for Collider {
     }
 }
 
+// The shape's type and table, in the same format they're parsed from.
+#[cfg(feature = "lua")]
+fn shape_to_lua<'lua>(shape: &Shape, lua: &'lua Lua) -> LuaResult<(&'static str, Table<'lua>)> {
+    let t = lua.create_table()?;
+
+    let shape_type = match *shape {
+        Shape::AABB(r) => {
+            set_rect3(&t, r)?;
+            "aabb"
+        },
+        Shape::Circle { offset, radius, ref depth } => {
+            let offset_t = lua.create_table()?;
+            offset_t.set("x", offset.x)?;
+            offset_t.set("y", offset.y)?;
+
+            t.set("offset", offset_t)?;
+            t.set("radius", radius)?;
+            t.set("min_z", depth.start)?;
+            t.set("max_z", depth.end)?;
+            "circle"
+        },
+        Shape::Slope { rect, left, right } => {
+            set_rect3(&t, rect)?;
+            t.set("left", left)?;
+            t.set("right", right)?;
+            "slope"
+        },
+        Shape::Polygon { ref points, ref depth } => {
+            let points_t = lua.create_table()?;
+            for (i, p) in points.iter().enumerate() {
+                let point_t = lua.create_table()?;
+                point_t.set("x", p.x)?;
+                point_t.set("y", p.y)?;
+                points_t.set(i + 1, point_t)?;
+            }
+
+            t.set("points", points_t)?;
+            t.set("min_z", depth.start)?;
+            t.set("max_z", depth.end)?;
+            "polygon"
+        },
+    };
+
+    Ok((shape_type, t))
+}
+
+#[cfg(feature = "lua")]
+fn shape_from_lua(shape_type: &str, t: Table) -> LuaResult<Shape> {
+    match shape_type {
+        "aabb" => Ok(Shape::AABB(rect3_from_lua(&t)?)),
+        "circle" => Ok(Shape::Circle {
+            offset: {
+                let t: Table = t.get("offset")?;
+                Vector2::new(
+                    t.get("x")?,
+                    t.get("y")?,
+                )
+            },
+            radius: t.get("radius")?,
+            depth:
+                t.get("min_z")?
+                .. t.get("max_z")?
+        }),
+        "slope" => Ok(Shape::Slope {
+            rect: rect3_from_lua(&t)?,
+            left: t.get("left")?,
+            right: t.get("right")?,
+        }),
+        "polygon" => {
+            let mut points = Vec::new();
+            for point in t.get::<_, Table>("points")?.sequence_values::<Table>() {
+                let point = point?;
+                points.push(Vector2::new(point.get("x")?, point.get("y")?));
+            }
+
+            if points.len() < 3 {
+                return Err(LuaError::RuntimeError("A polygon needs at least 3 points".into()));
+            }
+
+            Ok(Shape::Polygon {
+                points,
+                depth:
+                    t.get("min_z")?
+                    .. t.get("max_z")?
+            })
+        },
+        _ => Err(LuaError::RuntimeError(format!("'{}' is not a valid shape type", shape_type))),
+    }
+}
+
+#[cfg(feature = "lua")]
+fn rect3_from_lua(t: &Table) -> LuaResult<Rect3<f32>> {
+    Ok(Rect3::new(
+        Vector3::new(
+            t.get("min_x")?, 
+            t.get("min_y")?, 
+            t.get("min_z")?, 
+        ),
+        Vector3::new(
+            t.get("max_x")?, 
+            t.get("max_y")?, 
+            t.get("max_z")?, 
+        )
+    ))
+}
+
+#[cfg(feature = "lua")]
+fn set_rect3(t: &Table, r: Rect3<f32>) -> LuaResult<()> {
+    t.set("min_x", r.min.x)?;
+    t.set("min_y", r.min.y)?;
+    t.set("min_z", r.min.z)?;
+    t.set("max_x", r.max.x)?;
+    t.set("max_y", r.max.y)?;
+    t.set("max_z", r.max.z)?;
+
+    Ok(())
+}
+
+/* NOTE:
+    The shape is written as a table like the one it's parsed from, which may name a new 'shape_type' (otherwise the 
+    shape keeps its type). Native pre-solve hooks read as nil, and can only be replaced by a Lua function.
+*/
+#[cfg(feature = "lua")]
+impl ComponentFields for Collider {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "shape_type" => lua.pack(shape_to_lua(&self.shape, lua)?.0),
+            "shape" => lua.pack(shape_to_lua(&self.shape, lua)?.1),
+            "sweep" => lua.pack(self.sweep),
+            "one_way" => lua.pack(self.one_way.map(Vector3f)),
+            "drop_through" => lua.pack(self.drop_through),
+            "on_collide" => callback_to_lua(&self.on_collide, lua),
+            "pre_solve" => match self.pre_solve {
+                Some(PreSolveHook::Script(ref key)) => Ok(LuaValue::Function(lua.registry_value(key)?)),
+                _ => Ok(LuaValue::Nil),
+            },
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "shape_type" => return Err(read_only(field)),
+            "shape" => {
+                let t: Table = lua.unpack(value)?;
+                let shape_type = match t.get::<_, Option<String>>("shape_type")? {
+                    Some(shape_type) => shape_type,
+                    None => shape_to_lua(&self.shape, lua)?.0.into(),
+                };
+
+                self.shape = shape_from_lua(&shape_type, t)?;
+            },
+            "sweep" => self.sweep = lua.unpack(value)?,
+            "one_way" => self.one_way = match value {
+                LuaValue::Nil => None,
+                value => Some(vector3_from_lua(value)?),
+            },
+            "drop_through" => self.drop_through = lua.unpack(value)?,
+            "on_collide" => set_callback(&mut self.on_collide, value, lua)?,
+            "pre_solve" => {
+                let mut key = None;
+                set_callback(&mut key, value, lua)?;
+
+                if let Some(PreSolveHook::Script(old_key)) = ::std::mem::replace(&mut self.pre_solve, key.map(PreSolveHook::Script)) {
+                    lua.remove_registry_value(old_key)?;
+                }
+            },
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
+
 impl fmt::Debug for Collider {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result { 
         write!(f, "Collider: shape: {:?}, sweep: {}, index: {:?}", self.shape, self.sweep, self.index) 
@@ -183,4 +528,59 @@ fn get_bound() {
             Vector3::new(6.0, 6.0, 1.0),
         ),
     });
-}
\ No newline at end of file
+}
+
+#[test]
+fn transformed_bound() {
+    let aabb = Shape::AABB(
+        Rect3::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(2.0, 1.0, 1.0)
+        )
+    );
+
+    let mut tran = Transform::new(Vector3::new(4.0, 4.0, 0.0));
+    tran.scale = Vector2::new(-1.0, 2.0);
+
+    assert_eq!(aabb.transformed(&tran).bound(tran.pos), Bound {
+        rect: Rect3::new(
+            Vector3::new(2.0, 4.0, 0.0),
+            Vector3::new(4.0, 6.0, 1.0),
+        ),
+    });
+
+    // A quarter turn makes the AABB a polygon, but its bound is still a rect.
+    tran.scale = Vector2::new(1.0, 1.0);
+    tran.rotation = ::std::f32::consts::PI / 2.0;
+    let rotated = aabb.transformed(&tran);
+    let bound = rotated.bound(tran.pos);
+
+    assert!(rotated.polygon().is_some());
+    assert!(relative_eq!(bound.rect.min, Vector3::new(3.0, 4.0, 0.0), epsilon = 0.0001));
+    assert!(relative_eq!(bound.rect.max, Vector3::new(4.0, 6.0, 1.0), epsilon = 0.0001));
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn pre_solve_from_lua() {
+    use ::script::types::LuaCtor;
+
+    let lua = Lua::new();
+    Vector3f::add_ctors(&lua);
+
+    assert_eq!(PreSolve::from_lua(lua.eval("false", None).unwrap()).unwrap(), PreSolve::Ignore);
+    assert_eq!(PreSolve::from_lua(lua.eval("nil", None).unwrap()).unwrap(), PreSolve::Resolve);
+    assert_eq!(PreSolve::from_lua(lua.eval("true", None).unwrap()).unwrap(), PreSolve::Resolve);
+    assert_eq!(
+        PreSolve::from_lua(lua.eval("vec3f(0.0, 1.0, 0.0)", None).unwrap()).unwrap(), 
+        PreSolve::Normal(Vector3::new(0.0, 1.0, 0.0))
+    );
+
+    // Zero normals can't be normalized.
+    assert!(PreSolve::from_lua(lua.eval("vec3f(0.0, 0.0, 0.0)", None).unwrap()).is_err());
+    assert!(PreSolve::from_lua(lua.eval("vec3f(1e-20, 0.0, 0.0)", None).unwrap()).is_err());
+
+    let disp = Vector3::new(0.5, 0.0, 0.0);
+    assert_eq!(PreSolve::Normal(Vector3::new(0.0, 0.0, 0.0)).displacement(disp), disp);
+    assert_eq!(PreSolve::Normal(Vector3::new(0.0, 2.0, 0.0)).displacement(disp), Vector3::new(0.0, 0.5, 0.0));
+}
@@ -1,5 +1,5 @@
 pub mod transform;
-pub use self::transform::Transform;
+pub use self::transform::{Transform, LocalTransform, Parent};
 
 pub mod physics;
 pub use self::physics::{Velocity};
@@ -13,5 +13,10 @@ pub use self::tilemap::{TileMap, RenderStrip, CollisionStrip};
 pub mod collider;
 pub use self::collider::{Collider};
 
+pub mod prefab;
+pub use self::prefab::{Prefab};
+
+#[cfg(feature = "lua")]
 pub mod script;
-pub use self::script::{ScriptBehavior};
\ No newline at end of file
+#[cfg(feature = "lua")]
+pub use self::script::{ScriptBehavior, Wait, Timers};
\ No newline at end of file
@@ -1,5 +1,9 @@
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+#[cfg(feature = "lua")]
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields};
+#[cfg(feature = "lua")]
+use ::script::parse::no_field;
 
+#[cfg(feature = "lua")]
 use rlua::{Value as LuaValue, Result as LuaResult, Error as LuaError, UserData, UserDataMethods, Lua};
 use cgmath::Vector3;
 use specs;
@@ -13,6 +17,7 @@ impl specs::Component for Velocity {
     type Storage = specs::VecStorage<Self>;
 }
 
+#[cfg(feature = "lua")]
 impl ComponentParser for Velocity { 
     fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
         match v {
@@ -32,4 +37,27 @@ impl ComponentParser for Velocity {
             })),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "lua")]
+impl ComponentFields for Velocity {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "x" => lua.pack(self.pos.x),
+            "y" => lua.pack(self.pos.y),
+            "z" => lua.pack(self.pos.z),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "x" => self.pos.x = lua.unpack(value)?,
+            "y" => self.pos.y = lua.unpack(value)?,
+            "z" => self.pos.z = lua.unpack(value)?,
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,20 @@
+use specs;
+
+// The name of the Lua table an entity was spawned from, so its Lua callbacks can be bound again (e.g. after loading 
+// a snapshot).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub name: String,
+}
+
+impl Prefab {
+    pub fn new(name: &str) -> Self {
+        Prefab {
+            name: name.into(),
+        }
+    }
+}
+
+impl specs::Component for Prefab {
+    type Storage = specs::VecStorage<Self>;
+}
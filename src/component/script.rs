@@ -1,19 +1,68 @@
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields};
+use ::script::parse::{no_field, callback_to_lua, set_callback};
+use ::script::sandbox;
+use ::script::state::new_state;
 
 use rlua::{Table, Value as LuaValue, Result as LuaResult, Error as LuaError, Function as LuaFunction, UserData, UserDataMethods, Lua, RegistryKey};
 use cgmath::{Vector3};
 use specs;
 
+// What a behavior's coroutine is waiting for before it's resumed.
+pub enum Wait {
+    // Resumed on the next tick.
+    Tick,
+    Seconds(f32),
+    Ticks(u32),
+    // Resumed once the function returns true (checked every tick).
+    Until(RegistryKey),
+}
+
+// The callback's registry value is freed once the component is dropped (see 'Game::expire_script_values').
 pub struct ScriptBehavior {
     pub on_tick: Option<RegistryKey>,
+    // Called every update, even while the game is paused (see 'system::script::run_on_updates').
+    pub on_update: Option<RegistryKey>,
+    // A coroutine resumed every tick (once it's done waiting), until it returns.
+    pub run: Option<RegistryKey>,
+    pub wait: Wait,
+    // How many coroutines have been started (so a coroutine replaced while it runs isn't mistaken for the new one).
+    pub runs: u32,
+    // The entity's own table, given to its callbacks (see 'script::state').
+    pub state: Option<RegistryKey>,
 }
 
 impl ScriptBehavior {
     pub fn new(on_tick: Option<RegistryKey>) -> Self {
         ScriptBehavior {
             on_tick,
+            on_update: None,
+            run: None,
+            wait: Wait::Tick,
+            runs: 0,
+            state: None,
         }
     }
+
+    // Starts a coroutine running the function (or stops the current one, given nil).
+    pub fn set_run<'lua>(&mut self, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        let func: Option<LuaFunction> = lua.unpack(value)?;
+        let new_key = match func {
+            Some(func) => {
+                let thread = lua.create_thread(func.clone())?;
+                sandbox::hook_thread(lua, &thread, &func, false)?;
+                Some(lua.create_registry_value(thread)?)
+            },
+            None => None,
+        };
+
+        if let Some(old_key) = ::std::mem::replace(&mut self.run, new_key) {
+            lua.remove_registry_value(old_key)?;
+        }
+        self.wait = Wait::Tick;
+        self.runs = self.runs.wrapping_add(1);
+
+        Ok(())
+    }
 }
 
 impl specs::Component for ScriptBehavior {
@@ -29,7 +78,15 @@ impl ComponentParser for ScriptBehavior {
                     func.map(|x| lua.create_registry_value(x).unwrap())
                 };
 
-                Ok(ScriptBehavior::new(key))
+                let mut behav = ScriptBehavior::new(key);
+                behav.on_update = {
+                    let func: Option<LuaFunction> = t.get("on_update").ok();
+                    func.map(|x| lua.create_registry_value(x).unwrap())
+                };
+                behav.state = Some(lua.create_registry_value(new_state(&t, lua)?)?);
+                behav.set_run(t.get("run")?, lua)?;
+
+                Ok(behav)
             },
             LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
             _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
@@ -39,4 +96,93 @@ impl ComponentParser for ScriptBehavior {
             })),
         }
     }
-}
\ No newline at end of file
+}
+
+impl ComponentFields for ScriptBehavior {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "on_tick" => callback_to_lua(&self.on_tick, lua),
+            "on_update" => callback_to_lua(&self.on_update, lua),
+            // The coroutine itself, not the function it was started with.
+            "run" => match self.run {
+                Some(ref key) => lua.registry_value(key),
+                None => Ok(LuaValue::Nil),
+            },
+            "state" => match self.state {
+                Some(ref key) => lua.registry_value(key),
+                None => Ok(LuaValue::Nil),
+            },
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "on_tick" => set_callback(&mut self.on_tick, value, lua),
+            "on_update" => set_callback(&mut self.on_update, value, lua),
+            "run" => self.set_run(value, lua),
+            "state" => {
+                let state: Table = lua.unpack(value)?;
+                let key = lua.create_registry_value(state)?;
+
+                if let Some(old_key) = ::std::mem::replace(&mut self.state, Some(key)) {
+                    lua.remove_registry_value(old_key)?;
+                }
+                Ok(())
+            },
+            _ => Err(no_field(field)),
+        }
+    }
+}
+
+pub struct Timer {
+    pub id: u32,
+    // Seconds until the function is called.
+    pub remaining: f32,
+    // How often the function is called after the first time (if it repeats).
+    pub interval: Option<f32>,
+    pub func: RegistryKey,
+}
+
+// Lua functions called after some time (see 'world:after' and 'world:every'), cancelled with their entity.
+pub struct Timers {
+    pub timers: Vec<Timer>,
+    next_id: u32,
+}
+
+impl Timers {
+    pub fn new() -> Self {
+        Timers {
+            timers: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn add(&mut self, seconds: f32, interval: Option<f32>, func: RegistryKey) -> u32 {
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.timers.push(Timer {
+            id,
+            remaining: seconds,
+            interval,
+            func,
+        });
+
+        id
+    }
+
+    pub fn remove(&mut self, id: u32) {
+        self.timers.retain(|x| x.id != id);
+    }
+}
+
+impl Default for Timers {
+    fn default() -> Self {
+        Timers::new()
+    }
+}
+
+impl specs::Component for Timers {
+    type Storage = specs::DenseVecStorage<Self>;
+}
@@ -1,23 +1,37 @@
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+#[cfg(feature = "lua")]
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields};
+#[cfg(feature = "lua")]
+use ::script::parse::{no_field, rect2_from_lua, rect2_to_lua};
 use ::utility::Rect2;
+#[cfg(feature = "render")]
 use ::Vertex;
 
+#[cfg(feature = "render")]
 use std::sync::Arc;
 
+#[cfg(feature = "lua")]
 use rlua::{Table, Value as LuaValue, Result as LuaResult, Error as LuaError, UserData, UserDataMethods, Lua};
+use cgmath::{One, Matrix4};
+#[cfg(feature = "lua")]
 use cgmath::Vector2;
+#[cfg(feature = "render")]
 use vulkano as vk;
 use specs;
 
 // Holds vulkano back-end rendering data
 pub struct Sprite {
     // TODO: Can't this just be a Box<>
+    #[cfg(feature = "render")]
     pub instance_set: Option<Arc<vk::descriptor::DescriptorSet + Send + Sync>>,
+    // The (interpolated) instance transform last written by the sprite system.
+    pub transform: Matrix4<f32>,
 
     pub bounds: Rect2<f32>,
     pub uv: Rect2<f32>,
 
+    #[cfg(feature = "render")]
     pub vertex_buf: Option<Arc<vk::buffer::ImmutableBuffer<[Vertex]>>>,
+    #[cfg(feature = "render")]
     pub index_buf: Option<Arc<vk::buffer::ImmutableBuffer<[u32]>>>,
 
     pub image_index: u32,
@@ -26,10 +40,14 @@ pub struct Sprite {
 impl Sprite {
     pub fn new(bounds: Rect2<f32>, uv: Rect2<f32>, image_index: u32) -> Sprite {
         Sprite {
+            #[cfg(feature = "render")]
             instance_set: None,
+            transform: Matrix4::one(),
             bounds,
             uv,
+            #[cfg(feature = "render")]
             vertex_buf: None,
+            #[cfg(feature = "render")]
             index_buf: None,
             image_index,
         }
@@ -40,6 +58,7 @@ impl specs::Component for Sprite {
     type Storage = specs::FlaggedStorage<Self, specs::VecStorage<Self>>;
 }
 
+#[cfg(feature = "lua")]
 impl ComponentParser for Sprite { 
     fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
         match v {
@@ -86,4 +105,28 @@ impl ComponentParser for Sprite {
             })),
         }
     }
-}
\ No newline at end of file
+}
+
+// Changing any of the fields has the sprite system rebuild the sprite's buffers.
+#[cfg(feature = "lua")]
+impl ComponentFields for Sprite {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "bounds" => rect2_to_lua(self.bounds, lua),
+            "uv" => rect2_to_lua(self.uv, lua),
+            "image_index" => lua.pack(self.image_index),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "bounds" => self.bounds = rect2_from_lua(lua.unpack(value)?)?,
+            "uv" => self.uv = rect2_from_lua(lua.unpack(value)?)?,
+            "image_index" => self.image_index = lua.unpack(value)?,
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,27 @@
+#[cfg(feature = "render")]
 use ::Vertex;
 use ::utility::{Rect2, Rect3};
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+#[cfg(feature = "lua")]
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields};
+#[cfg(feature = "lua")]
+use ::script::parse::{no_field, read_only};
+#[cfg(feature = "lua")]
+use ::script::types::{Vector2f, Vector3f};
 use ::parse;
+use ::component::collider;
 
 use std::collections::HashMap;
+#[cfg(feature = "lua")]
+use std::io::prelude::*;
+#[cfg(feature = "lua")]
+use std::fs::File;
+#[cfg(feature = "render")]
 use std::sync::Arc;
 
+#[cfg(feature = "lua")]
 use rlua::{Table, Value as LuaValue, Result as LuaResult, Error as LuaError, UserData, UserDataMethods, Lua};
 use cgmath::{Vector2, Vector3};
+#[cfg(feature = "render")]
 use vulkano as vk;
 use specs;
 
@@ -20,6 +34,11 @@ pub struct TileMap {
     tex_dims: Vector2<u32>,
     image_index: u32,
 
+    // Collide with the tile grid directly, instead of spawning a collider entity for each blocking tile.
+    pub grid_collision: bool,
+    // The collision strips' entities, by their strip position.
+    pub collision_strips: HashMap<Vector3<u32>, specs::Entity>,
+
     pub load: Option<parse::TileMap>
 }
 
@@ -29,6 +48,8 @@ impl TileMap {
             tile_dims,
             tex_dims,
             image_index,
+            grid_collision: false,
+            collision_strips: HashMap::new(),
             load,
         }
     }
@@ -44,6 +65,10 @@ impl TileMap {
     pub fn image_index(&self) -> u32 {
         self.image_index
     }
+
+    pub fn set_image_index(&mut self, image_index: u32) {
+        self.image_index = image_index;
+    }
 }
 
 impl specs::Component for TileMap {
@@ -57,7 +82,9 @@ pub struct RenderStrip {
     uvs: [Option<Rect2<f32>>; STRIP_LENGTH],
     
     // Vertex positions are relative to the tile map's origin (not moved by the tile map's instance set).
+    #[cfg(feature = "render")]
     pub vertex_buf: Option<Arc<vk::buffer::ImmutableBuffer<[Vertex]>>>,
+    #[cfg(feature = "render")]
     pub index_buf: Option<Arc<vk::buffer::ImmutableBuffer<[u32]>>>,
 }
 
@@ -71,7 +98,9 @@ impl RenderStrip {
             tile_map,
             pos,
             uvs,
+            #[cfg(feature = "render")]
             vertex_buf: None,
+            #[cfg(feature = "render")]
             index_buf: None,
         }
     }
@@ -90,14 +119,21 @@ impl RenderStrip {
 
     pub fn set_uvs(&mut self, uvs: [Option<Rect2<f32>>; STRIP_LENGTH]) {
         self.uvs = uvs;
-        self.vertex_buf = None;
-        self.index_buf = None;
+        self.clear_buffers();
     }
 
     pub fn set_uv(&mut self, pos: usize, uv: Rect2<f32>) {
         self.uvs[pos] = Some(uv);
-        self.vertex_buf = None;
-        self.index_buf = None;
+        self.clear_buffers();
+    }
+
+    // The buffers get recreated by the render system once they're cleared.
+    fn clear_buffers(&mut self) {
+        #[cfg(feature = "render")]
+        {
+            self.vertex_buf = None;
+            self.index_buf = None;
+        }
     }
 }
 
@@ -105,11 +141,74 @@ impl specs::Component for RenderStrip {
     type Storage = specs::FlaggedStorage<Self, specs::storage::BTreeStorage<Self>>;
 }
 
+// The collision shape of a single tile.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TileShape {
+    Full,
+    // The bottom half of the tile.
+    Half,
+    // A slope going from the left height to the right height (as fractions of the tile's height).
+    Slope(f32, f32),
+}
+
+impl TileShape {
+    /* NOTE:
+        Tile shape indices (as stored in a tile map's shape layer):
+            0: None, 1: Full, 2: Half,
+            3: 45° rising right, 4: 45° rising left,
+            5: 22.5° rising right (lower half), 6: 22.5° rising right (upper half),
+            7: 22.5° rising left (upper half), 8: 22.5° rising left (lower half)
+    */
+    pub fn from_index(idx: u16) -> Option<TileShape> {
+        match idx {
+            1 => Some(TileShape::Full),
+            2 => Some(TileShape::Half),
+            3 => Some(TileShape::Slope(0.0, 1.0)),
+            4 => Some(TileShape::Slope(1.0, 0.0)),
+            5 => Some(TileShape::Slope(0.0, 0.5)),
+            6 => Some(TileShape::Slope(0.5, 1.0)),
+            7 => Some(TileShape::Slope(1.0, 0.5)),
+            8 => Some(TileShape::Slope(0.5, 0.0)),
+            _ => None,
+        }
+    }
+
+    // The collider shape for a tile of the given dimensions.
+    pub fn collider_shape(&self, tile_dims: Vector3<f32>) -> collider::Shape {
+        match *self {
+            TileShape::Full => collider::Shape::AABB(
+                Rect3::new(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    tile_dims,
+                )
+            ),
+            TileShape::Half => collider::Shape::AABB(
+                Rect3::new(
+                    Vector3::new(0.0, tile_dims.y / 2.0, 0.0),
+                    tile_dims,
+                )
+            ),
+            TileShape::Slope(left, right) => collider::Shape::Slope {
+                rect: Rect3::new(
+                    Vector3::new(0.0, 0.0, 0.0),
+                    tile_dims,
+                ),
+                left,
+                right,
+            },
+        }
+    }
+}
+
 pub struct CollisionStrip {
     tile_map: specs::Entity,
     pos: Vector3<u32>,
 
     pub blocking: [bool; STRIP_LENGTH],
+    // The direction each tile can be passed through, if it's one-way.
+    pub one_way: [Option<Vector3<f32>>; STRIP_LENGTH],
+    // The shape of each tile, if it isn't just a full (blocking) tile.
+    pub shapes: [Option<TileShape>; STRIP_LENGTH],
 
     pub colliders: Vec<specs::Entity>,
 }
@@ -124,6 +223,8 @@ impl CollisionStrip {
             tile_map,
             pos,
             blocking,
+            one_way: [None; STRIP_LENGTH],
+            shapes: [None; STRIP_LENGTH],
             colliders: Vec::new(),
         }
     }
@@ -135,18 +236,41 @@ impl CollisionStrip {
     pub fn pos(&self) -> Vector3<u32> {
         self.pos
     }
+
+    // The collision shape of a tile in this strip, if the tile collides at all.
+    pub fn tile_shape(&self, idx: usize) -> Option<TileShape> {
+        if self.shapes[idx].is_some() {
+            self.shapes[idx]
+        } else if self.blocking[idx] || self.one_way[idx].is_some() {
+            Some(TileShape::Full)
+        } else {
+            None
+        }
+    }
 }
 
 impl specs::Component for CollisionStrip {
     type Storage = specs::FlaggedStorage<Self, specs::storage::BTreeStorage<Self>>;
 }
 
+#[cfg(feature = "lua")]
 impl ComponentParser for TileMap { 
     fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
         match v {
             LuaValue::Table(t) => {
-                // TODO: Load parse::TileMap from this, then call the component's load function w/ it.
+                // An empty path leaves the tile map to be loaded later.
                 let path: String = t.get("path")?;
+                let load = if path.is_empty() {
+                    None
+                } else {
+                    let mut bytes = Vec::new();
+                    File::open(&path)?.read_to_end(&mut bytes)?;
+
+                    match parse::tile_map(&bytes) {
+                        Ok((_, map)) => Some(map),
+                        Err(_) => return Err(ScriptError::InvalidComponent(format!("Couldn't parse tile map '{}'", path))),
+                    }
+                };
 
                 let tile_dims = {
                     let t: Table = t.get("tile_dimensions")?;
@@ -165,12 +289,15 @@ impl ComponentParser for TileMap {
                     )
                 };
 
-                Ok(TileMap::new(
+                let mut map = TileMap::new(
                     tile_dims,
                     tex_dims,
                     t.get("image_index")?,
-                    None
-                ))
+                    load
+                );
+                map.grid_collision = t.get::<_, Option<bool>>("grid_collision")?.unwrap_or(false);
+
+                Ok(map)
             },
             LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
             _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
@@ -180,4 +307,29 @@ impl ComponentParser for TileMap {
             })),
         }
     }
-}
\ No newline at end of file
+}
+
+// The tile map's dimensions and collision mode are used to build its strips, so they can't be changed afterwards.
+#[cfg(feature = "lua")]
+impl ComponentFields for TileMap {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "tile_dimensions" => lua.pack(Vector3f(self.tile_dims)),
+            "texture_dimensions" => lua.pack(Vector2f(self.tex_dims.cast().unwrap())),
+            "image_index" => lua.pack(self.image_index),
+            "grid_collision" => lua.pack(self.grid_collision),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "tile_dimensions" | "texture_dimensions" | "grid_collision" => Err(read_only(field)),
+            "image_index" => {
+                self.set_image_index(lua.unpack(value)?);
+                Ok(())
+            },
+            _ => Err(no_field(field)),
+        }
+    }
+}
@@ -1,13 +1,23 @@
-use ::script::{ScriptResult, ScriptError, ComponentParser};
+#[cfg(feature = "lua")]
+use ::script::{ScriptResult, ScriptError, ComponentParser, ComponentFields, LuaEntity};
+#[cfg(feature = "lua")]
+use ::script::parse::{no_field, vector2_from_lua, vector3_from_lua};
+#[cfg(feature = "lua")]
+use ::script::types::{Vector2f, Vector3f};
 
+#[cfg(feature = "lua")]
 use rlua::{Table, Value as LuaValue, Result as LuaResult, Error as LuaError, UserData, UserDataMethods, Lua};
-use cgmath::{Vector3};
+use cgmath::{Rad, Matrix2, Matrix4, Vector2, Vector3};
 use specs;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Transform {
     pub last_pos: Vector3<f32>,
     pub pos: Vector3<f32>,
+    // Rotation about the Z axis (in radians), around the entity's position.
+    pub rotation: f32,
+    // A negative scale flips the entity along that axis.
+    pub scale: Vector2<f32>,
 }
 
 impl Transform {
@@ -15,14 +25,49 @@ impl Transform {
         Transform {
             pos,
             last_pos: pos,
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
         }
     }
+
+    // Whether the transform only translates (so shapes can be used as they are).
+    pub fn is_translation(&self) -> bool {
+        self.rotation == 0.0 && self.scale == Vector2::new(1.0, 1.0)
+    }
+
+    // Scales, then rotates a point relative to the entity's position.
+    pub fn rotate_scale(&self, v: Vector2<f32>) -> Vector2<f32> {
+        Matrix2::from_angle(Rad(self.rotation)) * Vector2::new(v.x * self.scale.x, v.y * self.scale.y)
+    }
+
+    // Undoes 'rotate_scale' (leaving axes scaled to nothing at zero).
+    pub fn unrotate_scale(&self, v: Vector2<f32>) -> Vector2<f32> {
+        let v = Matrix2::from_angle(Rad(-self.rotation)) * v;
+        Vector2::new(div_or_zero(v.x, self.scale.x), div_or_zero(v.y, self.scale.y))
+    }
+
+    // The position between the last tick's and this tick's (alpha of 0.0 being the last tick's).
+    pub fn lerp_pos(&self, alpha: f32) -> Vector3<f32> {
+        self.last_pos + (self.pos - self.last_pos) * alpha
+    }
+
+    // The transform as a matrix, interpolated between the last tick and this one.
+    pub fn matrix(&self, alpha: f32) -> Matrix4<f32> {
+        Matrix4::from_translation(self.lerp_pos(alpha)) * 
+            Matrix4::from_angle_z(Rad(self.rotation)) * 
+            Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, 1.0)
+    }
+}
+
+fn div_or_zero(a: f32, b: f32) -> f32 {
+    if b == 0.0 { 0.0 } else { a / b }
 }
 
 impl specs::Component for Transform {
     type Storage = specs::FlaggedStorage<Self, specs::VecStorage<Self>>;
 }
 
+#[cfg(feature = "lua")]
 impl ComponentParser for Transform { 
     fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
         match v {
@@ -36,7 +81,17 @@ impl ComponentParser for Transform {
                     )
                 };
 
-                Ok(Transform::new(pos))
+                let mut tran = Transform::new(pos);
+
+                if let Some(rotation) = t.get::<_, Option<f32>>("rotation")? {
+                    tran.rotation = rotation;
+                }
+
+                if let Some(t) = t.get::<_, Option<Table>>("scale")? {
+                    tran.scale = Vector2::new(t.get("x")?, t.get("y")?);
+                }
+
+                Ok(tran)
             },
             LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
             _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
@@ -46,4 +101,197 @@ impl ComponentParser for Transform {
             })),
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "lua")]
+impl ComponentFields for Transform {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "position" => lua.pack(Vector3f(self.pos)),
+            "rotation" => lua.pack(self.rotation),
+            "scale" => lua.pack(Vector2f(self.scale)),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "position" => self.pos = vector3_from_lua(value)?,
+            "rotation" => self.rotation = lua.unpack(value)?,
+            "scale" => self.scale = vector2_from_lua(value)?,
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
+
+// Transform relative to the entity's parent (the entity's transform is then kept in sync with it).
+#[derive(Debug)]
+pub struct LocalTransform {
+    // In the parent's space, so it's rotated and scaled along with the parent.
+    pub pos: Vector3<f32>,
+    pub rotation: f32,
+    pub scale: Vector2<f32>,
+    // Where the hierarchy last put the entity (its position, rotation and scale). If anything has moved it since (its 
+    // velocity, a collision, etc.), its local transform is moved along with it.
+    pub synced: Option<(Vector3<f32>, f32, Vector2<f32>)>,
+}
+
+impl LocalTransform {
+    pub fn new(pos: Vector3<f32>) -> Self {
+        LocalTransform {
+            pos,
+            rotation: 0.0,
+            scale: Vector2::new(1.0, 1.0),
+            synced: None,
+        }
+    }
+
+    // The local transform that keeps an entity where it currently is, relative to the parent.
+    pub fn relative_to(tran: &Transform, parent: &Transform) -> Self {
+        let offset = tran.pos - parent.pos;
+
+        LocalTransform {
+            pos: parent.unrotate_scale(offset.truncate()).extend(offset.z),
+            rotation: tran.rotation - parent.rotation,
+            scale: Vector2::new(div_or_zero(tran.scale.x, parent.scale.x), div_or_zero(tran.scale.y, parent.scale.y)),
+            synced: None,
+        }
+    }
+
+    // The entity's position, rotation and scale in the world, given its parent's transform.
+    pub fn apply(&self, parent: &Transform) -> (Vector3<f32>, f32, Vector2<f32>) {
+        (
+            parent.pos + parent.rotate_scale(self.pos.truncate()).extend(self.pos.z),
+            parent.rotation + self.rotation,
+            Vector2::new(parent.scale.x * self.scale.x, parent.scale.y * self.scale.y),
+        )
+    }
+}
+
+impl specs::Component for LocalTransform {
+    type Storage = specs::VecStorage<Self>;
+}
+
+#[cfg(feature = "lua")]
+impl ComponentParser for LocalTransform { 
+    fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
+        match v {
+            LuaValue::Table(t) => {
+                let pos = {
+                    let t: Table = t.get("position")?;
+                    Vector3::new(
+                        t.get("x")?, 
+                        t.get("y")?, 
+                        t.get("z")?
+                    )
+                };
+
+                let mut local = LocalTransform::new(pos);
+
+                if let Some(rotation) = t.get::<_, Option<f32>>("rotation")? {
+                    local.rotation = rotation;
+                }
+
+                if let Some(t) = t.get::<_, Option<Table>>("scale")? {
+                    local.scale = Vector2::new(t.get("x")?, t.get("y")?);
+                }
+
+                Ok(local)
+            },
+            LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
+            _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
+                from: "_",
+                to: "table",
+                message: None, 
+            })),
+        }
+    }
+}
+
+#[cfg(feature = "lua")]
+impl ComponentFields for LocalTransform {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "position" => lua.pack(Vector3f(self.pos)),
+            "rotation" => lua.pack(self.rotation),
+            "scale" => lua.pack(Vector2f(self.scale)),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "position" => self.pos = vector3_from_lua(value)?,
+            "rotation" => self.rotation = lua.unpack(value)?,
+            "scale" => self.scale = vector2_from_lua(value)?,
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct Parent {
+    pub entity: specs::Entity,
+    // Delete this entity along with its parent (otherwise it's left where it is, without a parent).
+    pub cascade: bool,
+}
+
+impl Parent {
+    pub fn new(entity: specs::Entity, cascade: bool) -> Self {
+        Parent {
+            entity,
+            cascade,
+        }
+    }
+}
+
+impl specs::Component for Parent {
+    type Storage = specs::storage::BTreeStorage<Self>;
+}
+
+#[cfg(feature = "lua")]
+impl ComponentParser for Parent { 
+    fn parse(v: LuaValue, _: &Lua) -> ScriptResult<Self> {
+        match v {
+            LuaValue::Table(t) => {
+                let entity: LuaEntity = t.get("entity")?;
+
+                Ok(Parent::new(
+                    entity.0, 
+                    t.get::<_, Option<bool>>("cascade")?.unwrap_or(false)
+                ))
+            },
+            LuaValue::Error(err) => Err(ScriptError::LuaError(err)),
+            _ => Err(ScriptError::LuaError(LuaError::FromLuaConversionError {
+                from: "_",
+                to: "table",
+                message: None, 
+            })),
+        }
+    }
+}
+
+#[cfg(feature = "lua")]
+impl ComponentFields for Parent {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+        match field {
+            "entity" => lua.pack(LuaEntity(self.entity)),
+            "cascade" => lua.pack(self.cascade),
+            _ => Err(no_field(field)),
+        }
+    }
+
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+        match field {
+            "entity" => self.entity = lua.unpack::<LuaEntity>(value)?.0,
+            "cascade" => self.cascade = lua.unpack(value)?,
+            _ => return Err(no_field(field)),
+        }
+
+        Ok(())
+    }
+}
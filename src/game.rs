@@ -1,17 +1,35 @@
 use component as comp;
 use resource as res;
 use system as sys;
+use snapshot::{self, SnapshotError};
+#[cfg(feature = "lua")]
+use script::{Script, ScriptError, ScriptWatcher, ScriptConfig, read_manifest};
 
+#[cfg(feature = "lua")]
+use std::sync::{Arc, Mutex};
 use std::time::{Instant};
 
+#[cfg(feature = "lua")]
 use specs::RunNow;
 use specs;
+use shred;
+
+// The longest a frame is allowed to take (in seconds), any longer (e.g. after a hitch or a debugger break) and the 
+// game slows down rather than catching up.
+const MAX_FRAME_TIME: f32 = 0.25;
+// The most ticks run in a single update, any leftover time is dropped.
+const MAX_TICKS: u32 = 8;
 
 pub struct Game<'a> {
     dt: f32,
     logic_disp: specs::Dispatcher<'static, 'a>,
-    render_disp: specs::Dispatcher<'static, 'a>,
+    // Headless games (e.g. servers and tests) have nothing to render.
+    render_disp: Option<specs::Dispatcher<'static, 'a>>,
+    #[cfg(feature = "lua")]
     on_tick: sys::script::OnTickEvent,
+    // Reloads the scripts when they change (if hot reloading is enabled).
+    #[cfg(feature = "lua")]
+    script_watcher: Option<ScriptWatcher>,
     
     accumumlator: f32,
     last_update: Option<Instant>,
@@ -20,62 +38,546 @@ pub struct Game<'a> {
 }
 
 impl<'a> Game<'a> {
-    pub fn new(dt: f32, mut logic_disp: specs::Dispatcher<'static, 'a>, mut render_disp: specs::Dispatcher<'static, 'a>) -> Game<'a> {
-        let mut world = specs::World::new();
-        
+    pub fn new(dt: f32, logic_disp: specs::Dispatcher<'static, 'a>, render_disp: specs::Dispatcher<'static, 'a>) -> Game<'a> {
+        Game::with_world(dt, specs::World::new(), logic_disp, Some(render_disp))
+    }
+
+    // Creates a game that only runs its logic, without needing a GPU.
+    pub fn headless(dt: f32, logic_disp: specs::Dispatcher<'static, 'a>) -> Game<'a> {
+        Game::with_world(dt, specs::World::new(), logic_disp, None)
+    }
+
+    // Creates the game with a world which may already hold resources (and entities).
+    pub fn with_world(dt: f32, mut world: specs::World, mut logic_disp: specs::Dispatcher<'static, 'a>, mut render_disp: Option<specs::Dispatcher<'static, 'a>>) -> Game<'a> {
         world.add_resource(res::DeltaTime(dt));   
+        world.add_resource(res::Interpolation(0.0));
+        world.res.entry().or_insert_with(|| res::TimeScale(1.0));
+        world.res.entry().or_insert_with(|| res::SingleStep::default());
+        #[cfg(feature = "lua")]
+        world.res.entry().or_insert_with(|| res::ScriptErrors::default());
+
+        // Transforms are synced by the game itself.
+        world.register::<comp::Transform>();
         
         // Register the components and resources used in the registered systems (with default values)
         logic_disp.setup(&mut world.res);
-        render_disp.setup(&mut world.res);
+        if let Some(ref mut render_disp) = render_disp {
+            render_disp.setup(&mut world.res);
+        }
 
         Game {
             dt,
             logic_disp,
             render_disp,
+            #[cfg(feature = "lua")]
             on_tick: {
                 let mut on_tick = sys::script::OnTickEvent;
                 on_tick.setup(&mut world.res);
                 on_tick
             },
+            #[cfg(feature = "lua")]
+            script_watcher: None,
             accumumlator: 0.0,
             last_update: None,
             world,
         }
     }
 
-    pub fn update(&mut self, time_scale: f32) {
-        // This update's delta time is the constant delta time multiplied by this update's time scale.
+    pub fn update(&mut self) {
         (*self.world.write_resource::<res::DeltaTime>()).0 = self.dt;
 
+        // Only this frame's script errors are kept around to be shown.
+        #[cfg(feature = "lua")]
+        self.clear_script_errors();
+
+        #[cfg(feature = "lua")]
+        {
+            if let Some(ref mut watcher) = self.script_watcher {
+                watcher.update(&self.world);
+            }
+        }
+
         let frame_time = if let Some(lu) = self.last_update {
             let dur = lu.elapsed();
-            dur.as_secs() as f32 + dur.subsec_nanos() as f32 / 1_000_000_000.0
+            (dur.as_secs() as f32 + dur.subsec_nanos() as f32 / 1_000_000_000.0).min(MAX_FRAME_TIME)
         } else {
             0.0
         };
 
-        self.accumumlator += frame_time * time_scale;
-        while self.accumumlator >= self.dt {
-            self.on_tick.run_now(&self.world.res);
-            self.logic_disp.dispatch(&mut self.world.res);
-            self.world.exec(|mut tran: specs::WriteStorage<comp::Transform>| {
-                use specs::Join;
-
-                for mut tran in (&mut tran.restrict_mut()).join() {
-                    if relative_ne!(tran.get_unchecked().last_pos, tran.get_unchecked().pos) {
-                        let tran = tran.get_mut_unchecked();
-                        tran.last_pos = tran.pos;
-                    }
-                }
-            });
-            self.world.maintain();
+        // Nothing ticks while the game is paused, but scripts still get to run (e.g. to unpause it).
+        #[cfg(feature = "lua")]
+        sys::script::run_on_updates(&self.world.res, frame_time);
+
+        let ticks = {
+            let mut single_step = self.world.write_resource::<res::SingleStep>();
+
+            if single_step.enabled {
+                // Only the requested steps are run, so time doesn't build up while stepping.
+                self.accumumlator = 0.0;
+                ::std::mem::replace(&mut single_step.steps, 0).min(MAX_TICKS)
+            } else {
+                single_step.steps = 0;
+
+                // This update's time is scaled, e.g. slowing down or pausing the game.
+                let time_scale = self.world.read_resource::<res::TimeScale>().0.max(0.0);
+                self.accumumlator += frame_time * time_scale;
+
+                let ticks = ((self.accumumlator / self.dt) as u32).min(MAX_TICKS);
+                self.accumumlator -= ticks as f32 * self.dt;
+
+                // Drop whatever the tick limit didn't get through (keeping the fraction of a tick left over).
+                self.accumumlator %= self.dt;
+
+                ticks
+            }
+        };
 
-            self.accumumlator -= self.dt;
+        for _ in 0..ticks {
+            self.run_tick();
         }
+
+        // While single-stepping, the game is shown as it is after the last step.
+        let alpha = if self.single_step() { 1.0 } else { self.accumumlator / self.dt };
+        (*self.world.write_resource::<res::Interpolation>()).0 = alpha;
         
-        self.render_disp.dispatch(&mut self.world.res);
+        if let Some(ref mut render_disp) = self.render_disp {
+            render_disp.dispatch(&mut self.world.res);
+        }
+
+        #[cfg(feature = "lua")]
+        self.expire_script_values();
 
         self.last_update = Some(Instant::now());
     }
-}
\ No newline at end of file
+
+    // Advances the game by exactly one tick, without rendering.
+    pub fn tick(&mut self) {
+        // Ticking directly is a frame of its own, so only its script errors are kept around.
+        #[cfg(feature = "lua")]
+        self.clear_script_errors();
+
+        self.run_tick();
+
+        #[cfg(feature = "lua")]
+        self.expire_script_values();
+    }
+
+    fn run_tick(&mut self) {
+        /* NOTE:
+            The last position is synced at the start of the tick (rather than the end), so after the tick it's 
+            still the previous tick's position, which rendering interpolates from.
+        */
+        self.world.exec(|mut tran: specs::WriteStorage<comp::Transform>| {
+            use specs::Join;
+
+            for mut tran in (&mut tran.restrict_mut()).join() {
+                if relative_ne!(tran.get_unchecked().last_pos, tran.get_unchecked().pos) {
+                    let tran = tran.get_mut_unchecked();
+                    tran.last_pos = tran.pos;
+                }
+            }
+        });
+        #[cfg(feature = "lua")]
+        self.on_tick.run_now(&self.world.res);
+        self.logic_disp.dispatch(&mut self.world.res);
+        self.world.maintain();
+    }
+
+    #[cfg(feature = "lua")]
+    fn clear_script_errors(&self) {
+        self.world.write_resource::<res::ScriptErrors>().clear(&self.world.entities());
+    }
+
+    /* NOTE:
+        Components holding Lua values (e.g. callbacks) hold them by registry key, which queues the value to be freed 
+        when it's dropped, i.e. when the component is removed or its entity is deleted. Only Lua itself is able to 
+        free them though, which happens here, once every update (or tick, when the game is ticked directly).
+    */
+    #[cfg(feature = "lua")]
+    fn expire_script_values(&self) {
+        if let Some(ref mutex) = self.world.read_resource::<res::Script>().0 {
+            mutex.lock().unwrap().expire_registry_values();
+        }
+    }
+
+    pub fn single_step(&self) -> bool {
+        self.world.read_resource::<res::SingleStep>().enabled
+    }
+
+    pub fn set_single_step(&mut self, enabled: bool) {
+        (*self.world.write_resource::<res::SingleStep>()).enabled = enabled;
+    }
+
+    // Advances the game by one tick on the next update (when single-stepping).
+    pub fn step(&mut self) {
+        (*self.world.write_resource::<res::SingleStep>()).steps += 1;
+    }
+}
+
+/* NOTE:
+    The builder holds everything needed to wire up a game, so games built on the engine don't need to touch its 
+    internals. The core logic systems are registered under these names, for other systems to depend on:
+    "tile_map", "tile_map_collision", "velocity", "hierarchy" and "collision". A game with no render systems is 
+    headless.
+*/
+pub struct GameBuilder<'a> {
+    dt: f32,
+    logic: specs::DispatcherBuilder<'static, 'a>,
+    // Only created once a render system is added, otherwise the game is headless.
+    render: Option<specs::DispatcherBuilder<'static, 'a>>,
+    world: specs::World,
+    // Loaded in order, before any entities are spawned (with a config of their own, if they were given one).
+    #[cfg(feature = "lua")]
+    scripts: Vec<(String, Option<ScriptConfig>)>,
+    // Lists more scripts, loaded before the ones added by path (see 'script::read_manifest').
+    #[cfg(feature = "lua")]
+    script_manifest: Option<String>,
+    // Names of the Lua entity tables to spawn once the scripts are loaded.
+    #[cfg(feature = "lua")]
+    entities: Vec<String>,
+    // Reload the scripts when they change on disk.
+    #[cfg(feature = "lua")]
+    hot_reload: bool,
+    // How the scripts are allowed to run (if the game doesn't provide its own Script).
+    #[cfg(feature = "lua")]
+    script_config: ScriptConfig,
+    // Loaded after the scripts, so the entities' Lua callbacks can be bound again.
+    snapshot: Option<String>,
+}
+
+impl<'a> GameBuilder<'a> {
+    pub fn new(dt: f32) -> GameBuilder<'a> {
+        GameBuilder {
+            dt,
+            logic: specs::DispatcherBuilder::new(),
+            render: None,
+            world: specs::World::new(),
+            #[cfg(feature = "lua")]
+            scripts: Vec::new(),
+            #[cfg(feature = "lua")]
+            script_manifest: None,
+            #[cfg(feature = "lua")]
+            entities: Vec::new(),
+            #[cfg(feature = "lua")]
+            hot_reload: false,
+            #[cfg(feature = "lua")]
+            script_config: ScriptConfig::default(),
+            snapshot: None,
+        }
+    }
+
+    // Registers the engine's own logic systems (tile maps, movement, the transform hierarchy and collision).
+    pub fn with_core_systems(self) -> Self {
+        self.with_logic(sys::TileMapSystem, "tile_map", &[])
+            .with_logic(sys::TileMapCollisionSystem::new(), "tile_map_collision", &["tile_map"])
+            .with_logic(sys::VelocitySystem, "velocity", &[])
+            .with_logic(sys::TransformHierarchySystem, "hierarchy", &["velocity"])
+            .with_logic(sys::CollisionSystem::new(), "collision", &["hierarchy", "tile_map_collision"])
+    }
+
+    // Registers Vulkan-free versions of the render data systems (keeping the render order, interpolation, etc. up to 
+    // date), for headless games that still need them.
+    pub fn with_headless_render_systems(self) -> Self {
+        self.with_render(sys::HeadlessTileMapRenderSystem::new(), "tile_map_render", &[])
+            .with_render(sys::HeadlessSpriteSystem::new(), "sprite", &[])
+    }
+
+    // Adds a system run every tick.
+    pub fn with_logic<S>(mut self, system: S, name: &str, deps: &[&str]) -> Self
+    where
+        S: for<'c> specs::System<'c> + Send + 'static,
+    {
+        self.logic.add(system, name, deps);
+        self
+    }
+
+    // Adds a system run every frame, after the ticks.
+    pub fn with_render<S>(mut self, system: S, name: &str, deps: &[&str]) -> Self
+    where
+        S: for<'c> specs::System<'c> + Send + 'static,
+    {
+        self.render.get_or_insert_with(specs::DispatcherBuilder::new).add(system, name, deps);
+        self
+    }
+
+    pub fn with_resource<R: shred::Resource>(mut self, resource: R) -> Self {
+        self.world.add_resource(resource);
+        self
+    }
+
+    #[cfg(feature = "lua")]
+    pub fn with_script(mut self, path: &str) -> Self {
+        self.scripts.push((path.into(), None));
+        self
+    }
+
+    // Loads a script which runs with a config of its own, e.g. 'ScriptConfig::sandboxed()' for a mod loaded alongside
+    // the game's own scripts (see 'Script::load_file_with_config').
+    #[cfg(feature = "lua")]
+    pub fn with_script_using(mut self, path: &str, config: ScriptConfig) -> Self {
+        self.scripts.push((path.into(), Some(config)));
+        self
+    }
+
+    // Loads the scripts listed in a manifest file, in order.
+    #[cfg(feature = "lua")]
+    pub fn with_script_manifest(mut self, path: &str) -> Self {
+        self.script_manifest = Some(path.into());
+        self
+    }
+
+    // How many times an entity's Lua callback is able to fail before it's disabled (None to never disable them).
+    #[cfg(feature = "lua")]
+    pub fn with_max_script_failures(self, max_failures: Option<u32>) -> Self {
+        self.with_resource(res::ScriptErrors::new(max_failures))
+    }
+
+    // Watches the scripts, reloading them (and rebinding the entities' callbacks) when they change on disk.
+    #[cfg(feature = "lua")]
+    pub fn with_hot_reload(mut self) -> Self {
+        self.hot_reload = true;
+        self
+    }
+
+    // Restricts what the scripts are able to do, e.g. 'ScriptConfig::sandboxed()' for mods and user content. It 
+    // applies to every script (and the modules they require), except the ones added with a config of their own.
+    #[cfg(feature = "lua")]
+    pub fn with_script_config(mut self, config: ScriptConfig) -> Self {
+        self.script_config = config;
+        self
+    }
+
+    #[cfg(feature = "lua")]
+    pub fn with_entity(mut self, lua_name: &str) -> Self {
+        self.entities.push(lua_name.into());
+        self
+    }
+
+    // Spawns the entities saved in a snapshot file.
+    pub fn with_snapshot(mut self, path: &str) -> Self {
+        self.snapshot = Some(path.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Game<'a>, GameError> {
+        let mut game = Game::with_world(self.dt, self.world, self.logic.build(), self.render.map(|x| x.build()));
+
+        #[cfg(feature = "lua")]
+        {
+            let mut scripts: Vec<_> = match self.script_manifest {
+                Some(ref path) => read_manifest(path)?.into_iter().map(|path| (path, None)).collect(),
+                None => Vec::new(),
+            };
+            scripts.extend(self.scripts.iter().cloned());
+
+            game = run_startup_scripts(game, &scripts, &self.entities, &self.script_config)?;
+
+            if self.hot_reload {
+                let mut watcher = ScriptWatcher::new();
+                for &(ref path, _) in &scripts {
+                    watcher.watch(path);
+                }
+
+                game.script_watcher = Some(watcher);
+            }
+        }
+
+        if let Some(ref path) = self.snapshot {
+            snapshot::load_file(&mut game.world, path)?;
+        }
+
+        Ok(game)
+    }
+}
+
+#[derive(Debug)]
+pub enum GameError {
+    #[cfg(feature = "lua")]
+    ScriptError(ScriptError),
+    SnapshotError(SnapshotError),
+}
+
+impl From<SnapshotError> for GameError {
+    fn from(error: SnapshotError) -> Self {
+        GameError::SnapshotError(error)
+    }
+}
+
+#[cfg(feature = "lua")]
+impl From<ScriptError> for GameError {
+    fn from(error: ScriptError) -> Self {
+        GameError::ScriptError(error)
+    }
+}
+
+// Loads the startup scripts, then spawns the entities they define.
+#[cfg(feature = "lua")]
+fn run_startup_scripts<'a>(mut game: Game<'a>, scripts: &[(String, Option<ScriptConfig>)], entities: &[String], config: &ScriptConfig) -> Result<Game<'a>, ScriptError> {
+    if scripts.is_empty() && entities.is_empty() {
+        return Ok(game);
+    }
+
+    Script::register_components(&mut game.world);
+
+    // Scripts need somewhere to run, if one wasn't provided.
+    let mutex = {
+        let mut script = game.world.write_resource::<res::Script>();
+        script.0.get_or_insert_with(|| Arc::new(Mutex::new(Script::with_config(config)))).clone()
+    };
+
+    {
+        let script = mutex.lock().unwrap();
+
+        for &(ref path, ref own_config) in scripts {
+            match *own_config {
+                Some(ref own_config) => script.load_file_with_config(path, own_config)?,
+                None => script.load_file(path)?,
+            }
+        }
+
+        for lua_name in entities {
+            script.parse_entity(lua_name, game.world.create_entity())?;
+        }
+    }
+
+    Ok(game)
+}
+
+#[cfg(test)]
+struct TickCounter;
+
+#[cfg(test)]
+#[derive(Default)]
+struct Ticks(u32);
+
+#[cfg(test)]
+impl<'a> specs::System<'a> for TickCounter {
+    type SystemData = specs::Write<'a, Ticks>;
+
+    fn run(&mut self, mut ticks: Self::SystemData) {
+        ticks.0 += 1;
+    }
+}
+
+#[cfg(test)]
+fn counting_game<'a>() -> Game<'a> {
+    GameBuilder::new(1.0/60.0)
+        .with_logic(TickCounter, "tick_counter", &[])
+        .build().unwrap()
+}
+
+#[test]
+fn clamp_ticks() {
+    use std::time::Duration;
+
+    let mut game = counting_game();
+
+    // A long hitch only runs as many ticks as allowed.
+    game.last_update = Some(Instant::now() - Duration::from_secs(10));
+    game.update();
+    assert_eq!(game.world.read_resource::<Ticks>().0, MAX_TICKS);
+    assert!(game.accumumlator < game.dt);
+
+    // Paused games don't tick at all.
+    (*game.world.write_resource::<res::TimeScale>()).0 = 0.0;
+    game.last_update = Some(Instant::now() - Duration::from_secs(10));
+    game.update();
+    assert_eq!(game.world.read_resource::<Ticks>().0, MAX_TICKS);
+}
+
+#[test]
+fn single_step() {
+    let mut game = counting_game();
+    game.set_single_step(true);
+
+    game.update();
+    assert_eq!(game.world.read_resource::<Ticks>().0, 0);
+
+    game.step();
+    game.step();
+    game.update();
+    assert_eq!(game.world.read_resource::<Ticks>().0, 2);
+
+    game.update();
+    assert_eq!(game.world.read_resource::<Ticks>().0, 2);
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn scripts_unpause() {
+    use ::script::{scripted_game_with, spawn};
+    use std::time::Duration;
+
+    let builder = GameBuilder::new(1.0/60.0).with_logic(TickCounter, "tick_counter", &[]);
+    let (mut game, mutex) = scripted_game_with(builder, Script::new(), r#"
+        updates = 0
+        pauser = {
+            script = {
+                on_tick = function(world, this, dt) world:set_time_scale(0.0) end,
+                on_update = function(world, this, frame_time)
+                    updates = updates + 1
+                    if updates == 3 then world:set_time_scale(1.0) end
+                end,
+            },
+        }
+    "#);
+    spawn(&mut game, "pauser");
+
+    let update = |game: &mut Game| {
+        game.last_update = Some(Instant::now() - Duration::from_millis(20));
+        game.update();
+        game.world.read_resource::<Ticks>().0
+    };
+
+    // The first tick pauses the game, which the script unpauses on the third update.
+    assert_eq!(update(&mut game), 1);
+    assert_eq!(update(&mut game), 1);
+    assert_eq!(update(&mut game), 2);
+    assert_eq!(mutex.lock().unwrap().globals().get::<_, i32>("updates").unwrap(), 3);
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn destroyed_entities_free_callbacks() {
+    use ::script::{scripted_game, spawn};
+
+    let (mut game, mutex) = scripted_game("");
+
+    let memory_used = |mutex: &Arc<Mutex<Script>>| {
+        mutex.lock().unwrap().exec::<f64>(r#"collectgarbage("collect") return collectgarbage("count")"#, None).unwrap()
+    };
+
+    let churn = |game: &mut Game| {
+        for _ in 0..500 {
+            // Every entity gets its own callbacks, each holding onto a table.
+            mutex.lock().unwrap().exec::<()>(r#"
+                local data = {}
+                for i = 1, 100 do data[i] = i end
+
+                prefab("churn", {
+                    transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+                    script = { on_tick = function(world, this, dt) return data end },
+                    collider = {
+                        shape_type = "aabb",
+                        shape = { min_x = 0.0, min_y = 0.0, min_z = 0.0, max_x = 0.1, max_y = 0.1, max_z = 0.1 },
+                        on_collide = function(world, this, other) return data end,
+                        pre_solve = function(this, other, norm) return data end,
+                    },
+                })
+            "#, None).unwrap();
+            let entity = spawn(game, "churn");
+
+            game.tick();
+            game.world.delete_entity(entity).unwrap();
+            game.tick();
+        }
+    };
+
+    churn(&mut game);
+    let before = memory_used(&mutex);
+    churn(&mut game);
+    churn(&mut game);
+
+    // Each round leaks over a megabyte if the callbacks aren't freed.
+    assert!(memory_used(&mutex) - before < 256.0);
+}
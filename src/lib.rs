@@ -0,0 +1,106 @@
+#[macro_use]
+extern crate log;
+#[cfg(feature = "render")]
+#[macro_use]
+extern crate vulkano;
+#[cfg(feature = "render")]
+extern crate winit;
+#[cfg(feature = "render")]
+extern crate image;
+#[cfg(feature = "render")]
+#[macro_use]
+extern crate vulkano_shader_derive;
+extern crate dmsort;
+#[macro_use]
+extern crate cgmath;
+extern crate specs;
+extern crate shred;
+#[cfg(feature = "lua")]
+extern crate rlua;
+#[macro_use]
+extern crate nom;
+extern crate num_traits;
+#[macro_use]
+extern crate num_derive;
+
+pub mod collision;
+pub mod resource;
+pub mod component;
+pub mod utility;
+pub mod system;
+pub mod render;
+#[cfg(feature = "lua")]
+#[macro_use]
+pub mod script;
+pub mod parse;
+pub mod snapshot;
+pub mod game;
+
+// Root aliases, which modules use in their paths.
+use component as comp;
+#[cfg(feature = "render")]
+use vulkano as vk;
+
+#[cfg(feature = "render")]
+pub mod vs {
+    #[derive(VulkanoShader)]
+    #[ty = "vertex"]
+    #[src = "
+#version 450
+layout(location = 0) in vec3 position;
+layout(location = 1) in vec2 uv;
+
+layout(set = 0, binding = 0) uniform Instance {
+    mat4 transform;
+} instance;
+
+layout(set = 1, binding = 0) uniform ViewProjection {
+    mat4 view;
+    mat4 proj;
+} viewProj;
+
+layout(location = 0) out vec2 f_uv;
+
+void main() {
+    gl_Position = viewProj.proj * viewProj.view * instance.transform * vec4(position, 1.0);
+    f_uv = uv;
+}
+"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+#[cfg(feature = "render")]
+pub mod fs {
+    #[derive(VulkanoShader)]
+    #[ty = "fragment"]
+    #[src = "
+#version 450
+layout(set = 2, binding = 0) uniform sampler samp;
+layout(set = 2, binding = 1) uniform texture2D textures[4];
+
+layout(push_constant) uniform PER_OBJECT
+{
+    uint imgIdx;
+} pc;
+
+layout(location = 0) out vec4 f_color;
+layout(location = 0) in vec2 f_uv;
+
+void main() {
+    f_color = texture(sampler2D(textures[pc.imgIdx], samp), f_uv);
+}
+"]
+    #[allow(dead_code)]
+    struct Dummy;
+}
+
+#[cfg(feature = "render")]
+#[derive(Debug, Clone)]
+pub struct Vertex { 
+    position: [f32; 3],
+    uv: [f32; 2],
+}
+
+#[cfg(feature = "render")]
+impl_vertex!(Vertex, position, uv);
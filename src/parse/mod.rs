@@ -1,2 +1,5 @@
 mod tilemap;
-pub use self::tilemap::*;
\ No newline at end of file
+pub use self::tilemap::*;
+
+mod snapshot;
+pub use self::snapshot::*;
@@ -0,0 +1,263 @@
+use ::component::tilemap::{STRIP_LENGTH, TileShape};
+use ::component::collider::Shape;
+use ::utility::{Rect2, Rect3};
+
+use std::str::from_utf8;
+
+use num_traits::FromPrimitive;
+use cgmath::{Vector2, Vector3};
+use nom::*;
+
+// Starts every snapshot file.
+pub const SNAPSHOT_MAGIC: &'static [u8] = b"THSN";
+// Bumped whenever the layout of a snapshot changes.
+pub const SNAPSHOT_VERSION: u16 = 3;
+// The oldest layout that's still able to be loaded. Versions 1 and 2 length-prefix prefab names with a u8 rather than
+// a u32, and version 1 snapshots don't have any script state either.
+pub const OLDEST_SNAPSHOT_VERSION: u16 = 1;
+// How deeply saved Lua tables are able to nest (so a snapshot can't make parsing recurse until it overflows).
+pub const MAX_STATE_DEPTH: u32 = 32;
+
+#[derive(Debug)]
+pub struct SavedEntity {
+    // The Lua table the entity was spawned from (if any).
+    pub prefab: Option<String>,
+    pub components: Vec<SavedComponent>,
+}
+
+#[derive(Debug, PartialEq, FromPrimitive)]
+pub enum ComponentTag {
+    Transform = 0,
+    Velocity,
+    Collider,
+    Sprite,
+    TileMap,
+    RenderStrip,
+    CollisionStrip,
+    ScriptState,
+    LocalTransform,
+    Parent,
+}
+
+/* NOTE:
+    Other entities are referenced by their index in the snapshot. Strips hold one value per tile (STRIP_LENGTH of 
+    them), and GPU/broad phase state isn't saved at all (it's rebuilt once the components are inserted).
+*/
+#[derive(Debug)]
+pub enum SavedComponent {
+    Transform {
+        pos: Vector3<f32>,
+        rotation: f32,
+        scale: Vector2<f32>,
+    },
+    LocalTransform {
+        pos: Vector3<f32>,
+        rotation: f32,
+        scale: Vector2<f32>,
+    },
+    Parent {
+        entity: u32,
+        cascade: bool,
+    },
+    Velocity(Vector3<f32>),
+    Collider {
+        shape: Shape,
+        sweep: bool,
+        one_way: Option<Vector3<f32>>,
+    },
+    Sprite {
+        bounds: Rect2<f32>,
+        uv: Rect2<f32>,
+        image_index: u32,
+    },
+    TileMap {
+        tile_dims: Vector3<f32>,
+        tex_dims: Vector2<u32>,
+        image_index: u32,
+        grid_collision: bool,
+    },
+    RenderStrip {
+        tile_map: u32,
+        pos: Vector3<u32>,
+        uvs: Vec<Option<Rect2<f32>>>,
+    },
+    CollisionStrip {
+        tile_map: u32,
+        pos: Vector3<u32>,
+        blocking: Vec<bool>,
+        one_way: Vec<Option<Vector3<f32>>>,
+        shapes: Vec<Option<TileShape>>,
+    },
+    // The entity's Lua state table.
+    ScriptState(SavedValue),
+}
+
+// A Lua value, as far as snapshots are able to hold one (i.e. nothing that only makes sense in a running script).
+#[derive(Debug, Clone, PartialEq)]
+pub enum SavedValue {
+    Boolean(bool),
+    Integer(i64),
+    Number(f64),
+    // Lua strings are bytes, which aren't necessarily UTF-8.
+    String(Vec<u8>),
+    Table(Vec<(SavedValue, SavedValue)>),
+}
+
+// Parses the version, which decides how the rest of the snapshot is parsed.
+named!(pub snapshot_header<u16>, preceded!(tag!(SNAPSHOT_MAGIC), be_u16));
+
+named_args!(pub saved_entities(version: u16)<Vec<SavedEntity>>, length_count!(be_u32, call!(saved_entity, version)));
+
+named_args!(pub saved_entity(version: u16)<SavedEntity>, do_parse!(
+    prefab: map_res!(call!(prefab_name, version), from_utf8) >>
+    components: length_count!(be_u8, saved_component) >>
+    (SavedEntity { 
+        prefab: if prefab.is_empty() { None } else { Some(String::from(prefab)) }, 
+        components 
+    })
+));
+
+named!(pub saved_component<SavedComponent>, switch!(map_opt!(be_u8, FromPrimitive::from_u8),
+    ComponentTag::Transform => do_parse!(
+        pos: vector3 >>
+        rotation: be_f32 >>
+        scale: vector2 >>
+        (SavedComponent::Transform { pos, rotation, scale })
+    ) |
+    ComponentTag::LocalTransform => do_parse!(
+        pos: vector3 >>
+        rotation: be_f32 >>
+        scale: vector2 >>
+        (SavedComponent::LocalTransform { pos, rotation, scale })
+    ) |
+    ComponentTag::Parent => do_parse!(
+        entity: be_u32 >>
+        cascade: boolean >>
+        (SavedComponent::Parent { entity, cascade })
+    ) |
+    ComponentTag::Velocity => map!(vector3, SavedComponent::Velocity) |
+    ComponentTag::Collider => do_parse!(
+        shape: shape >>
+        sweep: boolean >>
+        one_way: opt_vector3 >>
+        (SavedComponent::Collider { shape, sweep, one_way })
+    ) |
+    ComponentTag::Sprite => do_parse!(
+        bounds: rect2 >>
+        uv: rect2 >>
+        image_index: be_u32 >>
+        (SavedComponent::Sprite { bounds, uv, image_index })
+    ) |
+    ComponentTag::TileMap => do_parse!(
+        tile_dims: vector3 >>
+        tex_dims: map!(count_fixed!(u32, be_u32, 2), Vector2::from) >>
+        image_index: be_u32 >>
+        grid_collision: boolean >>
+        (SavedComponent::TileMap { tile_dims, tex_dims, image_index, grid_collision })
+    ) |
+    ComponentTag::RenderStrip => do_parse!(
+        tile_map: be_u32 >>
+        pos: map!(count_fixed!(u32, be_u32, 3), Vector3::from) >>
+        uvs: count!(opt_rect2, STRIP_LENGTH) >>
+        (SavedComponent::RenderStrip { tile_map, pos, uvs })
+    ) |
+    ComponentTag::CollisionStrip => do_parse!(
+        tile_map: be_u32 >>
+        pos: map!(count_fixed!(u32, be_u32, 3), Vector3::from) >>
+        blocking: count!(boolean, STRIP_LENGTH) >>
+        one_way: count!(opt_vector3, STRIP_LENGTH) >>
+        shapes: count!(tile_shape, STRIP_LENGTH) >>
+        (SavedComponent::CollisionStrip { tile_map, pos, blocking, one_way, shapes })
+    ) |
+    ComponentTag::ScriptState => map!(call!(saved_value, 0), SavedComponent::ScriptState)
+));
+
+named_args!(prefab_name(version: u16)<&[u8]>, switch!(value!(version >= 3),
+    true => length_bytes!(be_u32) |
+    false => length_bytes!(be_u8)
+));
+
+named_args!(saved_value(depth: u32)<SavedValue>, preceded!(
+    verify!(value!(depth), |depth: u32| depth <= MAX_STATE_DEPTH),
+    switch!(be_u8,
+        0 => map!(boolean, SavedValue::Boolean) |
+        1 => map!(be_i64, SavedValue::Integer) |
+        2 => map!(be_f64, SavedValue::Number) |
+        3 => map!(length_bytes!(be_u32), |x| SavedValue::String(x.to_vec())) |
+        4 => map!(
+            length_count!(be_u32, pair!(call!(saved_value, depth + 1), call!(saved_value, depth + 1))), 
+            SavedValue::Table
+        )
+    )
+));
+
+named!(shape<Shape>, switch!(be_u8,
+    0 => map!(rect3, Shape::AABB) |
+    1 => do_parse!(
+        offset: vector2 >>
+        radius: be_f32 >>
+        min_z: be_f32 >>
+        max_z: be_f32 >>
+        (Shape::Circle { offset, radius, depth: min_z .. max_z })
+    ) |
+    2 => do_parse!(
+        rect: rect3 >>
+        left: be_f32 >>
+        right: be_f32 >>
+        (Shape::Slope { rect, left, right })
+    ) |
+    3 => do_parse!(
+        points: length_count!(be_u16, vector2) >>
+        min_z: be_f32 >>
+        max_z: be_f32 >>
+        (Shape::Polygon { points, depth: min_z .. max_z })
+    )
+));
+
+named!(tile_shape<Option<TileShape>>, switch!(be_u8,
+    0 => value!(None) |
+    1 => value!(Some(TileShape::Full)) |
+    2 => value!(Some(TileShape::Half)) |
+    3 => do_parse!(
+        left: be_f32 >>
+        right: be_f32 >>
+        (Some(TileShape::Slope(left, right)))
+    )
+));
+
+named!(boolean<bool>, map!(be_u8, |x| x != 0));
+
+named!(vector2<Vector2<f32>>, do_parse!(
+    x: be_f32 >>
+    y: be_f32 >>
+    (Vector2::new(x, y))
+));
+
+named!(vector3<Vector3<f32>>, do_parse!(
+    x: be_f32 >>
+    y: be_f32 >>
+    z: be_f32 >>
+    (Vector3::new(x, y, z))
+));
+
+named!(opt_vector3<Option<Vector3<f32>>>, switch!(be_u8,
+    0 => value!(None) |
+    1 => map!(vector3, Some)
+));
+
+named!(rect2<Rect2<f32>>, do_parse!(
+    min: vector2 >>
+    max: vector2 >>
+    (Rect2::new(min, max))
+));
+
+named!(opt_rect2<Option<Rect2<f32>>>, switch!(be_u8,
+    0 => value!(None) |
+    1 => map!(rect2, Some)
+));
+
+named!(rect3<Rect3<f32>>, do_parse!(
+    min: vector3 >>
+    max: vector3 >>
+    (Rect3::new(min, max))
+));
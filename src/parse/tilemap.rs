@@ -23,6 +23,10 @@ pub struct Chunk {
 pub enum LayerProperty {
     TileIndex = 0,
     Blocking,
+    // The direction a tile can be passed through (0: none, 1: -y, 2: +y, 3: -x, 4: +x).
+    OneWay,
+    // The collision shape of a tile (see 'component::tilemap::TileShape').
+    Shape,
 }
 
 #[derive(Debug)]
@@ -0,0 +1,84 @@
+#[cfg(feature = "render")]
+mod vulkan;
+#[cfg(feature = "render")]
+pub use self::vulkan::{VulkanBackend};
+#[cfg(feature = "render")]
+mod renderer;
+#[cfg(feature = "render")]
+pub use self::renderer::{Renderer, Pipeline, select_physical_device};
+
+use ::resource as res;
+
+use std::cmp::Ordering;
+
+use cgmath::Matrix4;
+use specs;
+
+// Where a draw goes in the draw order, lower keys are drawn first.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SortKey {
+    // The depth layer, lower layers are drawn below higher ones.
+    pub layer: f32,
+    // The bottom edge of what's drawn, within a layer things further down are drawn over things above them.
+    pub bottom: f32,
+}
+
+impl SortKey {
+    pub fn new(layer: f32, bottom: f32) -> SortKey {
+        SortKey {
+            layer,
+            bottom,
+        }
+    }
+
+    pub fn order(&self, other: &SortKey) -> Ordering {
+        match self.layer.partial_cmp(&other.layer).unwrap() {
+            Ordering::Equal => self.bottom.partial_cmp(&other.bottom).unwrap(),
+            order => order,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrawCommand {
+    // The render entity whose mesh (vertex and index buffers) gets drawn.
+    pub mesh: res::RenderId,
+    pub image_index: u32,
+    pub transform: Matrix4<f32>,
+    pub sort_key: SortKey,
+}
+
+/* NOTE:
+    The render system works out what to draw and in what order, then hands the (sorted) draw list to a backend. Like a 
+    system, a backend names the data it needs from the world (e.g. the GPU buffers of the meshes it's drawing), which 
+    is fetched along with the render system's own data.
+*/
+pub trait RenderBackend<'a> {
+    type SystemData: specs::SystemData<'a>;
+
+    fn draw(&mut self, commands: &[DrawCommand], data: Self::SystemData);
+}
+
+// Keeps the last frame's draw list in memory instead of drawing it, for tests and tools.
+#[derive(Default)]
+pub struct RecordingBackend {
+    pub commands: Vec<DrawCommand>,
+    // How many frames have been drawn.
+    pub frames: u32,
+}
+
+impl RecordingBackend {
+    pub fn new() -> RecordingBackend {
+        Default::default()
+    }
+}
+
+impl<'a> RenderBackend<'a> for RecordingBackend {
+    type SystemData = ();
+
+    fn draw(&mut self, commands: &[DrawCommand], _: Self::SystemData) {
+        self.commands.clear();
+        self.commands.extend_from_slice(commands);
+        self.frames += 1;
+    }
+}
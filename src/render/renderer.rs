@@ -0,0 +1,436 @@
+use ::{vs, fs, Vertex};
+use ::game::{Game, GameBuilder};
+use ::resource as res;
+use ::system as sys;
+use super::VulkanBackend;
+
+use std::sync::Arc;
+use std::sync::mpsc;
+use std::cmp::{max, min};
+
+use vulkano as vk;
+use vk::instance::{Instance, PhysicalDevice};
+use vk::swapchain::{Surface, Swapchain};
+use vk::image::SwapchainImage;
+use vk::framebuffer::{Framebuffer, FramebufferAbstract, RenderPassAbstract};
+use vk::buffer::{CpuBufferPool, DeviceLocalBuffer, BufferUsage};
+use vk::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
+use vk::descriptor::DescriptorSet;
+use vk::descriptor::descriptor_set::{FixedSizeDescriptorSetsPool, PersistentDescriptorSet};
+use vk::sync::GpuFuture;
+use vk::device::{Device, Queue};
+use cgmath::{self, ortho, Matrix4};
+use image;
+use winit::Window;
+
+// The pipeline every sprite and tile strip is drawn with.
+pub type Pipeline = vk::pipeline::GraphicsPipeline<
+    vk::pipeline::vertex::SingleBufferDefinition<Vertex>,
+    Box<vk::descriptor::PipelineLayoutAbstract + Send + Sync>,
+    Arc<RenderPassAbstract + Send + Sync>,
+>;
+
+pub fn select_physical_device<'a>(instance: &'a Arc<Instance>) -> Option<PhysicalDevice<'a>> {
+    // TODO: Better physical device selection.
+    PhysicalDevice::from_index(
+        instance,
+        0
+    )
+}
+
+/* NOTE:
+    The renderer owns the Vulkan side of the game (the device, the swapchain of the window's surface, the pipeline and
+    the buffers and descriptor sets shared by every draw), so a game only has to create the window and run the loop:
+    the render systems are added with 'GameBuilder::with_renderer', then each frame is drawn with 'Renderer::frame'.
+*/
+pub struct Renderer {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    surface: Arc<Surface<Window>>,
+    // Keeps track of the proper dimensions, allowing modification throughout the runtime.
+    dimensions: [u32; 2],
+    swapchain: Arc<Swapchain<Window>>,
+    images: Vec<Arc<SwapchainImage<Window>>>,
+    render_pass: Arc<RenderPassAbstract + Send + Sync>,
+    pipeline: Arc<Pipeline>,
+    // Built from the swapchain's images, rebuilt whenever the swapchain is.
+    framebuffers: Option<Vec<Arc<FramebufferAbstract + Send + Sync>>>,
+
+    view: Matrix4<f32>,
+    proj: Matrix4<f32>,
+    view_proj_buffer: CpuBufferPool<vs::ty::ViewProjection>,
+    local_view_proj_buffer: Arc<DeviceLocalBuffer<vs::ty::ViewProjection>>,
+    view_proj_set: Arc<DescriptorSet + Send + Sync>,
+    tex_set: Arc<DescriptorSet + Send + Sync>,
+
+    // Receives the render system's command buffer (once the render systems have been created).
+    cmd_buf_rx: Option<mpsc::Receiver<AutoCommandBuffer>>,
+    // Accumulates previous frames' futures until the GPU is done executing them.
+    // * Submitting a command produces a future, which holds required resources for as long as they are in use by the GPU.
+    previous_frame_end: Box<GpuFuture>,
+    recreate_swapchain: bool,
+}
+
+impl Renderer {
+    // Sets up rendering to the window's surface, with the texture loaded from the image file.
+    pub fn new(instance: &Arc<Instance>, surface: Arc<Surface<Window>>, texture_path: &str) -> Renderer {
+        // Selects a physical device from the ones available on the system.
+        let physical_device = select_physical_device(instance)
+            .expect("Couldn't select physical device");
+        info!("Physical device selected");
+
+        // The device and an iterator over the created queues.
+        // A queue is a CPU thread, executing it's commands one after another, that is used to submit commands to the GPU.
+        let (device, mut queues) = {
+            // Device extensions are similar to instance extensions, except they are for the device.
+            // * It is not possible to use the functions of a extension if it was not explicitly enabled.
+            let extensions = vk::instance::DeviceExtensions {
+                khr_swapchain: true,
+                .. vk::instance::DeviceExtensions::none()
+            };
+
+            // Features are similar too, except they are part of the core Vulkan specs instead of being separate documents.
+            // * It is not possible to use the functions of a feature if it was not explicitly enabled.
+            let features = physical_device.supported_features();
+
+            // TODO: Better handling of queues (specifying priorities, etc.)
+            // List of queues to create, each element indicates it's family and priority (0.0 - 1.0).
+            // Queues are divided in queue families, all the queues within have the same characteristics.
+            // * No guarantee can be made on the way the priority is handled by the implementation.
+            let queues = physical_device.queue_families()
+                .map(|family|
+                    (family, 1.0)
+                );
+
+            Device::new(
+                physical_device,
+                &features,
+                &extensions,
+                queues,
+            ).expect("Couldn't initialize device")
+        };
+
+        info!("Device initialized");
+        info!("Queues initialized");
+
+        // Acquires the capabilities of the Vulkan surface when used by the physical device.
+        let capabs = surface.capabilities(physical_device)
+            .expect("Couldn't acquire surface capabilities");
+        info!("Surface capabilities acquired");
+
+        let dimensions = capabs.current_extent.unwrap_or([640, 480]);
+
+        // The swapping system and the images that can be shown on the Vulkan surface.
+        // * The order in which the images are returned is important for the acquire_next_image and present functions.
+        let (swapchain, images) = {
+            // Try to use double-buffering.
+            let buffers_count = max(min(2, capabs.min_image_count), capabs.max_image_count.unwrap_or(2));
+
+            let transform = capabs.current_transform;
+
+            let (format, _color_space) = capabs.supported_formats[0];
+
+            let usage = vk::image::ImageUsage {
+                .. capabs.supported_usage_flags
+            };
+
+            let alpha = capabs.supported_composite_alpha.iter().next().unwrap();
+
+            let sharing_mode = vk::sync::SharingMode::Exclusive(0);
+
+            let present_mode = vk::swapchain::PresentMode::Fifo;
+
+            Swapchain::new(
+                device.clone(),
+                surface.clone(),
+                buffers_count,
+                format,
+                dimensions,
+                1,
+                usage,
+                sharing_mode,
+                transform,
+                alpha,
+                present_mode,
+                true,
+                None
+            ).expect("Couldn't initialize swapchain")
+        };
+        info!("Swapchain initialized");
+
+        let queue = queues.nth(0).unwrap();
+
+        let vs = vs::Shader::load(device.clone()).expect("Couldn't create shader module");
+        let fs = fs::Shader::load(device.clone()).expect("Couldn't create shader module");
+        info!("Shaders initialized");
+
+        // Defines layout of the subpass(es).
+        let render_pass = Arc::new(single_pass_renderpass!(device.clone(),
+            attachments: {
+                // Custom name we give to the first and only attachment.
+                color: {
+                    // GPU should clear the content of this attachment at the start of the drawing.
+                    load: Clear,
+                    // GPU should store the output of the draw in the actual image. We could also ask it to discard the result.
+                    store: Store,
+                    // Indicates the type of the format of the image. Here we use the format specified by the swapchain.
+                    format: swapchain.format(),
+                    samples: 1,
+                }
+            },
+            pass: {
+                // We use the attachment named color as the one and only color attachment.
+                color: [color],
+                // No depth-stencil attachment is indicated with empty brackets.
+                depth_stencil: {}
+            }
+        ).expect("Couldn't initialize render pass")) as Arc<RenderPassAbstract + Send + Sync>;
+
+        info!("Render pass initialized");
+
+        // Defines how to perform a draw operation.
+        let pipeline = Arc::new(vk::pipeline::GraphicsPipeline::start()
+            .vertex_input_single_buffer::<Vertex>()
+            .vertex_shader(vs.main_entry_point(), ())
+            .triangle_list()
+            .viewports_dynamic_scissors_irrelevant(1)
+            .fragment_shader(fs.main_entry_point(), ())
+            .render_pass(vk::framebuffer::Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(device.clone())
+            .unwrap());
+        info!("Pipeline initialized");
+
+        let view_proj_buffer = CpuBufferPool::<vs::ty::ViewProjection>::new(
+            device.clone(),
+            BufferUsage::uniform_buffer() | BufferUsage::transfer_source(),
+        );
+
+        let local_view_proj_buffer = DeviceLocalBuffer::<vs::ty::ViewProjection>::new(
+            device.clone(),
+            BufferUsage::uniform_buffer_transfer_destination(),
+            vec![queue.family()]
+        ).expect("Couldn't create uniform device local buffer");
+
+        let view_proj_set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 1)
+            .add_buffer(local_view_proj_buffer.clone()).unwrap()
+            .build().unwrap()
+        );
+
+        let (texture, tex_future) = {
+            let loaded = image::open(texture_path).unwrap().to_rgba();
+            let dims = loaded.dimensions();
+            let image_data = loaded.into_raw().clone();
+
+            vk::image::ImmutableImage::from_iter(
+                image_data.iter().cloned(),
+                vk::image::Dimensions::Dim2d {
+                    width: dims.0,
+                    height: dims.1
+                },
+                vk::format::R8G8B8A8Srgb,
+                queue.clone()
+            ).unwrap()
+        };
+        info!("Immutable texture image created");
+
+        let sampler = vk::sampler::Sampler::simple_repeat_linear(device.clone());
+
+        info!("Sampler created");
+
+        let tex_set = Arc::new(PersistentDescriptorSet::start(pipeline.clone(), 2)
+            .add_sampler(sampler).unwrap()
+            .enter_array().unwrap()
+                .add_image(texture.clone()).unwrap()
+                .add_image(texture.clone()).unwrap()
+                .add_image(texture.clone()).unwrap()
+                .add_image(texture.clone()).unwrap()
+            .leave_array().unwrap()
+            .build().unwrap());
+        info!("Texture set initialized");
+
+        Renderer {
+            device,
+            queue,
+            surface,
+            dimensions,
+            swapchain,
+            images,
+            render_pass,
+            pipeline,
+            framebuffers: None,
+            view: Matrix4::look_at_dir(cgmath::Point3::new(0.0, 0.0, -1.0), cgmath::Vector3::new(0.0, 0.0, 1.0), cgmath::Vector3::new(0.0, 1.0, 0.0)),
+            proj: projection(dimensions),
+            view_proj_buffer,
+            local_view_proj_buffer,
+            view_proj_set,
+            tex_set,
+            cmd_buf_rx: None,
+            previous_frame_end: Box::new(tex_future) as Box<GpuFuture>,
+            recreate_swapchain: false,
+        }
+    }
+
+    // The swapchain is rebuilt (at the window's new size) before the next frame.
+    pub fn resize(&mut self) {
+        self.recreate_swapchain = true;
+    }
+
+    // Updates the game, then draws and presents what its render systems recorded.
+    pub fn frame(&mut self, game: &mut Game) {
+        // Frees up resources that are no longer needed by checking what the GPU has already processed.
+        self.previous_frame_end.cleanup_finished();
+
+        if self.recreate_swapchain {
+            self.dimensions = self.surface.capabilities(self.device.physical_device())
+                .expect("Couldn't acquire surface capabilities")
+                .current_extent.unwrap_or([640, 480]);
+
+            // Recreate the swapchain and its images with the new dimensions.
+            let (new_swapchain, new_images) = match self.swapchain.recreate_with_dimension(self.dimensions) {
+                Ok(r) => r,
+                Err(vk::swapchain::SwapchainCreationError::UnsupportedDimensions) => {
+                    return;
+                },
+                Err(err) => panic!("{:?}", err)
+            };
+
+            self.swapchain = new_swapchain;
+            self.images = new_images;
+
+            self.proj = projection(self.dimensions);
+
+            // With new swapchain images, we recreate the frame buffers.
+            self.framebuffers = None;
+
+            self.recreate_swapchain = false;
+        }
+
+        if self.framebuffers.is_none() {
+            // Builds each frame buffer with the render pass and their corresponding image views.
+            let render_pass = &self.render_pass;
+
+            self.framebuffers = Some(self.images.iter().map(|image| {
+                Arc::new(Framebuffer::start(render_pass.clone())
+                    .add(image.clone()).unwrap()
+                    .build().unwrap()) as Arc<FramebufferAbstract + Send + Sync>
+            }).collect::<Vec<_>>());
+        }
+
+        // Blocks until able to acquire a drawable image from the swapchain. Returns the index of that image.
+        let (image_index, acquire_future) = match vk::swapchain::acquire_next_image(self.swapchain.clone(), None) {
+            Ok(r) => r,
+            Err(vk::swapchain::AcquireError::OutOfDate) => {
+                self.recreate_swapchain = true;
+                return;
+            },
+            Err(err) => panic!("{:?}", err)
+        };
+
+        // Passes this frame's available frame buffer into a resource.
+        (*game.world.write_resource::<res::Framebuffer>()).0 = Some(
+            self.framebuffers.as_ref().unwrap()[image_index].clone()
+        );
+
+        // TODO: Create the DynamicState somewhere else, it only needs an update when the dimensions change.
+        (*game.world.write_resource::<res::DynamicState>()).0 = Some(
+            vk::command_buffer::DynamicState {
+                line_width: None,
+                // List of viewports, the region of the image corresponding to the vertex coords -1.0 to 1.0.
+                viewports: Some(vec![vk::pipeline::viewport::Viewport {
+                    // Coordinates of the top-left corner of the viewport (in pixels).
+                    origin: [0.0, 0.0],
+                    // Dimensions of the viewport (in pixels).
+                    dimensions: [self.dimensions[0] as f32, self.dimensions[1] as f32],
+                    // The range to map z-coords with before comparison with other depth values.
+                    depth_range: 0.0 .. 1.0,
+                }]),
+                // List of scissor boxes, any pixel outside of the scissor box is discarded.
+                scissors: None,
+            }
+        );
+
+        game.update();
+
+        // Receives the render system's command buffer for execution.
+        let render_command_buffer = self.cmd_buf_rx.as_ref()
+            .expect("The game has no render systems, see 'GameBuilder::with_renderer'")
+            .recv().unwrap();
+
+        let view_proj_data = vs::ty::ViewProjection {
+            view: self.view.into(),
+            proj: self.proj.into(),
+        };
+
+        let misc_command_buffer = AutoCommandBufferBuilder::primary_one_time_submit(self.device.clone(), self.queue.family()).unwrap()
+            .copy_buffer(
+                self.view_proj_buffer.next(view_proj_data).unwrap(),
+                self.local_view_proj_buffer.clone(),
+            ).unwrap()
+            .build().unwrap();
+
+        let previous_frame_end = ::std::mem::replace(
+            &mut self.previous_frame_end,
+            Box::new(vk::sync::now(self.device.clone())) as Box<GpuFuture>
+        );
+
+        // Joins previous frames' accumulated futures with the new future.
+        let future = previous_frame_end.join(acquire_future)
+            // Submits a command to execute our command buffer on the selected queue.
+            .then_execute(self.queue.clone(), misc_command_buffer).unwrap()
+            .then_execute(self.queue.clone(), render_command_buffer).unwrap()
+            // Submits a command to present the image at the end of the queue (after executing previous commands).
+            .then_swapchain_present(self.queue.clone(), self.swapchain.clone(), image_index)
+            .then_signal_fence_and_flush();
+
+        match future {
+            Ok(future) => {
+                self.previous_frame_end = Box::new(future) as Box<_>;
+            }
+            Err(vk::sync::FlushError::OutOfDate) => {
+                self.recreate_swapchain = true;
+            }
+            Err(e) => {
+                error!("{:?}", e);
+            }
+        }
+    }
+}
+
+// Defined alongside the Vulkan setup it wires up.
+impl<'a> GameBuilder<'a> {
+    // Registers the Vulkan render systems, drawing each frame for the renderer (see 'Renderer::frame').
+    pub fn with_renderer(self, renderer: &mut Renderer) -> Self {
+        let sprite_sys = {
+            let instance_sets = FixedSizeDescriptorSetsPool::new(renderer.pipeline.clone(), 0);
+            let instance_buf = CpuBufferPool::<vs::ty::Instance>::new(
+                renderer.device.clone(),
+                BufferUsage::uniform_buffer() | BufferUsage::transfer_source(),
+            );
+
+            sys::SpriteSystem::new(instance_sets, instance_buf)
+        };
+
+        let render_sys = {
+            let (backend, rx) = VulkanBackend::new(renderer.pipeline.clone(), renderer.queue.clone());
+            renderer.cmd_buf_rx = Some(rx);
+            sys::RenderSystem::new(backend)
+        };
+
+        self.with_render(sys::TileMapRenderSystem::new(), "tile_map_render", &[])
+            .with_render(sprite_sys, "sprite", &[])
+            .with_render(render_sys, "render", &["sprite", "tile_map_render"])
+            .with_resource(res::TextureSet(Some(renderer.tex_set.clone())))
+            .with_resource(res::ViewProjectionSet(Some(renderer.view_proj_set.clone())))
+            .with_resource(res::Device(Some(renderer.device.clone())))
+            .with_resource(res::Queue(Some(renderer.queue.clone())))
+            .with_resource(res::Framebuffer(None))
+            .with_resource(res::DynamicState(None))
+    }
+}
+
+fn projection(dimensions: [u32; 2]) -> Matrix4<f32> {
+    let aspect = dimensions[0] as f32 / dimensions[1] as f32;
+    let (w, h) = (1. * aspect, 1.);
+
+    ortho(w, -w, -h, h, -10., 10.)
+}
@@ -0,0 +1,138 @@
+use ::fs;
+use ::vs;
+use ::component as comp;
+use ::resource as res;
+use super::{RenderBackend, DrawCommand};
+
+use std::sync::Arc;
+use std::sync::mpsc;
+
+use vulkano as vk;
+use vk::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
+use specs;
+use cgmath;
+
+// Records the draw list into a command buffer, which is sent off to be executed.
+pub struct VulkanBackend<L> {
+    pipeline: Arc<L>,
+    default_instance: Arc<vk::descriptor::DescriptorSet + Send + Sync>,
+    
+    cmd_buf_tx: mpsc::Sender<AutoCommandBuffer>
+}
+
+impl<L> VulkanBackend<L> 
+where
+    L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
+{
+    pub fn new(
+        pipeline: Arc<L>,
+        queue: Arc<vk::device::Queue>,
+    ) -> (VulkanBackend<L>, mpsc::Receiver<AutoCommandBuffer>) {
+        use vk::descriptor::descriptor_set::*;
+        use cgmath::One;
+
+        let (tx, rx) = mpsc::channel();
+
+        let (inst_buf, _future) = vk::buffer::ImmutableBuffer::from_data(
+            vs::ty::Instance {
+                transform: cgmath::Matrix4::one().into(),
+            },
+            vk::buffer::BufferUsage::uniform_buffer(),
+            queue
+        ).unwrap();
+
+        let set = PersistentDescriptorSet::start(pipeline.clone(), 0)
+            .add_buffer(inst_buf).unwrap()
+        .build().unwrap();
+
+        (VulkanBackend {
+            pipeline: pipeline,
+            default_instance: Arc::new(set),
+            cmd_buf_tx: tx
+        },
+        rx)
+    }
+}
+
+impl<'a, L> RenderBackend<'a> for VulkanBackend<L> 
+where 
+    L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
+{
+    type SystemData = (
+        specs::Read<'a, res::Device>,
+        specs::Read<'a, res::Queue>,
+        specs::Read<'a, res::Framebuffer>,
+        specs::Read<'a, res::DynamicState>,
+        specs::Read<'a, res::ViewProjectionSet>,
+        specs::Read<'a, res::TextureSet>,
+        specs::ReadStorage<'a, comp::Sprite>,
+        specs::ReadStorage<'a, comp::RenderStrip>,
+    );
+
+    fn draw(&mut self, commands: &[DrawCommand], (device, queue, framebuffer, state, view_proj, tex_set, sprite, strip): Self::SystemData) {
+        let queue = queue.0.as_ref().unwrap();
+        let device = device.0.as_ref().unwrap();
+        let framebuffer = framebuffer.0.as_ref().unwrap();
+        let state = state.0.as_ref().unwrap();
+        let view_proj = view_proj.0.as_ref().unwrap();
+        let tex_set = tex_set.0.as_ref().unwrap();
+
+        // Holds the list of commands that are going to be executed.
+        // * The only queues able to execute the command buffer are the ones of the family passed to the constructor.
+        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family()).unwrap()
+            .begin_render_pass(
+                framebuffer.clone(), 
+                false, 
+                // Clear values for the attachments marked 'load: Clear' on the render pass.
+                vec![
+                    // The color used to clear the screen.
+                    [1.0, 1.0, 1.0, 1.0].into()
+                ]
+            ).unwrap();
+
+        // Now to render.
+        for cmd in commands {
+            /* NOTE:
+                The command's transform isn't uploaded here, sprites already have an instance set holding it (written 
+                by the sprite system), and tile strips are drawn untransformed.
+            */
+            let (instance_set, v_buf, i_buf) = match cmd.mesh {
+                res::RenderId::Sprite(e) => match sprite.get(e) {
+                    Some(&comp::Sprite { 
+                        instance_set: Some(ref instance_set), 
+                        vertex_buf: Some(ref v_buf), 
+                        index_buf: Some(ref i_buf), 
+                        .. 
+                    }) => (instance_set.clone(), v_buf, i_buf),
+                    // Not built yet (or removed since the draw list was made).
+                    _ => continue,
+                },
+                res::RenderId::TileStrip(e) => match strip.get(e) {
+                    Some(&comp::RenderStrip { 
+                        vertex_buf: Some(ref v_buf), 
+                        index_buf: Some(ref i_buf), 
+                        .. 
+                    }) => (self.default_instance.clone(), v_buf, i_buf),
+                    _ => continue,
+                }
+            };
+
+            builder = builder.draw_indexed(
+                self.pipeline.clone(),
+                state.clone(),
+                vec![v_buf.clone()],
+                i_buf.clone(),
+                (instance_set, view_proj.clone(), tex_set.clone()),
+                fs::ty::PER_OBJECT { imgIdx: cmd.image_index }
+            ).unwrap();
+        }
+
+        let command_buffer = 
+            builder.end_render_pass().unwrap()
+            .build().unwrap();
+
+        // Sends the built command buffer off for execution.
+        self.cmd_buf_tx.send(command_buffer)
+            .expect("Couldn't send the command buffer, receiving end disconnected.");
+    }
+}
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+#[cfg(feature = "render")]
 use winit::{KeyboardInput, VirtualKeyCode, ElementState};
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -52,6 +53,7 @@ macro_rules! input {
             $($n,)*
         }
 
+        #[cfg(feature = "render")]
         pub fn key_to_input(key: &KeyboardInput) -> Option<(Input, InputState)> {
             let state = match key.state { 
                 ElementState::Pressed => InputState::Pressed, 
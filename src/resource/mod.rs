@@ -4,32 +4,134 @@ pub use self::render::{RenderId, SortedRender};
 pub mod input;
 pub use self::input::{InputList};
 
+#[cfg(feature = "lua")]
 use script;
 
-use std::sync::{Mutex, Arc};
+#[cfg(any(feature = "lua", feature = "render"))]
+use std::sync::Arc;
+#[cfg(feature = "lua")]
+use std::sync::Mutex;
+#[cfg(feature = "lua")]
+use std::collections::HashMap;
 
+#[cfg(feature = "lua")]
+use rlua::Error as LuaError;
+#[cfg(feature = "lua")]
+use specs;
+
+#[cfg(feature = "render")]
 use vulkano as vk;
 
 #[derive(Default)]
 pub struct DeltaTime(pub f32);
 
+// Scales how fast game time passes relative to real time (0.0 pauses the game).
+pub struct TimeScale(pub f32);
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        TimeScale(1.0)
+    }
+}
+
+// When enabled, the game only advances when a step is requested (one tick per step), for debugging.
+#[derive(Default)]
+pub struct SingleStep {
+    pub enabled: bool,
+    pub steps: u32,
+}
+
+// How far the game is between the last tick and the next one (0.0 - 1.0), used to interpolate rendering.
+#[derive(Default)]
+pub struct Interpolation(pub f32);
+
+#[cfg(feature = "lua")]
 #[derive(Default)]
 pub struct Script(pub Option<Arc<Mutex<script::Script>>>);
 
+// How many times a callback fails before it's disabled, unless configured otherwise.
+#[cfg(feature = "lua")]
+pub const DEFAULT_MAX_SCRIPT_FAILURES: u32 = 3;
+
+// Errors raised by script callbacks this frame (e.g. for a console or overlay to show), which are cleared at the 
+// start of every update (or tick, when the game is ticked directly).
+#[cfg(feature = "lua")]
+pub struct ScriptErrors {
+    pub errors: Vec<script::CallbackError>,
+    // Errors from (re)loading script files.
+    pub load_errors: Vec<String>,
+    // How many times an entity's callback is able to fail before it's disabled (never, if there's no limit).
+    pub max_failures: Option<u32>,
+    failures: HashMap<(specs::Entity, &'static str), u32>,
+}
+
+#[cfg(feature = "lua")]
+impl ScriptErrors {
+    pub fn new(max_failures: Option<u32>) -> Self {
+        ScriptErrors {
+            errors: Vec::new(),
+            load_errors: Vec::new(),
+            max_failures,
+            failures: HashMap::new(),
+        }
+    }
+
+    // Forgets the last frame's errors, along with the failures of entities which are no longer alive.
+    pub fn clear(&mut self, entities: &specs::world::EntitiesRes) {
+        self.errors.clear();
+        self.load_errors.clear();
+        self.failures.retain(|&(e, _), _| entities.is_alive(e));
+    }
+
+    // Logs a callback's error, returning whether the callback has failed enough times to be disabled.
+    pub fn report(&mut self, entity: specs::Entity, callback: &'static str, error: LuaError) -> bool {
+        let error = script::CallbackError::new(entity, callback, &error);
+        error!("{}", error);
+        self.errors.push(error);
+
+        let disable = {
+            let failures = self.failures.entry((entity, callback)).or_insert(0);
+            *failures += 1;
+
+            self.max_failures.map(|max| *failures >= max).unwrap_or(false)
+        };
+
+        if disable {
+            warn!("Disabled '{}' of entity {} after {} failures", callback, entity.id(), self.failures[&(entity, callback)]);
+            self.failures.remove(&(entity, callback));
+        }
+
+        disable
+    }
+}
+
+#[cfg(feature = "lua")]
+impl Default for ScriptErrors {
+    fn default() -> Self {
+        ScriptErrors::new(Some(DEFAULT_MAX_SCRIPT_FAILURES))
+    }
+}
+
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct ViewProjectionSet(pub Option<Arc<vk::descriptor::DescriptorSet + Send + Sync>>);
 
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct TextureSet(pub Option<Arc<vk::descriptor::DescriptorSet + Send + Sync>>);
 
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct Device(pub Option<Arc<vk::device::Device>>);
 
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct Queue(pub Option<Arc<vk::device::Queue>>);
 
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct Framebuffer(pub Option<Arc<vk::framebuffer::FramebufferAbstract + Send + Sync>>);
 
+#[cfg(feature = "render")]
 #[derive(Default)]
 pub struct DynamicState(pub Option<vk::command_buffer::DynamicState>);
\ No newline at end of file
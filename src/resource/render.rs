@@ -1,5 +1,6 @@
 use specs;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum RenderId {
     Sprite(specs::Entity),
     TileStrip(specs::Entity)
@@ -9,4 +10,22 @@ pub enum RenderId {
 pub struct SortedRender {
     pub ids: Vec<RenderId>,
     pub need_sort: bool,
-}
\ No newline at end of file
+}
+
+impl SortedRender {
+    // Stops drawing the sprites which were removed (or deleted along with their entity), by entity id.
+    pub fn remove_sprites(&mut self, removed: &specs::BitSet) {
+        self.ids.retain(|id| match *id {
+            RenderId::Sprite(e) => !removed.contains(e.id()),
+            RenderId::TileStrip(_) => true,
+        });
+    }
+
+    // Stops drawing the render strips which were removed (or deleted along with their entity), by entity id.
+    pub fn remove_strips(&mut self, removed: &specs::BitSet) {
+        self.ids.retain(|id| match *id {
+            RenderId::TileStrip(e) => !removed.contains(e.id()),
+            RenderId::Sprite(_) => true,
+        });
+    }
+}
@@ -0,0 +1,135 @@
+use ::component::Wait;
+use script::expired_world;
+
+use rlua::{Lua, Table, Value as LuaValue, Function as LuaFunction, Error as LuaError, Result as LuaResult, AnyUserData, MultiValue};
+
+/* NOTE:
+    A behavior's 'run' function is started as a coroutine, which 'OnTickEvent' resumes every tick until it returns.
+    The wait functions yield what the coroutine is waiting for, which is counted down before it's resumed again. The
+    coroutine outlives the callbacks resuming it, so rather than the world it's given a stand-in, which forwards to
+    the world of whichever callback is running.
+*/
+pub fn add_wait_fns(lua: &Lua) -> LuaResult<()> {
+    let current_world = lua.create_function(|lua, ()| {
+        lua.named_registry_value::<Option<AnyUserData>>("world")?.ok_or_else(expired_world)
+    })?;
+
+    let world: Table = lua.load(r#"
+        local current_world = ...
+        local yield = coroutine.yield
+
+        function wait(seconds) yield("seconds", seconds) end
+        function wait_ticks(ticks) yield("ticks", ticks) end
+        function wait_until(condition) yield("until", condition) end
+
+        return setmetatable({}, {
+            __index = function(_, name)
+                return function(_, ...)
+                    local world = current_world()
+                    return world[name](world, ...)
+                end
+            end,
+        })
+    "#, Some("coroutine"))?.call(current_world)?;
+
+    lua.set_named_registry_value("coroutine_world", world)
+}
+
+// The world as coroutines see it (see 'add_wait_fns').
+pub fn world(lua: &Lua) -> LuaResult<Table> {
+    lua.named_registry_value("coroutine_world")
+}
+
+// What a coroutine yielded (a plain 'coroutine.yield()' waits for the next tick).
+pub fn wait_from_lua(lua: &Lua, values: MultiValue) -> LuaResult<Wait> {
+    let (kind, arg): (LuaValue, LuaValue) = lua.unpack_multi(values)?;
+
+    let kind = match kind {
+        LuaValue::Nil => return Ok(Wait::Tick),
+        LuaValue::String(kind) => kind,
+        _ => return Err(LuaError::RuntimeError("Coroutines are only able to yield what they wait for".into())),
+    };
+
+    match kind.to_str()? {
+        "seconds" => Ok(Wait::Seconds(lua.unpack(arg)?)),
+        "ticks" => Ok(Wait::Ticks(lua.unpack(arg)?)),
+        "until" => Ok(Wait::Until(lua.create_registry_value(lua.unpack::<LuaFunction>(arg)?)?)),
+        kind => Err(LuaError::RuntimeError(format!("Coroutines can't wait for '{}'", kind))),
+    }
+}
+
+#[test]
+fn coroutines_and_timers() {
+    use ::resource as res;
+    use ::component as comp;
+    use ::script::{scripted_game, spawn};
+
+    let (mut game, mutex) = scripted_game(r#"
+        tick = 0
+        log = {}
+        after_calls = 0
+        every_calls = 0
+        doomed_calls = 0
+
+        clock = {
+            script = { on_tick = function(world, this, dt) tick = tick + 1 end },
+        }
+        walker = {
+            script = {
+                run = function(world, this)
+                    table.insert(log, "start " .. tick)
+                    wait_ticks(2)
+                    table.insert(log, "ticks " .. tick)
+                    wait(0.04)
+                    table.insert(log, "seconds " .. tick)
+                    wait_until(function() return ready end)
+                    table.insert(log, "until " .. tick .. " " .. tostring(world:is_alive(this)))
+                end,
+            },
+        }
+        timed = {
+            script = {
+                run = function(world, this)
+                    world:after(0.04, function(world, this) after_calls = after_calls + 1 end)
+                    world:every(1/60, function(world, this)
+                        every_calls = every_calls + 1
+                        return every_calls < 3
+                    end)
+                end,
+            },
+        }
+        doomed = {
+            script = {
+                run = function(world, this)
+                    world:every(1/60, function(world, this) doomed_calls = doomed_calls + 1 end)
+                end,
+            },
+        }
+    "#);
+    spawn(&mut game, "clock");
+    spawn(&mut game, "timed");
+    let walker = spawn(&mut game, "walker");
+    let doomed = spawn(&mut game, "doomed");
+
+    for _ in 0..3 {
+        game.tick();
+    }
+    game.world.delete_entity(doomed).unwrap();
+
+    for _ in 0..5 {
+        game.tick();
+    }
+    mutex.lock().unwrap().globals().set("ready", true).unwrap();
+    game.tick();
+
+    assert!(game.world.read_storage::<comp::ScriptBehavior>().get(walker).unwrap().run.is_none());
+    assert!(game.world.read_resource::<res::ScriptErrors>().errors.is_empty());
+
+    let script = mutex.lock().unwrap();
+    let globals = script.globals();
+    let log: Vec<String> = globals.get::<_, Table>("log").unwrap().sequence_values().collect::<LuaResult<_>>().unwrap();
+    assert_eq!(log, vec!["start 1", "ticks 3", "seconds 6", "until 9 true"]);
+    assert_eq!(globals.get::<_, i32>("after_calls").unwrap(), 1);
+    assert_eq!(globals.get::<_, i32>("every_calls").unwrap(), 3);
+    assert_eq!(globals.get::<_, i32>("doomed_calls").unwrap(), 2);
+}
@@ -1,7 +1,20 @@
 pub mod types;
 
-mod parse;
-pub use self::parse::ComponentParser;
+pub mod parse;
+pub use self::parse::{ComponentParser, ComponentFields};
+
+pub mod reload;
+pub use self::reload::ScriptWatcher;
+
+pub mod sandbox;
+pub use self::sandbox::{ScriptConfig, SandboxProfile};
+
+pub mod module;
+pub use self::module::read_manifest;
+
+pub mod coroutine;
+
+pub mod state;
 
 use ::resource as res;
 use ::component as comp;
@@ -13,14 +26,14 @@ use std::io::prelude::*;
 use std::io::Error as IoError;
 use std::fs::File;
 use std::rc::Rc;
+use std::fmt;
 
 use specs;
 use specs::Builder;
-use shred;
 use shred::cell::{Ref, RefMut};
 use cgmath::Vector3;
 use rlua::{Lua, Table, RegistryKey, Value as LuaValue, Result as LuaResult, Function as LuaFunction, Error as LuaError, String as LuaString,
-    UserData, UserDataMethods, AnyUserData, Scope as LuaScope};
+    UserData, UserDataMethods, MetaMethod, AnyUserData, Scope as LuaScope};
 
 #[derive(Clone)]
 pub struct LuaEntity(pub specs::Entity);
@@ -53,13 +66,305 @@ impl From<IoError> for ScriptError {
     }
 }
 
-#[derive(Clone)]
-pub struct LuaWorld(pub *const specs::Resources);
+// An error raised by one of an entity's Lua callbacks.
+#[derive(Debug, Clone)]
+pub struct CallbackError {
+    pub entity: specs::Entity,
+    // The callback's field name, e.g. "on_tick".
+    pub callback: &'static str,
+    // The error, along with the Lua traceback.
+    pub message: String,
+}
+
+impl CallbackError {
+    pub fn new(entity: specs::Entity, callback: &'static str, error: &LuaError) -> Self {
+        let message = match *error {
+            LuaError::CallbackError { ref traceback, ref cause } => format!("{}\n{}", cause, traceback),
+            ref error => error.to_string(),
+        };
+
+        CallbackError {
+            entity,
+            callback,
+            message,
+        }
+    }
+}
 
-unsafe impl Send for LuaWorld { }
+impl fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'{}' of entity {} failed: {}", self.callback, self.entity.id(), self.message)
+    }
+}
+
+/* NOTE:
+    Scripts are only given the world for the duration of a callback (see 'with_world'). The world is scoped userdata,
+    so once the callback returns it's destroyed, and a script holding onto it (or one of its component proxies) gets 
+    an error instead of reaching into resources which may no longer be there.
+*/
+pub struct LuaWorld {
+    // Only valid while the scope it was created in is alive (which is the only time Lua can reach it).
+    res: *const specs::Resources,
+    // The entity whose callback the world was given to.
+    entity: specs::Entity,
+}
+
+impl LuaWorld {
+    // The pointer is only valid during the callback the world was given to, which holds as long as the world is only 
+    // reached through the scoped userdata (destroyed once 'with_world' returns). That's why this isn't public.
+    fn res(&self) -> &specs::Resources {
+        unsafe { &*self.res }
+    }
+
+    fn fetch<'a, T: specs::SystemData<'a>>(&'a self) -> LuaResult<T> {
+        fetch(self.res())
+    }
+}
+
+// Fetches the system data a binding needs, with an error (rather than a panic) if one of its resources is missing or 
+// is already borrowed (e.g. by the system running the callback).
+fn fetch<'a, T: specs::SystemData<'a>>(res: &'a specs::Resources) -> LuaResult<T> {
+    let missing = || LuaError::RuntimeError("A resource the world needs is missing".into());
+    let borrowed = || LuaError::RuntimeError("A resource the world needs is already in use".into());
+
+    for id in T::reads() {
+        res.try_fetch_internal(id.0).ok_or_else(missing)?.try_borrow().map_err(|_| borrowed())?;
+    }
+    for id in T::writes() {
+        res.try_fetch_internal(id.0).ok_or_else(missing)?.try_borrow_mut().map_err(|_| borrowed())?;
+    }
+
+    Ok(T::fetch(res))
+}
+
+// Calls an entity's callback (the function, or coroutine, 'entry') with the world as userdata, which scripts are only
+// able to use until the function returns.
+pub fn with_world<'lua, R, F>(lua: &'lua Lua, res: &specs::Resources, entity: specs::Entity, entry: LuaValue<'lua>, f: F) -> LuaResult<R>
+where
+    F: FnOnce(AnyUserData) -> LuaResult<R>,
+{
+    // Every callback given the world gets the whole instruction budget (of the file it's from).
+    sandbox::enter(lua, &entry)?;
+
+    let result = lua.scope(|scope| {
+        let world = scope.create_userdata(LuaWorld { res: res as *const _, entity })?;
+
+        // Coroutines outlive the callback that resumed them, so they find the current world here instead.
+        lua.set_named_registry_value("world", world.clone())?;
+        let result = f(world);
+        lua.set_named_registry_value("world", LuaValue::Nil)?;
+
+        result
+    });
+
+    sandbox::reset_budget(lua)?;
+    result
+}
+
+fn add_timer<'lua>(lua: &'lua Lua, world: &LuaWorld, seconds: f32, interval: Option<f32>, func: LuaFunction<'lua>) -> LuaResult<()> {
+    let mut timers: specs::WriteStorage<comp::Timers> = world.fetch()?;
+
+    if timers.get(world.entity).is_none() {
+        timers.insert(world.entity, comp::Timers::new())
+            .map_err(|_| dead_entity(world.entity))?;
+    }
+
+    let func = lua.create_registry_value(func)?;
+    timers.get_mut(world.entity).unwrap().add(seconds, interval, func);
+
+    Ok(())
+}
+
+fn expired_world() -> LuaError {
+    LuaError::RuntimeError("The world is only usable during the callback it was given to".into())
+}
+
+// An entity's component, with its fields read and written (through its storage) by indexing it.
+pub struct ComponentRef {
+    // The (scoped) world the component was gotten from.
+    world: RegistryKey,
+    entity: specs::Entity,
+    lua_name: String,
+}
+
+impl UserData for ComponentRef {
+    fn add_methods(methods: &mut UserDataMethods<Self>) {
+        methods.add_meta_method(MetaMethod::Index, |lua, this, field: String| {
+            let world: AnyUserData = lua.registry_value(&this.world)?;
+            let world = world.borrow::<LuaWorld>().map_err(|_| expired_world())?;
+
+            Script::get_field(lua, &this.lua_name, this.entity, &field, world.res())
+        });
+
+        methods.add_meta_method(MetaMethod::NewIndex, |lua, this, (field, value): (String, LuaValue)| {
+            let world: AnyUserData = lua.registry_value(&this.world)?;
+            let world = world.borrow::<LuaWorld>().map_err(|_| expired_world())?;
+
+            Script::set_field(lua, &this.lua_name, this.entity, &field, value, world.res())
+        });
+    }
+}
+
+// The component's storage being unavailable is passed on, anything else means there's no such component.
+fn has_component_error(lua_name: &str, err: ScriptError) -> LuaError {
+    match err {
+        ScriptError::LuaError(err) => err,
+        _ => LuaError::RuntimeError(format!("There's no component named '{}'", lua_name)),
+    }
+}
+
+// For the world's functions which need an entity to have a component (it may have been destroyed).
+fn missing_component(entity: specs::Entity, lua_name: &str) -> LuaError {
+    LuaError::RuntimeError(format!("Entity {} has no '{}' component", entity.id(), lua_name))
+}
+
+fn dead_entity(entity: specs::Entity) -> LuaError {
+    LuaError::RuntimeError(format!("Entity {} is no longer alive", entity.id()))
+}
+
+// The entity's component (e.g. 'world:get(e, "sprite").image_index = 2'), or nil if it doesn't have one.
+fn get_component<'lua>(lua: &'lua Lua, (world, entity, lua_name): (AnyUserData<'lua>, LuaEntity, String)) -> LuaResult<Option<ComponentRef>> {
+    let has_component = {
+        let world = world.borrow::<LuaWorld>()?;
+        Script::has_component(&lua_name, entity.0, world.res()).map_err(|err| has_component_error(&lua_name, err))?
+    };
+
+    if has_component {
+        Ok(Some(ComponentRef {
+            world: lua.create_registry_value(world)?,
+            entity: entity.0,
+            lua_name,
+        }))
+    } else {
+        Ok(None)
+    }
+}
 
 pub type ScriptResult<T> = Result<T, ScriptError>;
 
+// How many bases a prefab is able to extend (through its bases), so cycles end in an error.
+const MAX_PREFAB_DEPTH: u32 = 16;
+
+/* NOTE:
+    Prefabs are entity tables registered with 'prefab(name, table)', which are able to extend another prefab by naming 
+    it in an 'extends' field. Fields are overridden deeply, so a prefab only needs to list what it changes, e.g.:
+        prefab("fast_bullet", { extends = "bullet", velocity = { x = 2.0 } })
+    Global entity tables are still found by name, as prefabs without a base.
+*/
+fn add_prefab_fn(lua: &Lua) {
+    lua.set_named_registry_value("prefabs", lua.create_table().unwrap()).unwrap();
+
+    lua.globals().set(
+        "prefab",
+        lua.create_function(|lua, (name, t): (String, Table)| {
+            let prefabs: Table = lua.named_registry_value("prefabs")?;
+            prefabs.set(name, t.clone())?;
+
+            Ok(t)
+        }).unwrap()
+    ).unwrap();
+}
+
+// Looks up the table a prefab was defined with (without its bases).
+fn find_prefab<'lua>(lua: &'lua Lua, lua_name: &str) -> LuaResult<Option<Table<'lua>>> {
+    let prefabs: Table = lua.named_registry_value("prefabs")?;
+
+    match prefabs.get::<_, Option<Table>>(lua_name)? {
+        Some(t) => Ok(Some(t)),
+        None => lua.globals().get::<_, Option<Table>>(lua_name),
+    }
+}
+
+// A new table with the fields of both tables, where tables in both are merged and other fields are overridden.
+fn merge_tables<'lua>(lua: &'lua Lua, base: Table<'lua>, overrides: Table<'lua>) -> LuaResult<Table<'lua>> {
+    let merged = lua.create_table()?;
+
+    for pair in base.pairs::<LuaValue, LuaValue>() {
+        let (k, v) = pair?;
+        merged.set(k, v)?;
+    }
+
+    for pair in overrides.pairs::<LuaValue, LuaValue>() {
+        let (k, v) = pair?;
+
+        let v = match (merged.get::<_, LuaValue>(k.clone())?, v) {
+            (LuaValue::Table(base), LuaValue::Table(overrides)) => LuaValue::Table(merge_tables(lua, base, overrides)?),
+            (_, v) => v,
+        };
+        merged.set(k, v)?;
+    }
+
+    Ok(merged)
+}
+
+impl Script {
+    // A prefab's entity table, with everything it extends merged in.
+    pub fn prefab(&self, lua_name: &str) -> ScriptResult<Table> {
+        self.resolve_prefab(lua_name, 0)
+    }
+
+    fn resolve_prefab(&self, lua_name: &str, depth: u32) -> ScriptResult<Table> {
+        if depth > MAX_PREFAB_DEPTH {
+            return Err(ScriptError::InvalidEntity(format!("'{}' extends too many prefabs (is there a cycle?)", lua_name)));
+        }
+
+        let ent_table = find_prefab(&self.lua, lua_name)?
+            .ok_or_else(|| ScriptError::InvalidEntity(lua_name.into()))?;
+
+        match ent_table.get::<_, Option<String>>("extends")? {
+            Some(base_name) => {
+                let base = self.resolve_prefab(&base_name, depth + 1)?;
+                let merged = merge_tables(&self.lua, base, ent_table)?;
+                merged.set("extends", LuaValue::Nil)?;
+
+                Ok(merged)
+            },
+            None => Ok(ent_table),
+        }
+    }
+}
+
+// Adds a component (from its Lua data) to an entity, once the world is free to be changed.
+fn add_component(world: &mut specs::World, lua_name: &str, data: RegistryKey, entity: specs::Entity) {
+    let mutex = world.read_resource::<res::Script>().0.clone().unwrap();
+    let script = mutex.lock().unwrap();
+
+    let result = script.registry_value::<LuaValue>(&data)
+        .map_err(ScriptError::from)
+        .and_then(|data| script.parse_component(lua_name, data, entity, world));
+
+    if let Err(err) = result {
+        error!("Couldn't add '{}' to entity {}: {:?}", lua_name, entity.id(), err);
+    }
+
+    script.remove_registry_value(data).unwrap();
+}
+
+// Builds a prefab spawned by a script, once the world is free to be changed.
+fn spawn_prefab(world: &mut specs::World, lua_name: &str, overrides: Option<RegistryKey>, entity: specs::Entity) {
+    let mutex = world.read_resource::<res::Script>().0.clone().unwrap();
+    let script = mutex.lock().unwrap();
+
+    // Destroyed (by a script) before it was ever built, deletions being merged first.
+    if world.is_alive(entity) {
+        let result = match overrides {
+            Some(ref key) => script.registry_value::<Table>(key)
+                .map_err(ScriptError::from)
+                .and_then(|overrides| script.insert_prefab(lua_name, Some(overrides), entity, world)),
+            None => script.insert_prefab(lua_name, None, entity, world),
+        };
+
+        if let Err(err) = result {
+            error!("Couldn't spawn '{}': {:?}", lua_name, err);
+            world.delete_entity(entity).ok();
+        }
+    }
+
+    if let Some(key) = overrides {
+        script.remove_registry_value(key).unwrap();
+    }
+}
+
 macro_rules! script {
     (components: [ $(($lua_names:expr) = $comp_names:ident: $comp_types:ty),* ],
     // For types implementing LuaCtor
@@ -70,6 +375,8 @@ macro_rules! script {
                 $(
                 methods.add_method($func_names, $funcs);
                 )*
+
+                methods.add_function("get", get_component);
             }
         }
 
@@ -79,47 +386,175 @@ macro_rules! script {
 
         impl Script {
             pub fn new() -> Script {
+                Script::with_config(&ScriptConfig::default())
+            }
+
+            pub fn with_config(config: &ScriptConfig) -> Script {
+                // The debug library is only needed to set the limit hook (see sandbox::apply), which every Script has
+                // as files with limits of their own are able to be loaded into any of them. Safe as long as scripts 
+                // never reach the library (which is able to break the state's invariants): 'sandbox::apply' keeps 
+                // the functions it needs aside and removes the library before any script runs.
+                let lua = unsafe { Lua::new_with_debug() };
+
                 let script = Script {
-                    lua: Lua::new()
+                    lua
                 };
 
                 // Register all the type constructors.
                 $(<$types as types::LuaCtor>::add_ctors(&script.lua);)*
 
+                add_prefab_fn(&script.lua);
+                coroutine::add_wait_fns(&script.lua).unwrap();
+                state::add_string_fn(&script.lua).unwrap();
+
+                // Reloading and modules need Lua's own 'load' (whatever scripts do with it, even if sandboxed).
+                let load: LuaFunction = script.lua.globals().get("load").unwrap();
+                script.lua.set_named_registry_value("load", load).unwrap();
+
+                sandbox::apply(&script.lua, config).unwrap();
+                module::add_require_fn(&script.lua, &config.module_root).unwrap();
+
                 script
             }
 
+            // Registers all the components scripts are able to create (in case no system uses them).
+            pub fn register_components(world: &mut specs::World) {
+                $(world.register::<$comp_types>();)*
+                world.register::<comp::Prefab>();
+                world.register::<comp::Timers>();
+            }
+
             pub fn load_file<'a>(&self, path: &str) -> ScriptResult<()> {
                 let mut file = File::open(path)?;
                 
                 let mut contents = String::new();
                 file.read_to_string(&mut contents)?;
                 
+                sandbox::reset_budget(&self.lua)?;
                 Ok(self.lua.exec::<()>(&contents, None)?)
             }
 
-            fn parse_component<'a>(&self, lua_name: &str, data: LuaValue, eb: specs::EntityBuilder<'a>) -> ScriptResult<specs::EntityBuilder<'a>> {
+            // Loads a file which runs with a config of its own rather than the Script's, e.g. a mod sandboxed with 
+            // limits of its own (see 'sandbox::add_source'). Reloading it keeps the config.
+            pub fn load_file_with_config(&self, path: &str, config: &ScriptConfig) -> ScriptResult<()> {
+                sandbox::add_source(&self.lua, path, config)?;
+                self.reload_file(path)
+            }
+
+            fn parse_component(&self, lua_name: &str, data: LuaValue, entity: specs::Entity, world: &specs::World) -> ScriptResult<()> {
                 match lua_name {
                     $($lua_names => {
-                        Ok(eb.with(
+                        world.write_storage::<$comp_types>().insert(
+                            entity,
                             <$comp_types as ComponentParser>::parse(data, &self.lua)?
-                        ))
+                        ).map_err(|_| ScriptError::InvalidEntity(format!("Entity {} is no longer alive", entity.id())))?;
+                        Ok(())
+                    }),*
+                    _ => Err(ScriptError::InvalidComponent(lua_name.into())),
+                }
+            }
+
+            // Whether scripts are able to create the component.
+            pub fn is_component(lua_name: &str) -> bool {
+                match lua_name {
+                    $($lua_names)|* => true,
+                    _ => false,
+                }
+            }
+
+            pub fn has_component(lua_name: &str, entity: specs::Entity, res: &specs::Resources) -> ScriptResult<bool> {
+                match lua_name {
+                    $($lua_names => {
+                        let storage: specs::ReadStorage<$comp_types> = fetch(res)?;
+                        Ok(storage.get(entity).is_some())
+                    }),*
+                    _ => Err(ScriptError::InvalidComponent(lua_name.into())),
+                }
+            }
+
+            // Reads a field of an entity's component (see ComponentFields).
+            pub fn get_field<'lua>(lua: &'lua Lua, lua_name: &str, entity: specs::Entity, field: &str, res: &specs::Resources) -> LuaResult<LuaValue<'lua>> {
+                match lua_name {
+                    $($lua_names => {
+                        let storage: specs::ReadStorage<$comp_types> = fetch(res)?;
+                        let comp = storage.get(entity)
+                            .ok_or_else(|| LuaError::RuntimeError(format!("Entity {} has no '{}' component", entity.id(), lua_name)))?;
+
+                        comp.get_field(field, lua)
+                    }),*
+                    _ => Err(LuaError::RuntimeError(format!("There's no component named '{}'", lua_name))),
+                }
+            }
+
+            // Writes a field of an entity's component, flagging the component as modified.
+            pub fn set_field<'lua>(lua: &'lua Lua, lua_name: &str, entity: specs::Entity, field: &str, value: LuaValue<'lua>, res: &specs::Resources) -> LuaResult<()> {
+                match lua_name {
+                    $($lua_names => {
+                        let mut storage: specs::WriteStorage<$comp_types> = fetch(res)?;
+                        let comp = storage.get_mut(entity)
+                            .ok_or_else(|| LuaError::RuntimeError(format!("Entity {} has no '{}' component", entity.id(), lua_name)))?;
+
+                        comp.set_field(field, value, lua)
+                    }),*
+                    _ => Err(LuaError::RuntimeError(format!("There's no component named '{}'", lua_name))),
+                }
+            }
+
+            pub fn remove_component(lua_name: &str, entity: specs::Entity, world: &specs::World) -> ScriptResult<()> {
+                match lua_name {
+                    $($lua_names => {
+                        world.write_storage::<$comp_types>().remove(entity);
+                        Ok(())
                     }),*
                     _ => Err(ScriptError::InvalidComponent(lua_name.into())),
                 }
             }
 
-            pub fn parse_entity(&self, lua_name: &str, mut eb: specs::EntityBuilder) -> ScriptResult<specs::Entity> {
-                let globals = self.lua.globals();
-                let ent_table: Table = globals.get(lua_name.clone())
-                    .map_err(|x| ScriptError::InvalidEntity(lua_name.into()))?;
+            pub fn parse_entity(&self, lua_name: &str, eb: specs::EntityBuilder) -> ScriptResult<specs::Entity> {
+                self.insert_prefab(lua_name, None, eb.entity, eb.world)?;
+                Ok(eb.build())
+            }
+
+            // Gives an (existing) entity the components of a prefab, with any overrides merged in.
+            pub fn insert_prefab(&self, lua_name: &str, overrides: Option<Table>, entity: specs::Entity, world: &specs::World) -> ScriptResult<()> {
+                let mut ent_table = self.prefab(lua_name)?;
+                if let Some(overrides) = overrides {
+                    ent_table = merge_tables(&self.lua, ent_table, overrides)?;
+                }
 
                 for comp_pair in ent_table.pairs::<String, _>() {
                     let (comp_lua_name, comp_data) = comp_pair?;
 
-                    eb = self.parse_component(&comp_lua_name, comp_data, eb)?;
+                    self.parse_component(&comp_lua_name, comp_data, entity, world)?;
                 }
-                Ok(eb.build())
+
+                world.write_storage().insert(entity, comp::Prefab::new(lua_name))
+                    .map_err(|_| ScriptError::InvalidEntity(format!("Entity {} is no longer alive", entity.id())))?;
+                Ok(())
+            }
+
+            // Binds an existing entity's Lua callbacks (e.g. 'on_tick', 'on_collide') from the entity table it was 
+            // spawned from, without touching its other components.
+            pub fn bind_callbacks(&self, lua_name: &str, entity: specs::Entity, world: &specs::World) -> ScriptResult<()> {
+                let ent_table = self.prefab(lua_name)?;
+
+                if let Some(data) = ent_table.get::<_, Option<LuaValue>>("script")? {
+                    let behavior = <comp::ScriptBehavior as ComponentParser>::parse(data, &self.lua)?;
+                    world.write_storage().insert(entity, behavior)
+                        .map_err(|_| ScriptError::InvalidEntity(format!("Entity {} is no longer alive", entity.id())))?;
+                }
+
+                if let Some(data) = ent_table.get::<_, Option<LuaValue>>("collider")? {
+                    let mut colls = world.write_storage::<comp::Collider>();
+
+                    if let Some(coll) = colls.get_mut(entity) {
+                        let parsed = <comp::Collider as ComponentParser>::parse(data, &self.lua)?;
+                        coll.on_collide = parsed.on_collide;
+                        coll.pre_solve = parsed.pre_solve;
+                    }
+                }
+
+                Ok(())
             }
         }
 
@@ -136,6 +571,8 @@ macro_rules! script {
 script!(
     components: [
         ("transform") = transform: comp::Transform,
+        ("local_transform") = local_transform: comp::LocalTransform,
+        ("parent")    = parent: comp::Parent,
         ("velocity")  = velocity: comp::Velocity,
         ("collider")  = collider: comp::Collider,
         ("sprite")    = sprite: comp::Sprite,
@@ -148,36 +585,506 @@ script!(
     ],
     functions: [
         ("position") = |_, this: &LuaWorld, entity: LuaEntity| -> LuaResult<types::Vector3f> {
-            unsafe {
-                let res = &*this.0;
-                let storage: specs::ReadStorage<comp::Transform> = specs::SystemData::fetch(&res);
-                Ok(types::Vector3f(storage.get(entity.0).unwrap().pos))
-            }
+            let storage: specs::ReadStorage<comp::Transform> = this.fetch()?;
+            let comp = storage.get(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+            Ok(types::Vector3f(comp.pos))
         },
         ("move") = |_, this: &LuaWorld, (entity, vec): (LuaEntity, types::Vector3f)| {
-            unsafe {
-                let res = &*this.0;
-                let mut storage: specs::WriteStorage<comp::Transform> = specs::SystemData::fetch(&res);
-                let comp = storage.get_mut(entity.0).unwrap();
+            let (mut storage, mut local): (specs::WriteStorage<comp::Transform>, specs::WriteStorage<comp::LocalTransform>) = this.fetch()?;
+            
+            // Children move relative to their parent.
+            if let Some(comp) = local.get_mut(entity.0) {
+                comp.pos += vec.0;
+            } else {
+                let comp = storage.get_mut(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
                 comp.pos += vec.0;
             }
             Ok(())
         },
-        ("set_velocity") = |_, this: &LuaWorld, (entity, vec): (LuaEntity, types::Vector3f)| {
-            unsafe {
-                let res = &*this.0;
-                let mut storage: specs::WriteStorage<comp::Velocity> = specs::SystemData::fetch(&res);
-                let comp = storage.get_mut(entity.0).unwrap();
-                comp.pos = vec.0;
+        ("rotation") = |_, this: &LuaWorld, entity: LuaEntity| -> LuaResult<f32> {
+            let storage: specs::ReadStorage<comp::Transform> = this.fetch()?;
+            let comp = storage.get(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+            Ok(comp.rotation)
+        },
+        ("set_rotation") = |_, this: &LuaWorld, (entity, rotation): (LuaEntity, f32)| {
+            let mut storage: specs::WriteStorage<comp::Transform> = this.fetch()?;
+            let comp = storage.get_mut(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+            comp.rotation = rotation;
+            Ok(())
+        },
+        ("scale") = |_, this: &LuaWorld, entity: LuaEntity| -> LuaResult<types::Vector2f> {
+            let storage: specs::ReadStorage<comp::Transform> = this.fetch()?;
+            let comp = storage.get(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+            Ok(types::Vector2f(comp.scale))
+        },
+        ("set_scale") = |_, this: &LuaWorld, (entity, vec): (LuaEntity, types::Vector2f)| {
+            let mut storage: specs::WriteStorage<comp::Transform> = this.fetch()?;
+            let comp = storage.get_mut(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+            comp.scale = vec.0;
+            Ok(())
+        },
+        ("set_parent") = |_, this: &LuaWorld, (entity, parent, cascade): (LuaEntity, Option<LuaEntity>, Option<bool>)| {
+            let (tran, mut local, mut par): (specs::ReadStorage<comp::Transform>, specs::WriteStorage<comp::LocalTransform>, specs::WriteStorage<comp::Parent>) = this.fetch()?;
+
+            match parent {
+                Some(parent) => {
+                    // Keep the child where it currently is, relative to its new parent.
+                    let child_tran = tran.get(entity.0).ok_or_else(|| missing_component(entity.0, "transform"))?;
+                    let parent_tran = tran.get(parent.0).ok_or_else(|| missing_component(parent.0, "transform"))?;
+                    let relative = comp::LocalTransform::relative_to(child_tran, parent_tran);
+
+                    local.insert(entity.0, relative).map_err(|_| dead_entity(entity.0))?;
+                    par.insert(entity.0, comp::Parent::new(parent.0, cascade.unwrap_or(false))).map_err(|_| dead_entity(entity.0))?;
+                },
+                None => {
+                    local.remove(entity.0);
+                    par.remove(entity.0);
+                }
             }
             Ok(())
         },
-        ("is_pressed") = |_, this: &LuaWorld, input_index: usize| -> LuaResult<bool> {
-            unsafe {
-                let res = &*this.0;
-                let input_list: shred::Fetch<res::input::InputList> = res.fetch();
-                Ok(input_list.inputs[input_index].map(|x| x == res::input::InputState::Pressed).unwrap_or(false))
+        ("spawn") = |lua, this: &LuaWorld, (lua_name, overrides): (String, Option<Table>)| -> LuaResult<LuaEntity> {
+            if find_prefab(lua, &lua_name)?.is_none() {
+                return Err(LuaError::RuntimeError(format!("There's no prefab named '{}'", lua_name)));
+            }
+
+            // Kept in the registry until the entity is built.
+            let overrides = match overrides {
+                Some(t) => Some(lua.create_registry_value(t)?),
+                None => None,
+            };
+
+            let (ents, lazy): (specs::Entities, specs::Read<specs::LazyUpdate>) = this.fetch()?;
+
+            // The entity exists right away (so it can be returned), its components are added after the tick.
+            let entity = ents.create();
+            lazy.exec_mut(move |world| spawn_prefab(world, &lua_name, overrides, entity));
+
+            Ok(LuaEntity(entity))
+        },
+        /* NOTE:
+            Destroying an entity or changing its components is deferred until the end of the tick (so nothing being 
+            iterated over changes), which means 'has_component' and 'is_alive' don't see those changes until then.
+        */
+        ("destroy") = |_, this: &LuaWorld, entity: LuaEntity| {
+            let ents: specs::Entities = this.fetch()?;
+
+            // Destroying an entity twice does nothing.
+            ents.delete(entity.0).ok();
+            Ok(())
+        },
+        ("is_alive") = |_, this: &LuaWorld, entity: LuaEntity| -> LuaResult<bool> {
+            let ents: specs::Entities = this.fetch()?;
+            Ok(ents.is_alive(entity.0))
+        },
+        ("add_component") = |lua, this: &LuaWorld, (entity, lua_name, data): (LuaEntity, String, LuaValue)| {
+            if !Script::is_component(&lua_name) {
+                return Err(LuaError::RuntimeError(format!("There's no component named '{}'", lua_name)));
+            }
+
+            // Kept in the registry until the component is added.
+            let data = lua.create_registry_value(data)?;
+            let entity = entity.0;
+
+            let lazy: specs::Read<specs::LazyUpdate> = this.fetch()?;
+            lazy.exec_mut(move |world| add_component(world, &lua_name, data, entity));
+            Ok(())
+        },
+        ("remove_component") = |_, this: &LuaWorld, (entity, lua_name): (LuaEntity, String)| {
+            if !Script::is_component(&lua_name) {
+                return Err(LuaError::RuntimeError(format!("There's no component named '{}'", lua_name)));
             }
+
+            let entity = entity.0;
+
+            let lazy: specs::Read<specs::LazyUpdate> = this.fetch()?;
+            lazy.exec(move |world| Script::remove_component(&lua_name, entity, world).unwrap());
+            Ok(())
+        },
+        ("has_component") = |_, this: &LuaWorld, (entity, lua_name): (LuaEntity, String)| -> LuaResult<bool> {
+            Script::has_component(&lua_name, entity.0, this.res()).map_err(|err| has_component_error(&lua_name, err))
+        },
+        ("set_velocity") = |_, this: &LuaWorld, (entity, vec): (LuaEntity, types::Vector3f)| {
+            let mut storage: specs::WriteStorage<comp::Velocity> = this.fetch()?;
+            let comp = storage.get_mut(entity.0).ok_or_else(|| missing_component(entity.0, "velocity"))?;
+            comp.pos = vec.0;
+            Ok(())
+        },
+        ("drop_through") = |_, this: &LuaWorld, entity: LuaEntity| {
+            let mut storage: specs::WriteStorage<comp::Collider> = this.fetch()?;
+            let comp = storage.get_mut(entity.0).ok_or_else(|| missing_component(entity.0, "collider"))?;
+            comp.drop_through = true;
+            Ok(())
+        },
+        ("time_scale") = |_, this: &LuaWorld, _: ()| -> LuaResult<f32> {
+            let time_scale: specs::Read<res::TimeScale> = this.fetch()?;
+            Ok(time_scale.0)
+        },
+        ("set_time_scale") = |_, this: &LuaWorld, scale: f32| {
+            let mut time_scale: specs::Write<res::TimeScale> = this.fetch()?;
+            time_scale.0 = scale;
+            Ok(())
+        },
+        /* NOTE:
+            Timers belong to the entity whose callback added them, so they're cancelled along with it. A repeating
+            timer's function is also able to stop it by returning false.
+        */
+        ("after") = |lua, this: &LuaWorld, (seconds, func): (f32, LuaFunction)| {
+            add_timer(lua, this, seconds, None, func)
+        },
+        ("every") = |lua, this: &LuaWorld, (seconds, func): (f32, LuaFunction)| {
+            if seconds <= 0.0 {
+                return Err(LuaError::RuntimeError("A repeating timer needs a positive interval".into()));
+            }
+
+            add_timer(lua, this, seconds, Some(seconds), func)
+        },
+        ("is_pressed") = |_, this: &LuaWorld, input_index: usize| -> LuaResult<bool> {
+            let input_list: specs::Read<res::input::InputList> = this.fetch()?;
+            let input = input_list.inputs.get(input_index)
+                .ok_or_else(|| LuaError::RuntimeError(format!("There's no input {}", input_index)))?;
+
+            Ok(input.map(|x| x == res::input::InputState::Pressed).unwrap_or(false))
         }
     ]
-);
\ No newline at end of file
+);
+
+// Builds a game (with the engine's core systems) which has already run a script, for tests.
+#[cfg(test)]
+pub fn scripted_game<'a>(src: &str) -> (::game::Game<'a>, Arc<Mutex<Script>>) {
+    scripted_game_with(::game::GameBuilder::new(1.0/60.0).with_core_systems(), Script::new(), src)
+}
+
+// Like 'scripted_game', for tests which need their own systems, resources or script config.
+#[cfg(test)]
+pub fn scripted_game_with<'a>(builder: ::game::GameBuilder<'a>, script: Script, src: &str) -> (::game::Game<'a>, Arc<Mutex<Script>>) {
+    script.lua.exec::<()>(src, None).unwrap();
+
+    let mutex = Arc::new(Mutex::new(script));
+    let mut game = builder
+        .with_resource(res::Script(Some(mutex.clone())))
+        .build().unwrap();
+
+    Script::register_components(&mut game.world);
+    (game, mutex)
+}
+
+// Spawns a prefab of the game's script, for tests.
+#[cfg(test)]
+pub fn spawn(game: &mut ::game::Game, lua_name: &str) -> specs::Entity {
+    let mutex = game.world.read_resource::<res::Script>().0.clone().unwrap();
+    let script = mutex.lock().unwrap();
+    script.parse_entity(lua_name, game.world.create_entity()).unwrap()
+}
+
+#[test]
+fn prefab_inheritance() {
+    let script = Script::new();
+    script.lua.exec::<()>(r#"
+        prefab("base", {
+            transform = { position = { x = 1.0, y = 2.0, z = 0.0 } },
+            velocity = { x = 0.0, y = 0.0, z = 0.0 },
+        })
+        prefab("derived", {
+            extends = "base",
+            transform = { position = { x = 5.0 } },
+        })
+        prefab("cycle", { extends = "cycle" })
+    "#, None).unwrap();
+
+    let mut world = specs::World::new();
+    Script::register_components(&mut world);
+
+    let ent = script.parse_entity("derived", world.create_entity()).unwrap();
+    assert_eq!(world.read_storage::<comp::Transform>().get(ent).unwrap().pos, Vector3::new(5.0, 2.0, 0.0));
+    assert!(world.read_storage::<comp::Velocity>().get(ent).is_some());
+
+    assert!(script.prefab("cycle").is_err());
+}
+
+#[test]
+fn spawn_prefab_from_script() {
+    use specs::Join;
+
+    let (mut game, _) = scripted_game(r#"
+        prefab("bullet", {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+        })
+        spawner = {
+            script = {
+                on_tick = function(world, this, dt)
+                    world:spawn("bullet", { transform = { position = { x = 3.0 } } })
+                end
+            },
+        }
+    "#);
+    spawn(&mut game, "spawner");
+
+    game.tick();
+
+    let tran = game.world.read_storage::<comp::Transform>();
+    let positions: Vec<_> = (&tran).join().map(|x| x.pos).collect();
+    assert_eq!(positions, vec![Vector3::new(3.0, 0.0, 0.0)]);
+}
+
+#[test]
+fn bindings_on_destroyed_entities() {
+    let (mut game, mutex) = scripted_game(r#"
+        prefab("box", {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            velocity = { x = 0.0, y = 0.0, z = 0.0 },
+            collider = {
+                shape_type = "aabb",
+                shape = { min_x = 0.0, min_y = 0.0, min_z = 0.0, max_x = 0.1, max_y = 0.1, max_z = 0.1 },
+            },
+        })
+        tester = {
+            script = {
+                on_tick = function(world, this, dt)
+                    if not dead then
+                        dead = world:spawn("box")
+                        world:destroy(dead)
+                        return
+                    end
+
+                    local v3, v2 = vec3f(1.0, 0.0, 0.0), vec2f(1.0, 1.0)
+                    failed = {}
+                    for name, call in pairs({
+                        position = function() return world:position(dead) end,
+                        move = function() world:move(dead, v3) end,
+                        rotation = function() return world:rotation(dead) end,
+                        set_rotation = function() world:set_rotation(dead, 1.0) end,
+                        scale = function() return world:scale(dead) end,
+                        set_scale = function() world:set_scale(dead, v2) end,
+                        set_parent = function() world:set_parent(dead, this) end,
+                        set_velocity = function() world:set_velocity(dead, v3) end,
+                        drop_through = function() world:drop_through(dead) end,
+                        is_pressed = function() return world:is_pressed(10000) end,
+                    }) do
+                        failed[name] = not pcall(call)
+                    end
+                end
+            },
+        }
+    "#);
+    spawn(&mut game, "tester");
+
+    game.tick();
+    game.tick();
+
+    let script = mutex.lock().unwrap();
+    let failed = script.globals().get::<_, Table>("failed").unwrap();
+    for name in &["position", "move", "rotation", "set_rotation", "scale", "set_scale", "set_parent", "set_velocity", 
+        "drop_through", "is_pressed"] {
+        assert!(failed.get::<_, bool>(*name).unwrap(), "'{}' didn't fail", name);
+    }
+}
+
+#[test]
+fn spawn_then_destroy() {
+    use specs::Join;
+
+    let (mut game, mutex) = scripted_game(r#"
+        prefab("bullet", {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+        })
+        spawner = {
+            script = {
+                on_tick = function(world, this, dt)
+                    local e = world:spawn("bullet")
+                    world:destroy(e)
+                    spawned = e
+                end
+            },
+        }
+    "#);
+    spawn(&mut game, "spawner");
+
+    game.tick();
+    game.tick();
+
+    // Only the spawner is left, the bullets never got any components.
+    assert_eq!((&*game.world.entities()).join().count(), 1);
+    assert_eq!((&game.world.read_storage::<comp::Transform>()).join().count(), 0);
+    assert_eq!((&game.world.read_storage::<comp::Prefab>()).join().count(), 1);
+
+    let script = mutex.lock().unwrap();
+    let spawned = script.globals().get::<_, LuaEntity>("spawned").unwrap();
+    assert!(!game.world.is_alive(spawned.0));
+}
+
+#[test]
+fn entity_lifecycle() {
+    let (mut game, mutex) = scripted_game(r#"
+        prefab("box", {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            collider = {
+                shape_type = "aabb",
+                shape = { min_x = 0.0, min_y = 0.0, min_z = 0.0, max_x = 0.1, max_y = 0.1, max_z = 0.1 },
+                sweep = false,
+            },
+        })
+        step = 0
+        manager = {
+            script = {
+                on_tick = function(world, this, dt)
+                    step = step + 1
+
+                    if step == 1 then
+                        target = world:spawn("box")
+                        world:spawn("box")
+                    elseif step == 2 then
+                        world:add_component(target, "velocity", { x = 1.0, y = 0.0, z = 0.0 })
+                        world:remove_component(target, "collider")
+                    elseif step == 3 then
+                        had_velocity = world:has_component(target, "velocity")
+                        had_collider = world:has_component(target, "collider")
+                        world:destroy(target)
+                    elseif step == 4 then
+                        was_alive = world:is_alive(target)
+                    end
+                end
+            },
+        }
+    "#);
+    spawn(&mut game, "manager");
+
+    for _ in 0..5 {
+        game.tick();
+    }
+
+    let script = mutex.lock().unwrap();
+    let globals = script.globals();
+    assert!(globals.get::<_, bool>("had_velocity").unwrap());
+    assert!(!globals.get::<_, bool>("had_collider").unwrap());
+    assert!(!globals.get::<_, bool>("was_alive").unwrap());
+}
+
+#[test]
+fn component_proxies() {
+    let (mut game, mutex) = scripted_game(r#"
+        thing = {
+            transform = { position = { x = 1.0, y = 2.0, z = 0.0 } },
+            sprite = {
+                bounds = { min_x = 0.0, min_y = 0.0, max_x = 1.0, max_y = 1.0 },
+                uv = { min_x = 0.0, min_y = 0.0, max_x = 1.0, max_y = 1.0 },
+                image_index = 0,
+            },
+            script = {
+                on_tick = function(world, this, dt)
+                    world:get(this, "sprite").image_index = 2
+                    local tran = world:get(this, "transform")
+                    tran.position = { x = tran.position.x + 1.0, y = 2.0, z = 0.0 }
+                    no_velocity = world:get(this, "velocity") == nil
+                    bad_field = not pcall(function() world:get(this, "sprite").colour = 1 end)
+                end
+            },
+        }
+    "#);
+    let ent = spawn(&mut game, "thing");
+
+    let mut mod_read = game.world.write_storage::<comp::Sprite>().track_modified();
+    game.tick();
+
+    let mut modified = specs::BitSet::new();
+    game.world.write_storage::<comp::Sprite>().populate_modified(&mut mod_read, &mut modified);
+    assert!(modified.contains(ent.id()));
+
+    assert_eq!(game.world.read_storage::<comp::Sprite>().get(ent).unwrap().image_index, 2);
+    assert_eq!(game.world.read_storage::<comp::Transform>().get(ent).unwrap().pos, Vector3::new(2.0, 2.0, 0.0));
+
+    let script = mutex.lock().unwrap();
+    assert!(script.globals().get::<_, bool>("no_velocity").unwrap());
+    assert!(script.globals().get::<_, bool>("bad_field").unwrap());
+}
+
+#[test]
+fn world_expires_after_callback() {
+    let (mut game, mutex) = scripted_game(r#"
+        hoarder = {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            script = {
+                on_tick = function(world, this, dt)
+                    if kept_world == nil then
+                        kept_world = world
+                        kept_transform = world:get(this, "transform")
+                    else
+                        world_usable = pcall(function() return kept_world:is_alive(this) end)
+                        transform_usable = pcall(function() return kept_transform.position end)
+                        fresh_usable = pcall(function() return world:get(this, "transform").position end)
+                    end
+                end
+            },
+        }
+    "#);
+    spawn(&mut game, "hoarder");
+
+    game.tick();
+    game.tick();
+
+    let script = mutex.lock().unwrap();
+    assert!(!script.globals().get::<_, bool>("world_usable").unwrap());
+    assert!(!script.globals().get::<_, bool>("transform_usable").unwrap());
+    assert!(script.globals().get::<_, bool>("fresh_usable").unwrap());
+}
+
+#[test]
+fn borrowed_resources_raise_errors() {
+    let script = Script::new();
+    let mut world = specs::World::new();
+    Script::register_components(&mut world);
+    script.lua.exec::<()>(r#"
+        crate = { transform = { position = { x = 0.0, y = 0.0, z = 0.0 } } }
+    "#, None).unwrap();
+    let e = script.parse_entity("crate", world.create_entity()).unwrap();
+
+    // The storage is borrowed (like it would be by a system running the callback), so the binding can't fetch it.
+    let _tran = world.write_storage::<comp::Transform>();
+    let result = with_world(&script, &world.res, e, LuaValue::Nil, |world| {
+        script.lua.load("local world, this = ...; world:position(this)", None)?.call::<_, ()>((world, LuaEntity(e)))
+    });
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn failing_callbacks_are_disabled() {
+    use ::game::GameBuilder;
+
+    let builder = GameBuilder::new(1.0/60.0).with_max_script_failures(Some(2));
+    let (mut game, mutex) = scripted_game_with(builder, Script::new(), r#"
+        ticks = 0
+        broken = {
+            script = {
+                on_tick = function(world, this, dt)
+                    error("broken on purpose")
+                end
+            },
+        }
+        working = {
+            script = {
+                on_tick = function(world, this, dt)
+                    ticks = ticks + 1
+                end
+            },
+        }
+    "#);
+    let broken = spawn(&mut game, "broken");
+    spawn(&mut game, "working");
+
+    // Each tick only keeps its own errors.
+    let mut errors = Vec::new();
+    for _ in 0..4 {
+        game.tick();
+        errors.extend(game.world.read_resource::<res::ScriptErrors>().errors.iter().cloned());
+    }
+
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[0].entity, broken);
+    assert_eq!(errors[0].callback, "on_tick");
+    assert!(errors[0].message.contains("broken on purpose"));
+    assert!(errors[0].message.contains("traceback"));
+
+    assert!(game.world.read_storage::<comp::ScriptBehavior>().get(broken).unwrap().on_tick.is_none());
+    assert_eq!(mutex.lock().unwrap().globals().get::<_, u32>("ticks").unwrap(), 4);
+}
@@ -0,0 +1,177 @@
+use script::ScriptResult;
+
+use std::io::prelude::*;
+use std::fs::File;
+use std::path::Path;
+
+use rlua::{Lua, Table, Value as LuaValue, Function as LuaFunction, Error as LuaError, Result as LuaResult};
+
+// Where 'require' looks for modules, unless the script's config says otherwise.
+pub const DEFAULT_MODULE_ROOT: &str = "assets/scripts";
+
+/* NOTE:
+    Lua's own 'require' (and 'package') search wherever the process happens to be able to read, so it's replaced by
+    one which only loads '.lua' files under the module root: 'require("enemies.slime")' runs
+    '<root>/enemies/slime.lua' once, and returns what it returned (or true) from then on. Modules are cached for the
+    lifetime of the script, so reloading a file doesn't run the modules it requires again (unless a module itself 
+    changed, see 'ScriptWatcher', which empties the cache).
+*/
+pub fn add_require_fn(lua: &Lua, root: &str) -> LuaResult<()> {
+    lua.set_named_registry_value("module_root", root)?;
+    lua.set_named_registry_value("modules", lua.create_table()?)?;
+    // The modules being loaded, in the order they were required (to report cycles).
+    lua.set_named_registry_value("module_stack", lua.create_table()?)?;
+    // The files of the modules loaded so far, so they're able to be watched.
+    lua.set_named_registry_value("module_paths", lua.create_table()?)?;
+
+    lua.globals().set("require", lua.create_function(require)?)
+}
+
+fn require<'lua>(lua: &'lua Lua, name: String) -> LuaResult<LuaValue<'lua>> {
+    let modules: Table = lua.named_registry_value("modules")?;
+    if let Some(module) = modules.get::<_, Option<LuaValue>>(name.as_str())? {
+        return Ok(module);
+    }
+
+    let stack: Table = lua.named_registry_value("module_stack")?;
+    let loading: Vec<String> = stack.clone().sequence_values().collect::<LuaResult<_>>()?;
+
+    if loading.contains(&name) {
+        let cycle: Vec<&str> = loading.iter()
+            .skip_while(|x| **x != name)
+            .map(|x| x.as_str())
+            .chain(Some(name.as_str()))
+            .collect();
+
+        return Err(LuaError::RuntimeError(format!("Module '{}' requires itself ({})", name, cycle.join(" -> "))));
+    }
+
+    let (chunk, path) = load_module(lua, &name)?;
+
+    stack.set(loading.len() + 1, name.as_str())?;
+    let result = chunk.call::<_, LuaValue>(name.as_str());
+    stack.set(loading.len() + 1, LuaValue::Nil)?;
+
+    let module = match result? {
+        LuaValue::Nil => LuaValue::Boolean(true),
+        module => module,
+    };
+    modules.set(name.as_str(), module.clone())?;
+    lua.named_registry_value::<Table>("module_paths")?.set(name.as_str(), path)?;
+
+    Ok(module)
+}
+
+// The module's chunk, and the file it was loaded from.
+fn load_module<'lua>(lua: &'lua Lua, name: &str) -> LuaResult<(LuaFunction<'lua>, String)> {
+    // Only plain names, so modules can't be loaded from outside the root (e.g. with "..").
+    let valid = !name.is_empty() && name.split('.').all(|part| {
+        !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+    });
+
+    if !valid {
+        return Err(LuaError::RuntimeError(format!("'{}' isn't a valid module name", name)));
+    }
+
+    let root: String = lua.named_registry_value("module_root")?;
+    let path = Path::new(&root).join(name.replace('.', "/") + ".lua");
+    let path = path.to_string_lossy().into_owned();
+
+    let mut contents = String::new();
+    File::open(&path)
+        .and_then(|mut file| file.read_to_string(&mut contents))
+        .map_err(|err| LuaError::RuntimeError(format!("Couldn't load module '{}' from '{}': {}", name, path, err)))?;
+
+    // Lua's own 'load' (see 'Script::with_config') checks the chunk is text, not bytecode.
+    let load: LuaFunction = lua.named_registry_value("load")?;
+    let (chunk, err): (Option<LuaFunction>, Option<String>) = load.call((contents, format!("@{}", path), "t"))?;
+
+    let chunk = chunk.ok_or_else(|| LuaError::SyntaxError {
+        message: err.unwrap_or_default(),
+        incomplete_input: false,
+    })?;
+
+    Ok((chunk, path))
+}
+
+// The files of every module loaded so far.
+pub fn module_paths(lua: &Lua) -> LuaResult<Vec<String>> {
+    let paths: Table = lua.named_registry_value("module_paths")?;
+    paths.pairs::<String, String>().map(|pair| pair.map(|(_, path)| path)).collect()
+}
+
+// Forgets the loaded modules, so they're run again the next time they're required.
+pub fn clear_modules(lua: &Lua) -> LuaResult<()> {
+    lua.set_named_registry_value("modules", lua.create_table()?)
+}
+
+/* NOTE:
+    A manifest lists the script files a game loads, one per line and in order, relative to the manifest itself. Blank
+    lines and lines starting with '#' are skipped.
+*/
+pub fn read_manifest(path: &str) -> ScriptResult<Vec<String>> {
+    let mut contents = String::new();
+    File::open(path)?.read_to_string(&mut contents)?;
+
+    let dir = Path::new(path).parent().unwrap_or(Path::new(""));
+
+    Ok(contents.lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line).to_string_lossy().into_owned())
+        .collect())
+}
+
+#[test]
+fn require_modules() {
+    use ::script::{Script, ScriptConfig};
+    use std::env;
+    use std::fs;
+
+    let root = env::temp_dir().join(format!("tally_ho_module_test_{}", ::std::process::id()));
+    fs::create_dir_all(root.join("enemies")).unwrap();
+
+    let files: &[(&str, &[u8])] = &[
+        ("enemies/slime.lua", br#"
+            loads = (loads or 0) + 1
+            return { speed = require("enemies.base").speed * 2 }
+        "#),
+        ("enemies/base.lua", b"return { speed = 1.5 }"),
+        ("cycle_a.lua", br#"require("cycle_b")"#),
+        ("cycle_b.lua", br#"require("cycle_a")"#),
+        ("manifest.txt", b"# Loaded in order\nenemies/base.lua\n\nenemies/slime.lua\n"),
+    ];
+
+    for &(name, contents) in files {
+        File::create(root.join(name)).unwrap().write_all(contents).unwrap();
+    }
+
+    let script = Script::with_config(&ScriptConfig {
+        module_root: root.to_str().unwrap().into(),
+        ..ScriptConfig::sandboxed()
+    });
+
+    script.lua.exec::<()>(r#"
+        slime = require("enemies.slime")
+        same = require("enemies.slime") == slime
+    "#, None).unwrap();
+
+    let globals = script.lua.globals();
+    assert_eq!(globals.get::<_, Table>("slime").unwrap().get::<_, f32>("speed").unwrap(), 3.0);
+    assert!(globals.get::<_, bool>("same").unwrap());
+    assert_eq!(globals.get::<_, i32>("loads").unwrap(), 1);
+
+    let err = format!("{:?}", script.lua.exec::<()>(r#"require("cycle_a")"#, None).unwrap_err());
+    assert!(err.contains("cycle_a -> cycle_b -> cycle_a"));
+
+    assert!(script.lua.exec::<()>(r#"require("..secrets")"#, None).is_err());
+    assert!(script.lua.exec::<()>(r#"require("missing")"#, None).is_err());
+
+    let manifest = read_manifest(root.join("manifest.txt").to_str().unwrap()).unwrap();
+    assert_eq!(manifest, vec![
+        root.join("enemies/base.lua").to_str().unwrap().to_string(),
+        root.join("enemies/slime.lua").to_str().unwrap().to_string(),
+    ]);
+
+    fs::remove_dir_all(&root).unwrap();
+}
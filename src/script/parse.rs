@@ -1,4 +1,8 @@
-use rlua::{Value as LuaValue, Result as LuaResult, Lua};
+use ::utility::Rect2;
+use ::script::types::{Vector2f, Vector3f};
+
+use rlua::{Value as LuaValue, Result as LuaResult, Error as LuaError, Function as LuaFunction, Table, RegistryKey, Lua};
+use cgmath::{Vector2, Vector3};
 use specs;
 use script::ScriptResult;
 
@@ -6,4 +10,88 @@ use script::ScriptResult;
 // TODO: parsed, such as: component_parser!(ComponentStruct, "x", "y", "z", "other_data")
 pub trait ComponentParser: Sized + specs::Component {
     fn parse(LuaValue, &Lua) -> ScriptResult<Self>;
-}
\ No newline at end of file
+}
+
+/* NOTE:
+    Fields are read and written by the names the component parser uses, e.g. 'world:get(e, "sprite").image_index'.
+    Vectors are read as vec2f/vec3f (but can be written as tables), while rects and shapes are read and written as
+    the same tables they're parsed from.
+*/
+pub trait ComponentFields: specs::Component {
+    fn get_field<'lua>(&self, field: &str, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>>;
+    fn set_field<'lua>(&mut self, field: &str, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()>;
+}
+
+pub fn no_field(field: &str) -> LuaError {
+    LuaError::RuntimeError(format!("There's no field named '{}'", field))
+}
+
+pub fn read_only(field: &str) -> LuaError {
+    LuaError::RuntimeError(format!("The field '{}' can't be changed", field))
+}
+
+// A vector from either a vec2f or an {x, y} table.
+pub fn vector2_from_lua(value: LuaValue) -> LuaResult<Vector2<f32>> {
+    match value {
+        LuaValue::UserData(ref ud) if ud.is::<Vector2f>()? => Ok(ud.borrow::<Vector2f>()?.0),
+        LuaValue::Table(t) => Ok(Vector2::new(t.get("x")?, t.get("y")?)),
+        _ => Err(LuaError::FromLuaConversionError {
+            from: "_",
+            to: "vec2f",
+            message: None,
+        }),
+    }
+}
+
+// A vector from either a vec3f or an {x, y, z} table.
+pub fn vector3_from_lua(value: LuaValue) -> LuaResult<Vector3<f32>> {
+    match value {
+        LuaValue::UserData(ref ud) if ud.is::<Vector3f>()? => Ok(ud.borrow::<Vector3f>()?.0),
+        LuaValue::Table(t) => Ok(Vector3::new(t.get("x")?, t.get("y")?, t.get("z")?)),
+        _ => Err(LuaError::FromLuaConversionError {
+            from: "_",
+            to: "vec3f",
+            message: None,
+        }),
+    }
+}
+
+pub fn rect2_from_lua(t: Table) -> LuaResult<Rect2<f32>> {
+    Ok(Rect2::new(
+        Vector2::new(t.get("min_x")?, t.get("min_y")?),
+        Vector2::new(t.get("max_x")?, t.get("max_y")?),
+    ))
+}
+
+pub fn rect2_to_lua<'lua>(rect: Rect2<f32>, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+    let t = lua.create_table()?;
+    t.set("min_x", rect.min.x)?;
+    t.set("min_y", rect.min.y)?;
+    t.set("max_x", rect.max.x)?;
+    t.set("max_y", rect.max.y)?;
+
+    Ok(LuaValue::Table(t))
+}
+
+// The function a callback's registry key points to (or nil).
+pub fn callback_to_lua<'lua>(key: &Option<RegistryKey>, lua: &'lua Lua) -> LuaResult<LuaValue<'lua>> {
+    match *key {
+        Some(ref key) => Ok(LuaValue::Function(lua.registry_value::<LuaFunction>(key)?)),
+        None => Ok(LuaValue::Nil),
+    }
+}
+
+// Replaces a callback with a function (or nil), freeing the old one's registry value.
+pub fn set_callback<'lua>(key: &mut Option<RegistryKey>, value: LuaValue<'lua>, lua: &'lua Lua) -> LuaResult<()> {
+    let func: Option<LuaFunction> = lua.unpack(value)?;
+    let new_key = match func {
+        Some(func) => Some(lua.create_registry_value(func)?),
+        None => None,
+    };
+
+    if let Some(old_key) = ::std::mem::replace(key, new_key) {
+        lua.remove_registry_value(old_key)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use ::component as comp;
+use ::component::collider::PreSolveHook;
+use ::resource as res;
+use script::{Script, ScriptResult, ComponentFields};
+use script::{sandbox, module};
+
+use std::io::prelude::*;
+use std::fs::{self, File};
+use std::time::{Instant, SystemTime};
+
+use specs;
+use rlua::{Table, Value as LuaValue, Function as LuaFunction, Error as LuaError};
+
+// The callbacks rebound from an entity's prefab, by component.
+const BEHAVIOR_CALLBACKS: &[&str] = &["on_tick", "on_update", "run"];
+const COLLIDER_CALLBACKS: &[&str] = &["on_collide", "pre_solve"];
+
+// How often (in seconds) the watched files are checked for changes.
+const WATCH_INTERVAL: f32 = 0.5;
+
+/* NOTE:
+    A reloaded file runs in its own environment (reading through to the globals), and prefabs it registers are kept
+    aside as well. Only once the whole file has run are its globals and prefabs moved over, so a file with an error
+    leaves the last working version in place.
+*/
+impl Script {
+    pub fn reload_file(&self, path: &str) -> ScriptResult<()> {
+        let mut contents = String::new();
+        File::open(path)?.read_to_string(&mut contents)?;
+
+        let globals = self.globals();
+        let prefabs: Table = self.named_registry_value("prefabs")?;
+
+        // Files loaded with a config of their own read through their own environment instead.
+        let source_env = sandbox::source_env(&self.lua, path)?;
+
+        let env = self.create_table()?;
+        let env_meta = self.create_table()?;
+        match source_env {
+            Some(source_env) => {
+                env_meta.set("__index", source_env)?;
+                // Which would otherwise be a way around it, to the globals.
+                env_meta.set("__metatable", false)?;
+            },
+            None => env_meta.set("__index", globals.clone())?,
+        }
+        env.set_metatable(Some(env_meta.clone()));
+
+        let staged_prefabs = self.create_table()?;
+        let staged_meta = self.create_table()?;
+        staged_meta.set("__index", prefabs.clone())?;
+        staged_prefabs.set_metatable(Some(staged_meta));
+
+        self.set_named_registry_value("prefabs", staged_prefabs.clone())?;
+        let result = self.exec_with_env(&contents, path, env.clone());
+        self.set_named_registry_value("prefabs", prefabs.clone())?;
+        result?;
+
+        for pair in staged_prefabs.pairs::<LuaValue, LuaValue>() {
+            let (k, v) = pair?;
+            prefabs.set(k, v)?;
+        }
+
+        // The file's functions keep the environment, so it's left as a window onto the globals.
+        let mut keys = Vec::new();
+        for pair in env.clone().pairs::<LuaValue, LuaValue>() {
+            let (k, v) = pair?;
+            globals.set(k.clone(), v)?;
+            keys.push(k);
+        }
+
+        for k in keys {
+            env.raw_set(k, LuaValue::Nil)?;
+        }
+        env_meta.set("__newindex", globals)?;
+
+        Ok(())
+    }
+
+    fn exec_with_env(&self, contents: &str, path: &str, env: Table) -> ScriptResult<()> {
+        // Lua's own 'load' (kept aside before scripts are able to touch it) is able to give a chunk its environment.
+        let load: LuaFunction = self.named_registry_value("load")?;
+        let (chunk, err): (Option<LuaFunction>, Option<String>) = load.call((contents, sandbox::source_name(path), "t", env))?;
+
+        let chunk = chunk.ok_or_else(|| LuaError::SyntaxError {
+            message: err.unwrap_or_default(),
+            incomplete_input: false,
+        })?;
+
+        // Runs with the limits of the file.
+        sandbox::enter_file(&self.lua, path)?;
+        let result = chunk.call::<_, ()>(());
+        sandbox::reset_budget(&self.lua)?;
+
+        Ok(result?)
+    }
+
+    // Points the Lua callbacks of every entity spawned from a prefab at the prefab's current functions.
+    pub fn rebind_all(&self, world: &specs::World) {
+        use specs::Join;
+
+        let entities: Vec<_> = {
+            let (ents, prefab): (specs::Entities, specs::ReadStorage<comp::Prefab>) = specs::SystemData::fetch(&world.res);
+            (&*ents, &prefab).join().map(|(ent, prefab)| (ent, prefab.name.clone())).collect()
+        };
+
+        for (ent, lua_name) in entities {
+            if let Err(err) = self.rebind_callbacks(&lua_name, ent, world) {
+                error!("Couldn't rebind the callbacks of entity {} ('{}'): {:?}", ent.id(), lua_name, err);
+            }
+        }
+    }
+
+    // Replaces the callbacks of an entity's existing components with the ones in its prefab (unlike
+    // 'bind_callbacks', which adds a script behavior if there isn't one). A running coroutine is started over with the
+    // prefab's 'run', and native pre-solve hooks are left alone unless the prefab has a Lua one.
+    pub fn rebind_callbacks(&self, lua_name: &str, entity: specs::Entity, world: &specs::World) -> ScriptResult<()> {
+        let ent_table = self.prefab(lua_name)?;
+
+        if let Some(t) = ent_table.get::<_, Option<Table>>("script")? {
+            if let Some(behav) = world.write_storage::<comp::ScriptBehavior>().get_mut(entity) {
+                for name in BEHAVIOR_CALLBACKS {
+                    behav.set_field(name, t.get(*name)?, &self.lua)?;
+                }
+            }
+        }
+
+        if let Some(t) = ent_table.get::<_, Option<Table>>("collider")? {
+            if let Some(coll) = world.write_storage::<comp::Collider>().get_mut(entity) {
+                for name in COLLIDER_CALLBACKS {
+                    let func: LuaValue = t.get(*name)?;
+
+                    // A native hook isn't in the prefab, so it's only replaced by a Lua one.
+                    if let ("pre_solve", Some(PreSolveHook::Native(_)), LuaValue::Nil) = (*name, &coll.pre_solve, &func) {
+                        continue;
+                    }
+
+                    coll.set_field(name, func, &self.lua)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+struct WatchedFile {
+    path: String,
+    modified: Option<SystemTime>,
+    // Loaded with 'require' rather than reloaded on its own.
+    module: bool,
+}
+
+/* NOTE:
+    Script files are reloaded when they change on disk (see 'Script::reload_file'). Modules are only run by requiring
+    them, so they're watched as they're loaded, and when one changes the module cache is emptied and every script is
+    reloaded (requiring whatever modules they need again).
+*/
+pub struct ScriptWatcher {
+    files: Vec<WatchedFile>,
+    last_check: Instant,
+}
+
+impl ScriptWatcher {
+    pub fn new() -> Self {
+        ScriptWatcher {
+            files: Vec::new(),
+            last_check: Instant::now(),
+        }
+    }
+
+    pub fn watch(&mut self, path: &str) {
+        self.files.push(WatchedFile {
+            path: path.into(),
+            modified: modified_time(path),
+            module: false,
+        });
+    }
+
+    // Reloads the files changed since they were last checked (at most every WATCH_INTERVAL), then rebinds the
+    // entities' callbacks.
+    pub fn update(&mut self, world: &specs::World) {
+        let elapsed = self.last_check.elapsed();
+        if (elapsed.as_secs() as f32 + elapsed.subsec_nanos() as f32 / 1_000_000_000.0) < WATCH_INTERVAL {
+            return;
+        }
+        self.last_check = Instant::now();
+
+        self.check(world);
+    }
+
+    fn check(&mut self, world: &specs::World) {
+        self.watch_modules(world);
+
+        let changed: Vec<(String, bool)> = self.files.iter_mut()
+            .filter_map(|file| {
+                let modified = modified_time(&file.path);
+
+                if modified.is_some() && modified != file.modified {
+                    file.modified = modified;
+                    Some((file.path.clone(), file.module))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        if changed.iter().any(|&(_, module)| module) {
+            let scripts: Vec<String> = self.files.iter().filter(|x| !x.module).map(|x| x.path.clone()).collect();
+
+            clear_modules(world);
+            reload_files(world, &scripts);
+        } else if !changed.is_empty() {
+            let changed: Vec<String> = changed.into_iter().map(|(path, _)| path).collect();
+            reload_files(world, &changed);
+        }
+    }
+
+    // Starts watching the modules loaded since the last check.
+    fn watch_modules(&mut self, world: &specs::World) {
+        let paths = match world.read_resource::<res::Script>().0 {
+            Some(ref mutex) => module::module_paths(&mutex.lock().unwrap()).unwrap_or_default(),
+            None => return,
+        };
+
+        for path in paths {
+            if self.files.iter().all(|x| x.path != path) {
+                self.files.push(WatchedFile {
+                    modified: modified_time(&path),
+                    path,
+                    module: true,
+                });
+            }
+        }
+    }
+}
+
+impl Default for ScriptWatcher {
+    fn default() -> Self {
+        ScriptWatcher::new()
+    }
+}
+
+fn clear_modules(world: &specs::World) {
+    if let Some(ref mutex) = world.read_resource::<res::Script>().0 {
+        if let Err(err) = module::clear_modules(&mutex.lock().unwrap()) {
+            error!("Couldn't clear the loaded modules: {:?}", err);
+        }
+    }
+}
+
+// Reloads script files (in order) and rebinds the entities' callbacks if any of them loaded.
+pub fn reload_files(world: &specs::World, paths: &[String]) {
+    let mutex = match world.read_resource::<res::Script>().0 {
+        Some(ref mutex) => mutex.clone(),
+        None => return,
+    };
+    let script = mutex.lock().unwrap();
+
+    let mut reloaded = false;
+    for path in paths {
+        match script.reload_file(path) {
+            Ok(()) => {
+                info!("Reloaded '{}'", path);
+                reloaded = true;
+            },
+            Err(err) => {
+                let message = format!("Couldn't reload '{}' (keeping the last version): {:?}", path, err);
+                error!("{}", message);
+                world.write_resource::<res::ScriptErrors>().load_errors.push(message);
+            },
+        }
+    }
+
+    if reloaded {
+        script.rebind_all(world);
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|x| x.modified()).ok()
+}
+
+#[test]
+fn reload_rebinds_callbacks() {
+    use ::game::GameBuilder;
+    use std::env;
+
+    let path = env::temp_dir().join(format!("tally_ho_reload_test_{}.lua", ::std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+
+    File::create(&path).unwrap().write_all(br#"
+        counter = 0
+        prefab("counter", {
+            script = { on_tick = function(world, this, dt) counter = counter + 1 end },
+        })
+    "#).unwrap();
+
+    let mut game = GameBuilder::new(1.0/60.0)
+        .with_script(&path)
+        .build().unwrap();
+
+    let mutex = game.world.read_resource::<res::Script>().0.clone().unwrap();
+    mutex.lock().unwrap().parse_entity("counter", game.world.create_entity()).unwrap();
+    game.tick();
+
+    // A broken version is reported, and the working one keeps running.
+    File::create(&path).unwrap().write_all(br#"
+        counter = 100
+        prefab("counter", {
+            script = { on_tick = function(world, this, dt) counter = counter + 10 end },
+        })
+        error("broken on purpose")
+    "#).unwrap();
+
+    reload_files(&game.world, &[path.clone()]);
+    assert_eq!(game.world.read_resource::<res::ScriptErrors>().load_errors.len(), 1);
+    game.tick();
+    assert_eq!(mutex.lock().unwrap().globals().get::<_, i32>("counter").unwrap(), 2);
+
+    File::create(&path).unwrap().write_all(br#"
+        prefab("counter", {
+            script = { on_tick = function(world, this, dt) counter = counter + 10 end },
+        })
+    "#).unwrap();
+
+    reload_files(&game.world, &[path.clone()]);
+    game.tick();
+    assert_eq!(mutex.lock().unwrap().globals().get::<_, i32>("counter").unwrap(), 12);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reload_changed_modules() {
+    use ::game::GameBuilder;
+    use ::script::ScriptConfig;
+    use std::{env, thread};
+    use std::time::Duration;
+
+    let root = env::temp_dir().join(format!("tally_ho_reload_module_test_{}", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let path = root.join("main.lua").to_str().unwrap().to_string();
+
+    File::create(root.join("helper.lua")).unwrap().write_all(b"return { step = 1 }").unwrap();
+    File::create(&path).unwrap().write_all(br#"
+        local helper = require("helper")
+        counter = counter or 0
+        prefab("counter", {
+            script = { on_tick = function(world, this, dt) counter = counter + helper.step end },
+        })
+    "#).unwrap();
+
+    let mut game = GameBuilder::new(1.0/60.0)
+        .with_script_config(ScriptConfig { module_root: root.to_str().unwrap().into(), ..ScriptConfig::default() })
+        .with_script(&path)
+        .build().unwrap();
+
+    let mutex = game.world.read_resource::<res::Script>().0.clone().unwrap();
+    mutex.lock().unwrap().parse_entity("counter", game.world.create_entity()).unwrap();
+
+    let mut watcher = ScriptWatcher::new();
+    watcher.watch(&path);
+    watcher.check(&game.world);
+    game.tick();
+
+    // Making sure the change is seen, whatever the file system's timestamp resolution.
+    thread::sleep(Duration::from_millis(20));
+    File::create(root.join("helper.lua")).unwrap().write_all(b"return { step = 10 }").unwrap();
+
+    watcher.check(&game.world);
+    game.tick();
+    assert_eq!(mutex.lock().unwrap().globals().get::<_, i32>("counter").unwrap(), 11);
+
+    fs::remove_dir_all(&root).unwrap();
+}
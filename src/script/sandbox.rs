@@ -0,0 +1,788 @@
+use script::module::DEFAULT_MODULE_ROOT;
+
+use rlua::{Lua, Table, Value as LuaValue, Function as LuaFunction, Thread, Error as LuaError, Result as LuaResult, 
+    MultiValue};
+
+// How many instructions run between checks of the instruction budget and memory limit.
+const HOOK_INTERVAL: u32 = 1000;
+
+// The limits of the 'sandboxed' config.
+pub const DEFAULT_INSTRUCTION_BUDGET: u32 = 1_000_000;
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+// What the Sandboxed profile takes away ('require' is replaced by the module loader, see 'module::add_require_fn').
+const SANDBOXED_GLOBALS: &[&str] = &["io", "package", "dofile", "loadfile", "load", "loadstring", "collectgarbage"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SandboxProfile {
+    // The whole standard library (except 'debug').
+    Unrestricted,
+    // No files, processes or bytecode: 'io', 'package', 'debug', 'dofile', 'loadfile', 'load', 'collectgarbage' and
+    // 'string.dump' are removed, and 'os' only keeps its time functions. Modules are still loaded from the module root.
+    Sandboxed,
+}
+
+// How scripts are allowed to run. A Script's own config applies to everything it runs, except the files loaded with 
+// a config of their own (see 'Script::load_file_with_config' and the note on 'apply').
+#[derive(Debug, Clone)]
+pub struct ScriptConfig {
+    pub sandbox: SandboxProfile,
+    // How many instructions a callback is able to run (each time it's called) before it's aborted.
+    pub instruction_budget: Option<u32>,
+    // How many bytes the Lua state is able to use, checked as the budget is (so it may be briefly exceeded).
+    pub memory_limit: Option<usize>,
+    // The directory 'require' loads modules from. Modules are shared by every file, so only the Script's own config
+    // decides it.
+    pub module_root: String,
+}
+
+impl ScriptConfig {
+    // For scripts which aren't trusted (e.g. mods and user content).
+    pub fn sandboxed() -> Self {
+        ScriptConfig {
+            sandbox: SandboxProfile::Sandboxed,
+            instruction_budget: Some(DEFAULT_INSTRUCTION_BUDGET),
+            memory_limit: Some(DEFAULT_MEMORY_LIMIT),
+            module_root: DEFAULT_MODULE_ROOT.into(),
+        }
+    }
+
+    pub fn has_limits(&self) -> bool {
+        self.instruction_budget.is_some() || self.memory_limit.is_some()
+    }
+}
+
+impl Default for ScriptConfig {
+    fn default() -> Self {
+        ScriptConfig {
+            sandbox: SandboxProfile::Unrestricted,
+            instruction_budget: None,
+            memory_limit: None,
+            module_root: DEFAULT_MODULE_ROOT.into(),
+        }
+    }
+}
+
+/* NOTE:
+    The limits are enforced by a count hook, set with 'debug.sethook'. The Lua state is always created with the debug
+    library to get at 'sethook' and 'getinfo' (and 'collectgarbage', for the memory limit), which are kept in the 
+    registry before the library is removed from the globals (and 'package.loaded'), so scripts never see it.
+
+    A file loaded with a config of its own (see 'add_source') is a source with its own limits and profile. Every 
+    callback the engine calls runs with the limits of the file that defined its function (the Script's own ones if it
+    wasn't one of those), whichever other functions it goes on to call. The memory limit is still of the whole Lua 
+    state (there's only one), just checked against the limit of whichever source is running. A source with another 
+    profile than the Script runs in an environment of its own (reading through to the globals it doesn't hide, and 
+    writing to them), so sources still share their globals (and the modules they require).
+
+    Hooks belong to a thread, so the coroutines scripts create get the hook as well (see 'hook_coroutines'), along 
+    with the strictest of their creator's limits and the ones of the file their function is from. The memory limit is
+    only checked every HOOK_INTERVAL instructions, which a single call is able to get far past, so the functions able
+    to build a huge string in one go ('string.rep', 'string.gsub', 'string.format' and 'table.concat') check the size
+    of what they'd return first (see 'limit_amplifiers'). It's still only best-effort: e.g. a loop of '..' 
+    concatenations is able to overshoot the limit between checks.
+*/
+pub fn apply(lua: &Lua, config: &ScriptConfig) -> LuaResult<()> {
+    let globals = lua.globals();
+
+    let debug: Table = globals.get("debug")?;
+    let coroutine: Table = globals.get("coroutine")?;
+    lua.set_named_registry_value("sethook", debug.get::<_, LuaFunction>("sethook")?)?;
+    lua.set_named_registry_value("getinfo", debug.get::<_, LuaFunction>("getinfo")?)?;
+    lua.set_named_registry_value("running", coroutine.get::<_, LuaFunction>("running")?)?;
+    lua.set_named_registry_value("collectgarbage", globals.get::<_, LuaFunction>("collectgarbage")?)?;
+
+    // The limits of the running callback (and where they came from), used by the hook and the wrapped functions.
+    let limits = lua.create_table()?;
+    let default = limits_table(lua, config)?;
+    limits.set("default", default.clone())?;
+    limits.set("current", default)?;
+    limits.set("run", 0)?;
+    limits.set("any", config.has_limits())?;
+    limits.set("sources", lua.create_table()?)?;
+    limits.set("envs", lua.create_table()?)?;
+    limits.set("sandboxed", config.sandbox == SandboxProfile::Sandboxed)?;
+
+    // Coroutines only keep their limits for as long as they're around.
+    let threads = lua.create_table()?;
+    let weak = lua.create_table()?;
+    weak.set("__mode", "k")?;
+    threads.set_metatable(Some(weak));
+    limits.set("threads", threads)?;
+
+    lua.set_named_registry_value("limits", limits)?;
+
+    set_hook(lua, None)?;
+    hook_coroutines(lua)?;
+    limit_amplifiers(lua)?;
+    add_env_fns(lua)?;
+
+    globals.set("debug", LuaValue::Nil)?;
+    if let Some(package) = globals.get::<_, Option<Table>>("package")? {
+        package.get::<_, Table>("loaded")?.set("debug", LuaValue::Nil)?;
+    }
+
+    if config.sandbox == SandboxProfile::Sandboxed {
+        for name in SANDBOXED_GLOBALS.iter().chain(&["require"]) {
+            globals.set(*name, LuaValue::Nil)?;
+        }
+
+        let string: Table = globals.get("string")?;
+        string.set("dump", LuaValue::Nil)?;
+        globals.set("os", safe_os(lua, globals.get("os")?)?)?;
+    }
+
+    Ok(())
+}
+
+fn limits_table<'lua>(lua: &'lua Lua, config: &ScriptConfig) -> LuaResult<Table<'lua>> {
+    let t = lua.create_table()?;
+    t.set("budget", config.instruction_budget.map(|x| x as i64))?;
+    t.set("memory", config.memory_limit.map(|x| x as f64))?;
+
+    Ok(t)
+}
+
+fn safe_os<'lua>(lua: &'lua Lua, os: Table<'lua>) -> LuaResult<Table<'lua>> {
+    let safe_os = lua.create_table()?;
+    for name in &["clock", "date", "difftime", "time"] {
+        safe_os.set(*name, os.get::<_, LuaValue>(*name)?)?;
+    }
+
+    Ok(safe_os)
+}
+
+// Keeps the functions building a source's environment (see 'add_source'), along with the parts of the standard 
+// library a Sandboxed Script removes (for its Unrestricted sources).
+fn add_env_fns(lua: &Lua) -> LuaResult<()> {
+    let globals = lua.globals();
+
+    let library = lua.create_table()?;
+    for name in SANDBOXED_GLOBALS.iter().chain(&["os"]) {
+        library.set(*name, globals.get::<_, LuaValue>(*name)?)?;
+    }
+    lua.set_named_registry_value("full_library", library)?;
+
+    let new_env: LuaFunction = lua.load(r#"
+        local globals, setmetatable, pairs = ...
+        -- Kept before a Sandboxed Script removes it.
+        local string, dump = string, string.dump
+
+        return function(overrides, hidden, keep_dump)
+            local env, given = {}, {}
+            for k, v in pairs(overrides) do
+                given[k] = v
+            end
+
+            -- A copy of its own, so the source doesn't change it for everything else.
+            given.string = {}
+            for k, v in pairs(string) do
+                given.string[k] = v
+            end
+            given.string.dump = keep_dump and dump or nil
+
+            setmetatable(env, {
+                __index = function(_, k)
+                    if k == "_G" then
+                        return env
+                    elseif hidden[k] then
+                        return nil
+                    end
+
+                    local v = given[k]
+                    if v == nil then
+                        v = globals[k]
+                    end
+                    return v
+                end,
+                __newindex = globals,
+                __metatable = false,
+            })
+
+            return env
+        end
+    "#, Some("sandbox"))?.call((globals, lua.globals().get::<_, LuaFunction>("setmetatable")?, 
+        lua.globals().get::<_, LuaFunction>("pairs")?))?;
+
+    lua.set_named_registry_value("new_env", new_env)
+}
+
+// Registers a file as a source with a config of its own, so the functions it defines run with the config's limits 
+// (and, if its profile isn't the Script's, in an environment of its own). The config's module root isn't used.
+pub fn add_source(lua: &Lua, path: &str, config: &ScriptConfig) -> LuaResult<()> {
+    let limits: Table = lua.named_registry_value("limits")?;
+    let source = source_name(path);
+
+    limits.get::<_, Table>("sources")?.set(source.as_str(), limits_table(lua, config)?)?;
+    if config.has_limits() {
+        limits.set("any", true)?;
+    }
+
+    let sandboxed = config.sandbox == SandboxProfile::Sandboxed;
+    let env = if sandboxed == limits.get::<_, bool>("sandboxed")? {
+        None
+    } else {
+        let new_env: LuaFunction = lua.named_registry_value("new_env")?;
+        let overrides = lua.create_table()?;
+        let hidden = lua.create_table()?;
+
+        if sandboxed {
+            overrides.set("os", safe_os(lua, lua.globals().get("os")?)?)?;
+            for name in SANDBOXED_GLOBALS {
+                hidden.set(*name, true)?;
+            }
+        } else {
+            for pair in lua.named_registry_value::<Table>("full_library")?.pairs::<LuaValue, LuaValue>() {
+                let (k, v) = pair?;
+                overrides.set(k, v)?;
+            }
+        }
+
+        Some(new_env.call::<_, Table>((overrides, hidden, !sandboxed))?)
+    };
+    limits.get::<_, Table>("envs")?.set(source, env)
+}
+
+// The environment the file runs in, if it's a source with one (see 'add_source').
+pub fn source_env<'lua>(lua: &'lua Lua, path: &str) -> LuaResult<Option<Table<'lua>>> {
+    let limits: Table = lua.named_registry_value("limits")?;
+    limits.get::<_, Table>("envs")?.get(source_name(path))
+}
+
+// How Lua names the chunk of a file (so it's able to be found from the functions it defines).
+pub fn source_name(path: &str) -> String {
+    format!("@{}", path)
+}
+
+// Sets the limit hook on a thread (the main thread if there's none).
+fn set_hook<'lua>(lua: &'lua Lua, thread: Option<LuaValue<'lua>>) -> LuaResult<()> {
+    let sethook: LuaFunction = lua.named_registry_value("sethook")?;
+    let hook = lua.create_function(check_limits)?;
+
+    match thread {
+        Some(thread) => sethook.call::<_, ()>((thread, hook, "", HOOK_INTERVAL)),
+        None => sethook.call::<_, ()>((hook, "", HOOK_INTERVAL)),
+    }
+}
+
+// Sets the limit hook on a new coroutine, which keeps the limits of the file its function is from (along with the 
+// ones of whatever created it, if a script did).
+pub fn hook_thread<'lua>(lua: &'lua Lua, thread: &Thread<'lua>, func: &LuaFunction<'lua>, from_script: bool) -> LuaResult<()> {
+    set_hook(lua, Some(LuaValue::Thread(thread.clone())))?;
+
+    let limits: Table = lua.named_registry_value("limits")?;
+    if !limits.get::<_, bool>("any")? {
+        return Ok(());
+    }
+
+    let mut own = limits_of(lua, &limits, func)?;
+    if from_script {
+        let (budget, memory) = effective_limits(lua, &limits)?;
+        let creator = lua.create_table()?;
+        creator.set("budget", budget)?;
+        creator.set("memory", memory)?;
+
+        own = strictest(lua, &own, &creator)?;
+    }
+
+    limits.get::<_, Table>("threads")?.set(thread.clone(), own)
+}
+
+// Replaces 'coroutine.create' and 'coroutine.wrap' with versions giving the new thread the limit hook.
+fn hook_coroutines(lua: &Lua) -> LuaResult<()> {
+    let create = lua.create_function(|lua, func: LuaFunction| {
+        let thread = lua.create_thread(func.clone())?;
+        hook_thread(lua, &thread, &func, true)?;
+        Ok(thread)
+    })?;
+
+    lua.load(r#"
+        local create = ...
+        local resume, pack, unpack, error = coroutine.resume, table.pack, table.unpack, error
+
+        coroutine.create = create
+        coroutine.wrap = function(func)
+            local co = create(func)
+
+            return function(...)
+                local results = pack(resume(co, ...))
+                if not results[1] then
+                    error(results[2], 0)
+                end
+
+                return unpack(results, 2, results.n)
+            end
+        end
+    "#, Some("sandbox"))?.call(create)
+}
+
+// Wraps the functions able to build a huge string in one call, so they fail (like the hook would) rather than go past
+// the memory limit of whatever's running.
+fn limit_amplifiers(lua: &Lua) -> LuaResult<()> {
+    let collectgarbage: LuaFunction = lua.named_registry_value("collectgarbage")?;
+    let running: LuaFunction = lua.named_registry_value("running")?;
+    let limits: Table = lua.named_registry_value("limits")?;
+
+    lua.load(r##"
+        local limits, collectgarbage, running = ...
+        local rep, gsub, format, concat = string.rep, string.gsub, string.format, table.concat
+        local find, sub, pack = string.find, string.sub, table.pack
+        local tostring, tonumber, type, select, pairs, error = tostring, tonumber, type, select, pairs, error
+
+        -- Like the hook's limit (see 'effective_limits').
+        local function memory_limit()
+            local limit = limits.current.memory
+            local co, main = running()
+
+            if not main then
+                local own = limits.threads[co]
+                if own and own.memory and (not limit or own.memory < limit) then
+                    limit = own.memory
+                end
+            end
+
+            return limit
+        end
+
+        local function check(limit, size)
+            if size > limit - collectgarbage("count") * 1024 then
+                collectgarbage("collect")
+
+                if size > limit - collectgarbage("count") * 1024 then
+                    error("Exceeded the memory limit", 3)
+                end
+            end
+        end
+
+        string.rep = function(s, n, sep)
+            local limit = memory_limit()
+            local count = tonumber(n) or 0
+            if limit and count > 0 then
+                check(limit, #tostring(s) * count + #tostring(sep or "") * (count - 1))
+            end
+
+            return rep(s, n, sep)
+        end
+
+        table.concat = function(list, sep, i, j)
+            local limit = memory_limit()
+            if limit and type(list) == "table" then
+                i, j = tonumber(i) or 1, tonumber(j) or #list
+                local size = #tostring(sep or "") * (j > i and j - i or 0)
+
+                for k = i, j do
+                    local v = list[k]
+                    size = size + (type(v) == "string" and #v or 24)
+                end
+                check(limit, size)
+            end
+
+            return concat(list, sep, i, j)
+        end
+
+        string.format = function(fmt, ...)
+            local limit = memory_limit()
+            if limit then
+                -- '%q' escapes a byte with at most 4, and widths and precisions are under 100.
+                local size = #tostring(fmt)
+                for k = 1, select("#", ...) do
+                    size = size + 4 * #tostring((select(k, ...))) + 100
+                end
+                check(limit, size)
+            end
+
+            return format(fmt, ...)
+        end
+
+        -- How many bytes of a string replacement aren't captures, and how many times it refers to each capture.
+        local function parse_repl(repl)
+            local literal, refs, k = 0, {}, 1
+
+            while k <= #repl do
+                local capture = sub(repl, k, k) == "%" and tonumber(sub(repl, k + 1, k + 1))
+
+                if capture then
+                    refs[capture] = (refs[capture] or 0) + 1
+                    k = k + 2
+                else
+                    literal = literal + 1
+                    k = k + (sub(repl, k, k) == "%" and 2 or 1)
+                end
+            end
+
+            return literal, refs
+        end
+
+        -- The size of what 'gsub' returns for a string replacement, walking the matches like it does.
+        local function gsub_size(s, pattern, repl, max)
+            local literal, refs = parse_repl(repl)
+            local anchored = sub(pattern, 1, 1) == "^"
+            local size, count, src, last = #s, 0, 1, nil
+
+            while not max or count < max do
+                local found = pack(find(s, pattern, src))
+                local first, last_char = found[1], found[2]
+                if not first then
+                    break
+                end
+
+                if first == last and last_char < first then
+                    -- An empty match right after the last match isn't replaced.
+                    if first > #s then
+                        break
+                    end
+                    src = first + 1
+                else
+                    count = count + 1
+                    size = size + literal - (last_char - first + 1)
+
+                    for ref, times in pairs(refs) do
+                        local capture = found[ref + 2]
+                        if ref == 0 or (ref == 1 and found.n == 2) then
+                            capture = sub(s, first, last_char)
+                        end
+
+                        size = size + #tostring(capture) * times
+                    end
+
+                    src, last = last_char + 1, last_char + 1
+                end
+
+                if anchored then
+                    break
+                end
+            end
+
+            return size
+        end
+
+        string.gsub = function(s, pattern, repl, n)
+            local limit = memory_limit()
+            local kind = type(repl)
+            if not limit or (type(s) ~= "string" and type(s) ~= "number") or type(pattern) ~= "string" then
+                return gsub(s, pattern, repl, n)
+            end
+            s = tostring(s)
+
+            if kind == "string" or kind == "number" then
+                check(limit, gsub_size(s, pattern, tostring(repl), tonumber(n)))
+                return gsub(s, pattern, repl, n)
+            elseif kind == "function" or kind == "table" then
+                -- What isn't replaced is part of the string, so only the replacements add to it.
+                local size = #s
+                return gsub(s, pattern, function(...)
+                    local v
+                    if kind == "table" then
+                        v = repl[(...)]
+                    else
+                        v = repl(...)
+                    end
+
+                    if type(v) == "string" or type(v) == "number" then
+                        size = size + #tostring(v)
+                        check(limit, size)
+                    end
+
+                    return v
+                end, n)
+            end
+
+            return gsub(s, pattern, repl, n)
+        end
+    "##, Some("sandbox"))?.call((limits, collectgarbage, running))
+}
+
+// Gives the next callback the Script's own limits, with the whole instruction budget.
+pub fn reset_budget(lua: &Lua) -> LuaResult<()> {
+    let limits: Table = lua.named_registry_value("limits")?;
+    limits.set("current", limits.get::<_, Table>("default")?)?;
+    limits.set("run", 0)
+}
+
+// Gives the callback about to be called (a function, or a coroutine to resume) its limits, with the whole instruction
+// budget.
+pub fn enter<'lua>(lua: &'lua Lua, entry: &LuaValue<'lua>) -> LuaResult<()> {
+    let limits: Table = lua.named_registry_value("limits")?;
+
+    let current = match *entry {
+        LuaValue::Function(ref func) => limits_of(lua, &limits, func)?,
+        LuaValue::Thread(ref thread) => match limits.get::<_, Table>("threads")?.get::<_, Option<Table>>(thread.clone())? {
+            Some(own) => own,
+            None => limits.get("default")?,
+        },
+        _ => limits.get("default")?,
+    };
+
+    limits.set("current", current)?;
+    limits.set("run", 0)
+}
+
+// Like 'enter', for running a file (i.e. loading or reloading it).
+pub fn enter_file(lua: &Lua, path: &str) -> LuaResult<()> {
+    let limits: Table = lua.named_registry_value("limits")?;
+
+    let current = match limits.get::<_, Table>("sources")?.get::<_, Option<Table>>(source_name(path))? {
+        Some(own) => own,
+        None => limits.get("default")?,
+    };
+
+    limits.set("current", current)?;
+    limits.set("run", 0)
+}
+
+// The limits of the file a function is from.
+fn limits_of<'lua>(lua: &'lua Lua, limits: &Table<'lua>, func: &LuaFunction<'lua>) -> LuaResult<Table<'lua>> {
+    let sources: Table = limits.get("sources")?;
+
+    // Most Scripts don't have any sources of their own.
+    if sources.clone().pairs::<LuaValue, LuaValue>().next().is_none() {
+        return limits.get("default");
+    }
+
+    let getinfo: LuaFunction = lua.named_registry_value("getinfo")?;
+    let info: Table = getinfo.call((func.clone(), "S"))?;
+
+    match sources.get::<_, Option<Table>>(info.get::<_, LuaValue>("source")?)? {
+        Some(own) => Ok(own),
+        None => limits.get("default"),
+    }
+}
+
+fn strictest<'lua>(lua: &'lua Lua, a: &Table<'lua>, b: &Table<'lua>) -> LuaResult<Table<'lua>> {
+    let t = lua.create_table()?;
+    t.set("budget", stricter::<i64>(a.get("budget")?, b.get("budget")?))?;
+    t.set("memory", stricter::<f64>(a.get("memory")?, b.get("memory")?))?;
+
+    Ok(t)
+}
+
+fn stricter<T: PartialOrd>(a: Option<T>, b: Option<T>) -> Option<T> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b < a { b } else { a }),
+        (a, None) => a,
+        (None, b) => b,
+    }
+}
+
+// The instruction budget and memory limit of whatever's running: the callback's, or the coroutine's (if they're 
+// stricter).
+fn effective_limits(lua: &Lua, limits: &Table) -> LuaResult<(Option<i64>, Option<f64>)> {
+    let current: Table = limits.get("current")?;
+    let mut budget: Option<i64> = current.get("budget")?;
+    let mut memory: Option<f64> = current.get("memory")?;
+
+    let running: LuaFunction = lua.named_registry_value("running")?;
+    let (co, main): (LuaValue, bool) = running.call(())?;
+
+    if !main {
+        if let Some(own) = limits.get::<_, Table>("threads")?.get::<_, Option<Table>>(co)? {
+            budget = stricter(budget, own.get("budget")?);
+            memory = stricter(memory, own.get("memory")?);
+        }
+    }
+
+    Ok((budget, memory))
+}
+
+fn check_limits(lua: &Lua, _: MultiValue) -> LuaResult<()> {
+    let limits: Table = lua.named_registry_value("limits")?;
+    if !limits.get::<_, bool>("any")? {
+        return Ok(());
+    }
+
+    let run = limits.get::<_, i64>("run")? + HOOK_INTERVAL as i64;
+    limits.set("run", run)?;
+
+    let (budget, memory) = effective_limits(lua, &limits)?;
+
+    if budget.map(|budget| run > budget).unwrap_or(false) {
+        return Err(LuaError::RuntimeError("Exceeded the instruction budget".into()));
+    }
+
+    if let Some(limit) = memory {
+        let collectgarbage: LuaFunction = lua.named_registry_value("collectgarbage")?;
+
+        // Only fail if there's still too much in use after collecting the garbage.
+        if collectgarbage.call::<_, f64>("count")? * 1024.0 > limit {
+            collectgarbage.call::<_, ()>("collect")?;
+
+            if collectgarbage.call::<_, f64>("count")? * 1024.0 > limit {
+                return Err(LuaError::RuntimeError("Exceeded the memory limit".into()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sandbox_limits_callbacks() {
+    use ::game::GameBuilder;
+    use ::resource as res;
+    use ::script::{Script, scripted_game_with, spawn};
+
+    let script = Script::with_config(&ScriptConfig {
+        memory_limit: Some(1024 * 1024),
+        ..ScriptConfig::sandboxed()
+    });
+
+    assert!(script.lua.exec::<bool>("return io == nil and os.execute == nil and debug == nil and load == nil", None).unwrap());
+    assert!(script.lua.exec::<bool>("return os.time() ~= nil", None).unwrap());
+
+    // Scripts with the whole standard library still can't get at the debug library the hook needed.
+    let unrestricted = Script::with_config(&ScriptConfig {
+        instruction_budget: Some(DEFAULT_INSTRUCTION_BUDGET),
+        ..ScriptConfig::default()
+    });
+    assert!(unrestricted.lua.exec::<bool>("return debug == nil and package.loaded.debug == nil", None).unwrap());
+
+    // The wrapped functions still work like Lua's own.
+    assert!(script.lua.exec::<bool>(r#"
+        return string.rep("ab", 3, ",") == "ab,ab,ab" and table.concat({ 1, "b", 3 }, "-") == "1-b-3"
+            and select(2, string.gsub("hello world", "o", "0")) == 2 and ("%d-%s"):format(4, "x") == "4-x"
+            and string.gsub("abc", "%w", "%0%0") == "aabbcc" and string.gsub("abc", "", "-") == "-a-b-c-"
+            and coroutine.wrap(function(a) return a + 1 end)(1) == 2
+    "#, None).unwrap());
+
+    let builder = GameBuilder::new(1.0/60.0).with_max_script_failures(Some(1));
+    let (mut game, mutex) = scripted_game_with(builder, script, r#"
+        ticks = 0
+        spinning = {
+            script = { on_tick = function(world, this, dt) while true do end end },
+        }
+        hoarding = {
+            script = {
+                on_tick = function(world, this, dt)
+                    local hoard = {}
+                    for i = 1, 100000000 do hoard[i] = i end
+                end,
+            },
+        }
+        spinning_run = {
+            script = { run = function(world, this) while true do end end },
+        }
+        spinning_coroutine = {
+            script = { on_tick = function() coroutine.wrap(function() while true do end end)() end },
+        }
+        repeating = {
+            script = { on_tick = function() local s = string.rep("x", 1 << 40) end },
+        }
+        substituting = {
+            script = { on_tick = function() local s = string.gsub(string.rep("x", 1000), "x", string.rep("%0", 1000)) end },
+        }
+        working = {
+            script = { on_tick = function(world, this, dt) ticks = ticks + 1 end },
+        }
+    "#);
+    for name in &["spinning", "hoarding", "spinning_run", "spinning_coroutine", "repeating", "substituting", "working"] {
+        spawn(&mut game, name);
+    }
+
+    let mut errors = Vec::new();
+    for _ in 0..2 {
+        game.tick();
+        errors.extend(game.world.read_resource::<res::ScriptErrors>().errors.iter().cloned());
+    }
+
+    assert_eq!(errors.len(), 6);
+    assert_eq!(errors.iter().filter(|x| x.message.contains("Exceeded the instruction budget")).count(), 3);
+    assert_eq!(errors.iter().filter(|x| x.message.contains("Exceeded the memory limit")).count(), 3);
+
+    // The working callback keeps its whole budget every tick.
+    assert_eq!(mutex.lock().unwrap().lua.globals().get::<_, i32>("ticks").unwrap(), 2);
+}
+
+#[test]
+fn limits_per_source() {
+    use ::game::GameBuilder;
+    use ::resource as res;
+    use std::{env, fs};
+    use std::fs::File;
+    use std::io::Write;
+
+    let root = env::temp_dir().join(format!("tally_ho_sandbox_source_test_{}", ::std::process::id()));
+    fs::create_dir_all(&root).unwrap();
+    let game_path = root.join("game.lua").to_str().unwrap().to_string();
+    let mod_path = root.join("mod.lua").to_str().unwrap().to_string();
+
+    File::create(&game_path).unwrap().write_all(br#"
+        ticks, unrestricted = 0, false
+        function busy_work()
+            local n = 0
+            for i = 1, 300000 do n = n + i end
+            return n
+        end
+
+        prefab("game_entity", {
+            script = {
+                on_tick = function(world, this, dt)
+                    busy_work()
+                    local hoard = string.rep("x", 4 * 1024 * 1024)
+                    unrestricted = io ~= nil and os.execute ~= nil and load ~= nil
+                    ticks = ticks + 1
+                end,
+            },
+        })
+    "#).unwrap();
+
+    File::create(&mod_path).unwrap().write_all(br#"
+        mod_ticks = 0
+        mod_sees = {
+            io = io ~= nil, execute = os.execute ~= nil, load = load ~= nil, global_io = _G.io ~= nil,
+            dump = string.dump ~= nil, env_meta = type(getmetatable(_ENV)) == "table",
+        }
+
+        prefab("spinning", { script = { on_tick = function() while true do end end } })
+        prefab("calling", { script = { on_tick = function() busy_work() end } })
+        prefab("wrapping", { script = { on_tick = function() coroutine.wrap(busy_work)() end } })
+        prefab("repeating", { script = { on_tick = function() local s = string.rep("x", 4 * 1024 * 1024) end } })
+        prefab("counting", { script = { on_tick = function() mod_ticks = mod_ticks + 1 end } })
+    "#).unwrap();
+
+    let mod_config = ScriptConfig {
+        instruction_budget: Some(100_000),
+        memory_limit: Some(1024 * 1024),
+        ..ScriptConfig::sandboxed()
+    };
+
+    let mut builder = GameBuilder::new(1.0/60.0)
+        .with_max_script_failures(Some(1))
+        .with_script(&game_path)
+        .with_script_using(&mod_path, mod_config);
+    for name in &["game_entity", "spinning", "calling", "wrapping", "repeating", "counting"] {
+        builder = builder.with_entity(name);
+    }
+    let mut game = builder.build().unwrap();
+    let mutex = game.world.read_resource::<res::Script>().0.clone().unwrap();
+
+    let mut errors = Vec::new();
+    for _ in 0..2 {
+        game.tick();
+        errors.extend(game.world.read_resource::<res::ScriptErrors>().errors.iter().cloned());
+    }
+
+    // Only the mod's callbacks (even the ones calling the game's functions) are held to its limits.
+    assert_eq!(errors.len(), 4);
+    assert_eq!(errors.iter().filter(|x| x.message.contains("Exceeded the instruction budget")).count(), 3);
+    assert_eq!(errors.iter().filter(|x| x.message.contains("Exceeded the memory limit")).count(), 1);
+
+    let script = mutex.lock().unwrap();
+    let globals = script.lua.globals();
+    assert_eq!(globals.get::<_, i32>("ticks").unwrap(), 2);
+    assert_eq!(globals.get::<_, i32>("mod_ticks").unwrap(), 2);
+    assert!(globals.get::<_, bool>("unrestricted").unwrap());
+
+    // The mod stays sandboxed when it's reloaded.
+    for reloaded in 0..2 {
+        if reloaded == 1 {
+            script.reload_file(&mod_path).unwrap();
+        }
+
+        let seen = globals.get::<_, Table>("mod_sees").unwrap();
+        for name in &["io", "execute", "load", "global_io", "dump", "env_meta"] {
+            assert!(!seen.get::<_, bool>(*name).unwrap(), "The mod sees '{}'", name);
+        }
+    }
+
+    fs::remove_dir_all(&root).unwrap();
+}
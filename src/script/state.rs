@@ -0,0 +1,216 @@
+use ::parse::{SavedValue, MAX_STATE_DEPTH};
+use script::sandbox;
+
+use std::str;
+
+use rlua::{Lua, Table, Value as LuaValue, Function as LuaFunction, Error as LuaError, Result as LuaResult, 
+    String as LuaString};
+
+/* NOTE:
+    Every scripted entity has a table of its own (for health, cooldowns, AI state, etc.), given to its callbacks after
+    their other arguments, e.g. 'on_tick(world, this, dt, state)' and 'run(world, this, dt, state)'. It's whatever the
+    behavior's 'init' function returns if it has one, otherwise a copy of the behavior's 'state' table (so entities
+    spawned from the same prefab don't share one). Snapshots only keep the booleans, numbers, strings and tables in it.
+*/
+pub fn new_state<'lua>(behavior: &Table<'lua>, lua: &'lua Lua) -> LuaResult<Table<'lua>> {
+    if let Some(init) = behavior.get::<_, Option<LuaFunction>>("init")? {
+        // Limited like the entity's other callbacks.
+        sandbox::enter(lua, &LuaValue::Function(init.clone()))?;
+        let state = init.call::<_, Option<Table>>(());
+        sandbox::reset_budget(lua)?;
+
+        return match state? {
+            Some(state) => Ok(state),
+            None => lua.create_table(),
+        };
+    }
+
+    match behavior.get::<_, Option<Table>>("state")? {
+        Some(state) => copy_table(lua, state, 0),
+        None => lua.create_table(),
+    }
+}
+
+fn too_deep() -> LuaError {
+    LuaError::RuntimeError(format!("State tables can't be nested more than {} deep (is there a cycle?)", MAX_STATE_DEPTH))
+}
+
+fn cyclic() -> LuaError {
+    LuaError::RuntimeError("State tables can't contain themselves to be saved".into())
+}
+
+fn copy_table<'lua>(lua: &'lua Lua, table: Table<'lua>, depth: u32) -> LuaResult<Table<'lua>> {
+    if depth > MAX_STATE_DEPTH {
+        return Err(too_deep());
+    }
+
+    let copy = lua.create_table()?;
+
+    for pair in table.pairs::<LuaValue, LuaValue>() {
+        let (k, v) = pair?;
+
+        let v = match v {
+            LuaValue::Table(t) => LuaValue::Table(copy_table(lua, t, depth + 1)?),
+            v => v,
+        };
+        copy.set(k, v)?;
+    }
+
+    Ok(copy)
+}
+
+// The parts of a value a snapshot is able to hold (None if there are none).
+pub fn save_value<'lua>(lua: &'lua Lua, value: LuaValue<'lua>) -> LuaResult<Option<SavedValue>> {
+    // The tables being saved (keyed by the tables themselves), to catch one containing itself.
+    let saving = lua.create_table()?;
+    save_value_at(value, &saving, 0)
+}
+
+fn save_value_at<'lua>(value: LuaValue<'lua>, saving: &Table<'lua>, depth: u32) -> LuaResult<Option<SavedValue>> {
+    match value {
+        LuaValue::Boolean(b) => Ok(Some(SavedValue::Boolean(b))),
+        LuaValue::Integer(i) => Ok(Some(SavedValue::Integer(i))),
+        LuaValue::Number(n) => Ok(Some(SavedValue::Number(n))),
+        LuaValue::String(s) => Ok(Some(SavedValue::String(s.as_bytes().to_vec()))),
+        LuaValue::Table(t) => {
+            if saving.raw_get::<_, bool>(t.clone())? {
+                return Err(cyclic());
+            }
+            if depth > MAX_STATE_DEPTH {
+                return Err(too_deep());
+            }
+            saving.raw_set(t.clone(), true)?;
+
+            let mut pairs = Vec::new();
+            for pair in t.clone().pairs::<LuaValue, LuaValue>() {
+                let (k, v) = pair?;
+
+                if let (Some(k), Some(v)) = (save_value_at(k, saving, depth + 1)?, save_value_at(v, saving, depth + 1)?) {
+                    pairs.push((k, v));
+                }
+            }
+
+            // Only the tables it's nested in count, the same table is able to be in a state more than once.
+            saving.raw_set(t, LuaValue::Nil)?;
+
+            Ok(Some(SavedValue::Table(pairs)))
+        },
+        // Functions, userdata, etc. only exist while the script is running.
+        _ => Ok(None),
+    }
+}
+
+pub fn load_value<'lua>(lua: &'lua Lua, value: &SavedValue) -> LuaResult<LuaValue<'lua>> {
+    match *value {
+        SavedValue::Boolean(b) => Ok(LuaValue::Boolean(b)),
+        SavedValue::Integer(i) => Ok(LuaValue::Integer(i)),
+        SavedValue::Number(n) => Ok(LuaValue::Number(n)),
+        SavedValue::String(ref s) => Ok(LuaValue::String(create_string(lua, s)?)),
+        SavedValue::Table(ref pairs) => {
+            let t = lua.create_table()?;
+            for &(ref k, ref v) in pairs {
+                t.set(load_value(lua, k)?, load_value(lua, v)?)?;
+            }
+
+            Ok(LuaValue::Table(t))
+        },
+    }
+}
+
+// Keeps Lua's own functions aside for putting strings together from bytes (see 'create_string').
+pub fn add_string_fn(lua: &Lua) -> LuaResult<()> {
+    let from_bytes: LuaFunction = lua.load(r#"
+        local char, concat, unpack, min = string.char, table.concat, table.unpack, math.min
+
+        return function(bytes)
+            local parts = {}
+            for i = 1, #bytes, 4096 do
+                parts[#parts + 1] = char(unpack(bytes, i, min(i + 4095, #bytes)))
+            end
+
+            return concat(parts)
+        end
+    "#, Some("state"))?.call(())?;
+
+    lua.set_named_registry_value("string_from_bytes", from_bytes)
+}
+
+// rlua is only able to create strings from UTF-8, so any other bytes are put together by Lua.
+fn create_string<'lua>(lua: &'lua Lua, bytes: &[u8]) -> LuaResult<LuaString<'lua>> {
+    if let Ok(s) = str::from_utf8(bytes) {
+        return lua.create_string(s);
+    }
+
+    let from_bytes: LuaFunction = lua.named_registry_value("string_from_bytes")?;
+    let bytes = lua.create_sequence_from(bytes.iter().cloned())?;
+
+    // Not a callback, so it shouldn't be cut short by whatever budget the last one left.
+    sandbox::reset_budget(lua)?;
+    from_bytes.call(bytes)
+}
+
+#[test]
+fn entity_state() {
+    use ::component as comp;
+    use ::script::{scripted_game, spawn};
+
+    let (mut game, mutex) = scripted_game(r#"
+        counter = {
+            script = {
+                state = { count = 0, nested = { hits = 0 } },
+                on_tick = function(world, this, dt, state) state.count = state.count + 1 end,
+            },
+        }
+        initialised = {
+            script = {
+                init = function() return { name = "from init" } end,
+                on_tick = function(world, this, dt, state) seen_name = state.name end,
+            },
+        }
+        runner = {
+            script = {
+                state = { runs = 0 },
+                run = function(world, this, dt, state)
+                    while true do
+                        state.runs = state.runs + 1
+                        run_dt = dt
+                        coroutine.yield()
+                    end
+                end,
+            },
+        }
+        bumper = {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            collider = {
+                shape_type = "aabb",
+                shape = { min_x = 0.0, min_y = 0.0, min_z = 0.0, max_x = 0.1, max_y = 0.1, max_z = 0.1 },
+                sweep = false,
+                on_collide = function(world, this, other, state) state.bumped = true end,
+            },
+            script = {},
+        }
+    "#);
+    let first = spawn(&mut game, "counter");
+    let second = spawn(&mut game, "counter");
+    spawn(&mut game, "initialised");
+    spawn(&mut game, "runner");
+    let bumper = spawn(&mut game, "bumper");
+    spawn(&mut game, "bumper");
+
+    for _ in 0..2 {
+        game.tick();
+    }
+
+    let script = mutex.lock().unwrap();
+    let behav = game.world.read_storage::<comp::ScriptBehavior>();
+    let state = |e| script.registry_value::<Table>(behav.get(e).unwrap().state.as_ref().unwrap()).unwrap();
+
+    // Entities from the same prefab get their own copies.
+    state(first).get::<_, Table>("nested").unwrap().set("hits", 5).unwrap();
+    assert_eq!(state(first).get::<_, i32>("count").unwrap(), 2);
+    assert_eq!(state(second).get::<_, i32>("count").unwrap(), 2);
+    assert_eq!(state(second).get::<_, Table>("nested").unwrap().get::<_, i32>("hits").unwrap(), 0);
+    assert_eq!(script.globals().get::<_, String>("seen_name").unwrap(), "from init");
+    assert!((script.globals().get::<_, f32>("run_dt").unwrap() - 1.0/60.0).abs() < 1e-6);
+    assert!(state(bumper).get::<_, bool>("bumped").unwrap());
+}
@@ -1,5 +1,5 @@
 use cgmath;
-use rlua::{UserData, UserDataMethods, MetaMethod, Lua, Result as LuaResult};
+use rlua::{UserData, UserDataMethods, MetaMethod, Lua, Result as LuaResult, Error as LuaError};
 
 pub trait LuaCtor {
     fn add_ctors(lua: &Lua);
@@ -26,6 +26,14 @@ impl UserData for Vector2f {
         methods.add_meta_method_mut(MetaMethod::Mul, |_, this, mul: f32| -> LuaResult<Self> {
             Ok(Vector2f(this.0 * mul))
         });
+
+        methods.add_meta_method(MetaMethod::Index, |_, this, field: String| -> LuaResult<f32> {
+            match field.as_ref() {
+                "x" => Ok(this.0.x),
+                "y" => Ok(this.0.y),
+                _ => Err(LuaError::RuntimeError(format!("There's no field named '{}'", field))),
+            }
+        });
     }
 }
 
@@ -61,6 +69,15 @@ impl UserData for Vector3f {
         methods.add_meta_method_mut(MetaMethod::Mul, |_, this, mul: f32| -> LuaResult<Self> {
             Ok(Vector3f(this.0 * mul))
         });
+
+        methods.add_meta_method(MetaMethod::Index, |_, this, field: String| -> LuaResult<f32> {
+            match field.as_ref() {
+                "x" => Ok(this.0.x),
+                "y" => Ok(this.0.y),
+                "z" => Ok(this.0.z),
+                _ => Err(LuaError::RuntimeError(format!("There's no field named '{}'", field))),
+            }
+        });
     }
 }
 
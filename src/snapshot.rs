@@ -0,0 +1,844 @@
+use ::component as comp;
+use ::parse::{self, SavedComponent, SavedValue, ComponentTag, SNAPSHOT_MAGIC, SNAPSHOT_VERSION, OLDEST_SNAPSHOT_VERSION};
+use ::utility::{Rect2, Rect3};
+#[cfg(feature = "lua")]
+use ::resource as res;
+#[cfg(feature = "lua")]
+use ::script::{Script, ScriptError, ComponentFields};
+#[cfg(feature = "lua")]
+use ::script::state::{save_value, load_value};
+
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::Error as IoError;
+use std::fs::File;
+
+use cgmath::{Vector2, Vector3};
+use specs;
+#[cfg(feature = "lua")]
+use rlua::Value as LuaValue;
+
+/* NOTE:
+    A snapshot holds every entity with a component worth saving (see 'parse::SavedComponent'), written big-endian
+    like tile maps. Loading spawns the saved entities into the world (meant to be a fresh one), and everything derived
+    from them (GPU buffers, the broad phase, tile colliders, etc.) is rebuilt by the systems seeing them inserted. Lua
+    callbacks can't be saved, so they're bound again from the entity's prefab. Their state tables are only saved by
+    'save_with_script', given the (locked) script they live in.
+*/
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    IoError(IoError),
+    // The snapshot couldn't be parsed (or references an entity it doesn't have).
+    Invalid(String),
+    UnsupportedVersion(u16),
+    #[cfg(feature = "lua")]
+    ScriptError(ScriptError),
+}
+
+impl From<IoError> for SnapshotError {
+    fn from(error: IoError) -> Self {
+        SnapshotError::IoError(error)
+    }
+}
+
+#[cfg(feature = "lua")]
+impl From<ScriptError> for SnapshotError {
+    fn from(error: ScriptError) -> Self {
+        SnapshotError::ScriptError(error)
+    }
+}
+
+pub type SnapshotResult<T> = Result<T, SnapshotError>;
+
+// Makes sure there's a storage for every saved component (systems only register the ones they use).
+fn register_components(world: &mut specs::World) {
+    world.register::<comp::Transform>();
+    world.register::<comp::LocalTransform>();
+    world.register::<comp::Parent>();
+    world.register::<comp::Velocity>();
+    world.register::<comp::Collider>();
+    world.register::<comp::Sprite>();
+    world.register::<comp::TileMap>();
+    world.register::<comp::RenderStrip>();
+    world.register::<comp::CollisionStrip>();
+    world.register::<comp::Prefab>();
+    #[cfg(feature = "lua")]
+    world.register::<comp::ScriptBehavior>();
+}
+
+// Saves the entities without their Lua state (see 'save_with_script').
+pub fn save(world: &mut specs::World) -> Vec<u8> {
+    save_entities(world, &HashMap::new())
+}
+
+// Saves the entities along with their Lua state tables, failing if one of them can't be saved (e.g. it contains 
+// itself).
+#[cfg(feature = "lua")]
+pub fn save_with_script(world: &mut specs::World, script: &Script) -> SnapshotResult<Vec<u8>> {
+    register_components(world);
+
+    let states = save_states(world, script)?;
+    Ok(save_entities(world, &states))
+}
+
+fn save_entities(world: &mut specs::World, states: &HashMap<specs::Entity, SavedValue>) -> Vec<u8> {
+    use specs::Join;
+
+    register_components(world);
+
+    let (ents, tran, local, parent, vel, coll, sprite, map, rndr_str, coll_str, prefab): (
+        specs::Entities,
+        specs::ReadStorage<comp::Transform>,
+        specs::ReadStorage<comp::LocalTransform>,
+        specs::ReadStorage<comp::Parent>,
+        specs::ReadStorage<comp::Velocity>,
+        specs::ReadStorage<comp::Collider>,
+        specs::ReadStorage<comp::Sprite>,
+        specs::ReadStorage<comp::TileMap>,
+        specs::ReadStorage<comp::RenderStrip>,
+        specs::ReadStorage<comp::CollisionStrip>,
+        specs::ReadStorage<comp::Prefab>,
+    ) = specs::SystemData::fetch(&world.res);
+
+    // The colliders of collision strips are recreated from the strips, so they aren't saved.
+    let mut strip_colliders = specs::BitSet::new();
+    for strip in (&coll_str).join() {
+        for e in strip.colliders.iter() {
+            strip_colliders.add(e.id());
+        }
+    }
+
+    let saved: Vec<specs::Entity> = (&*ents).join()
+        .filter(|e| !strip_colliders.contains(e.id()))
+        .filter(|e|
+            tran.get(*e).is_some() || local.get(*e).is_some() || parent.get(*e).is_some() || vel.get(*e).is_some() ||
+            coll.get(*e).is_some() || sprite.get(*e).is_some() || map.get(*e).is_some() || rndr_str.get(*e).is_some() ||
+            coll_str.get(*e).is_some() || prefab.get(*e).is_some()
+        )
+    .collect();
+
+    let indices: HashMap<specs::Entity, u32> = saved.iter().enumerate().map(|(idx, e)| (*e, idx as u32)).collect();
+
+    let mut buf = Vec::new();
+    buf.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u16(&mut buf, SNAPSHOT_VERSION);
+    write_u32(&mut buf, saved.len() as u32);
+
+    for e in saved.iter() {
+        let e = *e;
+
+        write_str(&mut buf, prefab.get(e).map(|x| x.name.as_str()).unwrap_or(""));
+
+        let mut comps = Vec::new();
+        let mut count = 0;
+
+        if let Some(tran) = tran.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::Transform as u8);
+            write_vector3(&mut comps, tran.pos);
+            write_f32(&mut comps, tran.rotation);
+            write_vector2(&mut comps, tran.scale);
+        }
+
+        if let Some(local) = local.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::LocalTransform as u8);
+            write_vector3(&mut comps, local.pos);
+            write_f32(&mut comps, local.rotation);
+            write_vector2(&mut comps, local.scale);
+        }
+
+        // A parent that isn't saved can't be referenced, so the entity is left without one.
+        if let Some((parent, cascade)) = parent.get(e).and_then(|x| indices.get(&x.entity).map(|idx| (*idx, x.cascade))) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::Parent as u8);
+            write_u32(&mut comps, parent);
+            write_u8(&mut comps, cascade as u8);
+        }
+
+        if let Some(vel) = vel.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::Velocity as u8);
+            write_vector3(&mut comps, vel.pos);
+        }
+
+        if let Some(coll) = coll.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::Collider as u8);
+            write_shape(&mut comps, &coll.shape);
+            write_u8(&mut comps, coll.sweep as u8);
+            write_opt_vector3(&mut comps, coll.one_way);
+        }
+
+        if let Some(sprite) = sprite.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::Sprite as u8);
+            write_rect2(&mut comps, sprite.bounds);
+            write_rect2(&mut comps, sprite.uv);
+            write_u32(&mut comps, sprite.image_index);
+        }
+
+        if let Some(map) = map.get(e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::TileMap as u8);
+            write_vector3(&mut comps, map.tile_dims());
+            write_u32(&mut comps, map.tex_dims().x);
+            write_u32(&mut comps, map.tex_dims().y);
+            write_u32(&mut comps, map.image_index());
+            write_u8(&mut comps, map.grid_collision as u8);
+        }
+
+        // Strips whose tile map is gone (and so isn't saved) are left out.
+        if let Some((strip, &map)) = rndr_str.get(e).and_then(|x| indices.get(&x.tile_map()).map(|idx| (x, idx))) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::RenderStrip as u8);
+            write_u32(&mut comps, map);
+            write_vector3_u32(&mut comps, strip.pos());
+            for uv in strip.uvs().iter() {
+                match *uv {
+                    Some(uv) => {
+                        write_u8(&mut comps, 1);
+                        write_rect2(&mut comps, uv);
+                    },
+                    None => write_u8(&mut comps, 0),
+                }
+            }
+        }
+
+        if let Some((strip, &map)) = coll_str.get(e).and_then(|x| indices.get(&x.tile_map()).map(|idx| (x, idx))) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::CollisionStrip as u8);
+            write_u32(&mut comps, map);
+            write_vector3_u32(&mut comps, strip.pos());
+            for blocking in strip.blocking.iter() {
+                write_u8(&mut comps, *blocking as u8);
+            }
+            for one_way in strip.one_way.iter() {
+                write_opt_vector3(&mut comps, *one_way);
+            }
+            for shape in strip.shapes.iter() {
+                write_tile_shape(&mut comps, *shape);
+            }
+        }
+
+        if let Some(state) = states.get(&e) {
+            count += 1;
+            write_u8(&mut comps, ComponentTag::ScriptState as u8);
+            write_saved_value(&mut comps, state);
+        }
+
+        write_u8(&mut buf, count);
+        buf.extend(comps);
+    }
+
+    buf
+}
+
+// The entities' Lua state tables, as far as they can be saved.
+#[cfg(feature = "lua")]
+fn save_states(world: &specs::World, script: &Script) -> SnapshotResult<HashMap<specs::Entity, SavedValue>> {
+    use specs::Join;
+
+    let mut states = HashMap::new();
+
+    let (ents, behav): (specs::Entities, specs::ReadStorage<comp::ScriptBehavior>) = specs::SystemData::fetch(&world.res);
+    for (e, behav) in (&*ents, &behav).join() {
+        if let Some(state) = ::system::script::state(script, behav) {
+            if let Some(saved) = save_value(script, LuaValue::Table(state)).map_err(ScriptError::from)? {
+                states.insert(e, saved);
+            }
+        }
+    }
+
+    Ok(states)
+}
+
+pub fn save_file(world: &mut specs::World, path: &str) -> SnapshotResult<()> {
+    let mut file = File::create(path)?;
+    file.write_all(&save(world))?;
+
+    Ok(())
+}
+
+#[cfg(feature = "lua")]
+pub fn save_file_with_script(world: &mut specs::World, script: &Script, path: &str) -> SnapshotResult<()> {
+    let bytes = save_with_script(world, script)?;
+    File::create(path)?.write_all(&bytes)?;
+
+    Ok(())
+}
+
+// Spawns the snapshot's entities, returning them in the order they were saved.
+pub fn load(world: &mut specs::World, bytes: &[u8]) -> SnapshotResult<Vec<specs::Entity>> {
+    use specs::Builder;
+
+    let (rest, version) = parse::snapshot_header(bytes)
+        .map_err(|_| SnapshotError::Invalid("Not a snapshot".into()))?;
+
+    if version < OLDEST_SNAPSHOT_VERSION || version > SNAPSHOT_VERSION {
+        return Err(SnapshotError::UnsupportedVersion(version));
+    }
+
+    let saved = parse::saved_entities(rest, version)
+        .map_err(|err| SnapshotError::Invalid(format!("{:?}", err)))?.1;
+
+    register_components(world);
+
+    // Every entity is created up front, so components are able to reference any of them.
+    let ents: Vec<specs::Entity> = saved.iter().map(|_| world.create_entity().build()).collect();
+    let entity = |idx: u32| ents.get(idx as usize).cloned()
+        .ok_or_else(|| SnapshotError::Invalid(format!("No entity {} in the snapshot", idx)));
+
+    // Restored once the entities' behaviors are bound.
+    #[cfg(feature = "lua")]
+    let mut states = Vec::new();
+
+    for (saved, &e) in saved.into_iter().zip(ents.iter()) {
+        if let Some(name) = saved.prefab {
+            world.write_storage().insert(e, comp::Prefab::new(&name)).unwrap();
+        }
+
+        for saved_comp in saved.components {
+            match saved_comp {
+                SavedComponent::Transform { pos, rotation, scale } => {
+                    let mut tran = comp::Transform::new(pos);
+                    tran.rotation = rotation;
+                    tran.scale = scale;
+
+                    world.write_storage().insert(e, tran).unwrap();
+                },
+                SavedComponent::LocalTransform { pos, rotation, scale } => {
+                    let mut local = comp::LocalTransform::new(pos);
+                    local.rotation = rotation;
+                    local.scale = scale;
+
+                    world.write_storage().insert(e, local).unwrap();
+                },
+                SavedComponent::Parent { entity: parent, cascade } => {
+                    world.write_storage().insert(e, comp::Parent::new(entity(parent)?, cascade)).unwrap();
+                },
+                SavedComponent::Velocity(pos) => {
+                    world.write_storage().insert(e, comp::Velocity { pos }).unwrap();
+                },
+                SavedComponent::Collider { shape, sweep, one_way } => {
+                    let mut coll = comp::Collider::new(shape, sweep);
+                    coll.one_way = one_way;
+
+                    world.write_storage().insert(e, coll).unwrap();
+                },
+                SavedComponent::Sprite { bounds, uv, image_index } => {
+                    world.write_storage().insert(e, comp::Sprite::new(bounds, uv, image_index)).unwrap();
+                },
+                SavedComponent::TileMap { tile_dims, tex_dims, image_index, grid_collision } => {
+                    // The map's strips are saved as they are, so there's nothing to load.
+                    let mut map = comp::TileMap::new(tile_dims, tex_dims, image_index, None);
+                    map.grid_collision = grid_collision;
+
+                    world.write_storage().insert(e, map).unwrap();
+                },
+                SavedComponent::RenderStrip { tile_map, pos, uvs } => {
+                    let mut strip_uvs = [None; comp::tilemap::STRIP_LENGTH];
+                    strip_uvs.copy_from_slice(&uvs[..]);
+
+                    world.write_storage().insert(e, comp::RenderStrip::new(entity(tile_map)?, pos, strip_uvs)).unwrap();
+                },
+                SavedComponent::CollisionStrip { tile_map, pos, blocking, one_way, shapes } => {
+                    let mut strip_blocking = [false; comp::tilemap::STRIP_LENGTH];
+                    strip_blocking.copy_from_slice(&blocking[..]);
+
+                    let mut strip = comp::CollisionStrip::new(entity(tile_map)?, pos, strip_blocking);
+                    strip.one_way.copy_from_slice(&one_way[..]);
+                    strip.shapes.copy_from_slice(&shapes[..]);
+
+                    world.write_storage().insert(e, strip).unwrap();
+                },
+                #[cfg(feature = "lua")]
+                SavedComponent::ScriptState(state) => states.push((e, state)),
+                #[cfg(not(feature = "lua"))]
+                SavedComponent::ScriptState(_) => (),
+            }
+        }
+    }
+
+    #[cfg(feature = "lua")]
+    bind_callbacks(world, &ents, &states)?;
+
+    Ok(ents)
+}
+
+pub fn load_file(world: &mut specs::World, path: &str) -> SnapshotResult<Vec<specs::Entity>> {
+    let mut bytes = Vec::new();
+    File::open(path)?.read_to_end(&mut bytes)?;
+
+    load(world, &bytes)
+}
+
+// Binds the Lua callbacks of the loaded entities from their prefabs, then gives them back their saved state.
+#[cfg(feature = "lua")]
+fn bind_callbacks(world: &mut specs::World, ents: &[specs::Entity], states: &[(specs::Entity, SavedValue)]) -> SnapshotResult<()> {
+    let mutex = match world.res.try_fetch::<res::Script>().and_then(|x| x.0.clone()) {
+        Some(mutex) => mutex,
+        // Nothing to bind to without a script.
+        None => return Ok(()),
+    };
+    let script = mutex.lock().unwrap();
+
+    ::script::Script::register_components(world);
+
+    for &e in ents {
+        let name = match world.read_storage::<comp::Prefab>().get(e) {
+            Some(prefab) => prefab.name.clone(),
+            None => continue,
+        };
+
+        script.bind_callbacks(&name, e, world)?;
+    }
+
+    for &(e, ref state) in states {
+        if let Some(behav) = world.write_storage::<comp::ScriptBehavior>().get_mut(e) {
+            load_value(&script, state)
+                .and_then(|state| behav.set_field("state", state, &script))
+                .map_err(ScriptError::from)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn write_u8(buf: &mut Vec<u8>, v: u8) {
+    buf.push(v);
+}
+
+fn write_u16(buf: &mut Vec<u8>, v: u16) {
+    buf.push((v >> 8) as u8);
+    buf.push(v as u8);
+}
+
+fn write_u32(buf: &mut Vec<u8>, v: u32) {
+    buf.push((v >> 24) as u8);
+    buf.push((v >> 16) as u8);
+    buf.push((v >> 8) as u8);
+    buf.push(v as u8);
+}
+
+fn write_f32(buf: &mut Vec<u8>, v: f32) {
+    write_u32(buf, v.to_bits());
+}
+
+fn write_u64(buf: &mut Vec<u8>, v: u64) {
+    write_u32(buf, (v >> 32) as u32);
+    write_u32(buf, v as u32);
+}
+
+fn write_str(buf: &mut Vec<u8>, v: &str) {
+    write_u32(buf, v.len() as u32);
+    buf.extend_from_slice(v.as_bytes());
+}
+
+fn write_vector2(buf: &mut Vec<u8>, v: Vector2<f32>) {
+    write_f32(buf, v.x);
+    write_f32(buf, v.y);
+}
+
+fn write_vector3(buf: &mut Vec<u8>, v: Vector3<f32>) {
+    write_f32(buf, v.x);
+    write_f32(buf, v.y);
+    write_f32(buf, v.z);
+}
+
+fn write_vector3_u32(buf: &mut Vec<u8>, v: Vector3<u32>) {
+    write_u32(buf, v.x);
+    write_u32(buf, v.y);
+    write_u32(buf, v.z);
+}
+
+fn write_opt_vector3(buf: &mut Vec<u8>, v: Option<Vector3<f32>>) {
+    match v {
+        Some(v) => {
+            write_u8(buf, 1);
+            write_vector3(buf, v);
+        },
+        None => write_u8(buf, 0),
+    }
+}
+
+fn write_rect2(buf: &mut Vec<u8>, v: Rect2<f32>) {
+    write_vector2(buf, v.min);
+    write_vector2(buf, v.max);
+}
+
+fn write_rect3(buf: &mut Vec<u8>, v: Rect3<f32>) {
+    write_vector3(buf, v.min);
+    write_vector3(buf, v.max);
+}
+
+fn write_saved_value(buf: &mut Vec<u8>, v: &SavedValue) {
+    match *v {
+        SavedValue::Boolean(b) => {
+            write_u8(buf, 0);
+            write_u8(buf, b as u8);
+        },
+        SavedValue::Integer(i) => {
+            write_u8(buf, 1);
+            write_u64(buf, i as u64);
+        },
+        SavedValue::Number(n) => {
+            write_u8(buf, 2);
+            write_u64(buf, n.to_bits());
+        },
+        SavedValue::String(ref s) => {
+            write_u8(buf, 3);
+            write_u32(buf, s.len() as u32);
+            buf.extend_from_slice(s);
+        },
+        SavedValue::Table(ref pairs) => {
+            write_u8(buf, 4);
+            write_u32(buf, pairs.len() as u32);
+            for &(ref k, ref v) in pairs {
+                write_saved_value(buf, k);
+                write_saved_value(buf, v);
+            }
+        },
+    }
+}
+
+fn write_shape(buf: &mut Vec<u8>, shape: &comp::collider::Shape) {
+    use comp::collider::Shape;
+
+    match *shape {
+        Shape::AABB(rect) => {
+            write_u8(buf, 0);
+            write_rect3(buf, rect);
+        },
+        Shape::Circle { offset, radius, ref depth } => {
+            write_u8(buf, 1);
+            write_vector2(buf, offset);
+            write_f32(buf, radius);
+            write_f32(buf, depth.start);
+            write_f32(buf, depth.end);
+        },
+        Shape::Slope { rect, left, right } => {
+            write_u8(buf, 2);
+            write_rect3(buf, rect);
+            write_f32(buf, left);
+            write_f32(buf, right);
+        },
+        Shape::Polygon { ref points, ref depth } => {
+            write_u8(buf, 3);
+            write_u16(buf, points.len() as u16);
+            for p in points.iter() {
+                write_vector2(buf, *p);
+            }
+            write_f32(buf, depth.start);
+            write_f32(buf, depth.end);
+        },
+    }
+}
+
+fn write_tile_shape(buf: &mut Vec<u8>, shape: Option<comp::tilemap::TileShape>) {
+    use comp::tilemap::TileShape;
+
+    match shape {
+        None => write_u8(buf, 0),
+        Some(TileShape::Full) => write_u8(buf, 1),
+        Some(TileShape::Half) => write_u8(buf, 2),
+        Some(TileShape::Slope(left, right)) => {
+            write_u8(buf, 3);
+            write_f32(buf, left);
+            write_f32(buf, right);
+        },
+    }
+}
+
+#[test]
+fn save_and_load() {
+    use specs::Builder;
+    use comp::tilemap::{STRIP_LENGTH, TileShape};
+    use cgmath::Zero;
+
+    let mut world = specs::World::new();
+    register_components(&mut world);
+
+    let mut tran = comp::Transform::new(Vector3::new(1.0, 2.0, 0.5));
+    tran.rotation = 0.25;
+    let mut coll = comp::Collider::new(
+        comp::collider::Shape::Circle { offset: Vector2::zero(), radius: 0.5, depth: 0.0..1.0 },
+        true
+    );
+    coll.one_way = Some(Vector3::new(0.0, -1.0, 0.0));
+
+    world.create_entity()
+        .with(tran)
+        .with(comp::Velocity { pos: Vector3::new(0.1, 0.0, 0.0) })
+        .with(coll)
+        .with(comp::Prefab::new("player"))
+    .build();
+
+    let map = world.create_entity()
+        .with(comp::TileMap::new(Vector3::new(0.1, 0.1, 0.1), Vector2::new(2, 2), 1, None))
+    .build();
+
+    let mut blocking = [false; STRIP_LENGTH];
+    blocking[3] = true;
+    let mut strip = comp::CollisionStrip::new(map, Vector3::new(0, 1, 0), blocking);
+    strip.shapes[4] = Some(TileShape::Slope(0.0, 0.5));
+
+    // A tile collider spawned for the strip, which is left out.
+    let tile = world.create_entity()
+        .with(comp::Transform::new(Vector3::zero()))
+    .build();
+    strip.colliders.push(tile);
+
+    world.create_entity()
+        .with(strip)
+    .build();
+
+    let bytes = save(&mut world);
+
+    let mut loaded = specs::World::new();
+    let ents = load(&mut loaded, &bytes).unwrap();
+    assert_eq!(ents.len(), 3);
+
+    let tran = loaded.read_storage::<comp::Transform>();
+    let coll = loaded.read_storage::<comp::Collider>();
+    let prefab = loaded.read_storage::<comp::Prefab>();
+    let map = loaded.read_storage::<comp::TileMap>();
+    let strip = loaded.read_storage::<comp::CollisionStrip>();
+
+    assert_eq!(tran.get(ents[0]).unwrap().pos, Vector3::new(1.0, 2.0, 0.5));
+    assert_eq!(tran.get(ents[0]).unwrap().rotation, 0.25);
+    assert_eq!(coll.get(ents[0]).unwrap().one_way, Some(Vector3::new(0.0, -1.0, 0.0)));
+    assert!(coll.get(ents[0]).unwrap().sweep);
+    assert_eq!(prefab.get(ents[0]).unwrap().name, "player");
+
+    assert_eq!(map.get(ents[1]).unwrap().tex_dims(), Vector2::new(2, 2));
+
+    let strip = strip.get(ents[2]).unwrap();
+    assert_eq!(strip.tile_map(), ents[1]);
+    assert_eq!(strip.blocking, blocking);
+    assert_eq!(strip.shapes[4], Some(TileShape::Slope(0.0, 0.5)));
+    // Recreated once the strip is seen by the tile map collision system.
+    assert!(strip.colliders.is_empty());
+}
+
+#[test]
+fn save_hierarchy() {
+    use specs::Builder;
+    use comp::tilemap::STRIP_LENGTH;
+    use cgmath::Zero;
+
+    let mut world = specs::World::new();
+    register_components(&mut world);
+
+    let parent = world.create_entity()
+        .with(comp::Transform::new(Vector3::new(1.0, 0.0, 0.0)))
+    .build();
+
+    let mut local = comp::LocalTransform::new(Vector3::new(0.5, 0.0, 0.0));
+    local.rotation = 0.5;
+    world.create_entity()
+        .with(comp::Transform::new(Vector3::zero()))
+        .with(local)
+        .with(comp::Parent::new(parent, true))
+    .build();
+
+    // A strip left behind by a deleted tile map.
+    let map = world.create_entity()
+        .with(comp::TileMap::new(Vector3::new(0.1, 0.1, 0.1), Vector2::new(2, 2), 1, None))
+    .build();
+    world.create_entity()
+        .with(comp::CollisionStrip::new(map, Vector3::zero(), [false; STRIP_LENGTH]))
+    .build();
+    world.delete_entity(map).unwrap();
+
+    let bytes = save(&mut world);
+
+    let mut loaded = specs::World::new();
+    let ents = load(&mut loaded, &bytes).unwrap();
+
+    let local = loaded.read_storage::<comp::LocalTransform>();
+    let parent = loaded.read_storage::<comp::Parent>();
+
+    assert_eq!(local.get(ents[1]).unwrap().pos, Vector3::new(0.5, 0.0, 0.0));
+    assert_eq!(local.get(ents[1]).unwrap().rotation, 0.5);
+    assert_eq!(parent.get(ents[1]).unwrap().entity, ents[0]);
+    assert!(parent.get(ents[1]).unwrap().cascade);
+    assert!(loaded.read_storage::<comp::CollisionStrip>().get(ents[2]).is_none());
+}
+
+#[test]
+fn unsupported_version() {
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u16(&mut bytes, SNAPSHOT_VERSION + 1);
+    write_u32(&mut bytes, 0);
+
+    match load(&mut specs::World::new(), &bytes) {
+        Err(SnapshotError::UnsupportedVersion(v)) => assert_eq!(v, SNAPSHOT_VERSION + 1),
+        _ => panic!("Loaded a snapshot with an unsupported version"),
+    }
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn bind_prefab_callbacks() {
+    use ::script::scripted_game;
+
+    let (mut game, mutex) = scripted_game(r#"
+        crate = {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            script = { on_tick = function(world, this, dt) end },
+        }
+    "#);
+
+    let bytes = {
+        let script = mutex.lock().unwrap();
+        script.parse_entity("crate", game.world.create_entity()).unwrap();
+        save(&mut game.world)
+    };
+
+    let mut loaded = specs::World::new();
+    loaded.add_resource(res::Script(Some(mutex.clone())));
+    let ents = load(&mut loaded, &bytes).unwrap();
+
+    assert!(loaded.read_storage::<comp::ScriptBehavior>().get(ents[0]).unwrap().on_tick.is_some());
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn save_script_state() {
+    use ::script::scripted_game;
+
+    let (mut game, mutex) = scripted_game(r#"
+        crate = {
+            transform = { position = { x = 0.0, y = 0.0, z = 0.0 } },
+            script = { state = { health = 3 } },
+        }
+    "#);
+
+    let bytes = {
+        let script = mutex.lock().unwrap();
+        let e = script.parse_entity("crate", game.world.create_entity()).unwrap();
+
+        let state: ::rlua::Table = script.lua.exec(r#"
+            local hits = { 4, 2 }
+            return {
+                health = 1, name = "battered", ratio = 0.5, hits = hits, last_hits = hits, [true] = "kept", skipped = print,
+                raw = "\xff\0a",
+            }
+        "#, None).unwrap();
+        game.world.write_storage::<comp::ScriptBehavior>().get_mut(e).unwrap()
+            .set_field("state", LuaValue::Table(state), &script).unwrap();
+
+        save_with_script(&mut game.world, &script).unwrap()
+    };
+
+    let mut loaded = specs::World::new();
+    loaded.add_resource(res::Script(Some(mutex.clone())));
+    let ents = load(&mut loaded, &bytes).unwrap();
+
+    let script = mutex.lock().unwrap();
+    let behav = loaded.read_storage::<comp::ScriptBehavior>();
+    let state = ::system::script::state(&script, behav.get(ents[0]).unwrap()).unwrap();
+
+    assert_eq!(state.get::<_, i64>("health").unwrap(), 1);
+    assert_eq!(state.get::<_, String>("name").unwrap(), "battered");
+    assert_eq!(state.get::<_, f64>("ratio").unwrap(), 0.5);
+    assert_eq!(state.get::<_, ::rlua::Table>("hits").unwrap().get::<_, i32>(2).unwrap(), 2);
+    assert_eq!(state.get::<_, ::rlua::Table>("last_hits").unwrap().get::<_, i32>(1).unwrap(), 4);
+    assert_eq!(state.get::<_, String>(true).unwrap(), "kept");
+    assert!(state.get::<_, Option<::rlua::Function>>("skipped").unwrap().is_none());
+    assert_eq!(state.get::<_, ::rlua::String>("raw").unwrap().as_bytes(), b"\xff\0a");
+}
+
+#[cfg(feature = "lua")]
+#[test]
+fn unsaveable_script_state() {
+    use ::script::Script;
+
+    let script = Script::new();
+    script.lua.exec::<()>(r#"
+        crate = { script = {} }
+    "#, None).unwrap();
+
+    let mut world = specs::World::new();
+    Script::register_components(&mut world);
+    let e = script.parse_entity("crate", world.create_entity()).unwrap();
+
+    let state: ::rlua::Table = script.lua.exec("local t = {} t.self = t return t", None).unwrap();
+    world.write_storage::<comp::ScriptBehavior>().get_mut(e).unwrap()
+        .set_field("state", LuaValue::Table(state), &script).unwrap();
+
+    match save_with_script(&mut world, &script) {
+        Err(SnapshotError::ScriptError(err)) => assert!(format!("{:?}", err).contains("contain themselves")),
+        _ => panic!("Saved a state table containing itself"),
+    }
+}
+
+#[test]
+fn load_short_prefab_names() {
+    // Version 2 snapshots only had a byte for the length of a prefab's name.
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u16(&mut bytes, 2);
+    write_u32(&mut bytes, 1);
+    write_u8(&mut bytes, 6);
+    bytes.extend_from_slice(b"player");
+    write_u8(&mut bytes, 1);
+    write_u8(&mut bytes, ComponentTag::Velocity as u8);
+    write_vector3(&mut bytes, Vector3::new(0.5, 0.0, 0.0));
+
+    let mut world = specs::World::new();
+    let ents = load(&mut world, &bytes).unwrap();
+    assert_eq!(world.read_storage::<comp::Prefab>().get(ents[0]).unwrap().name, "player");
+    assert_eq!(world.read_storage::<comp::Velocity>().get(ents[0]).unwrap().pos, Vector3::new(0.5, 0.0, 0.0));
+}
+
+#[test]
+fn load_older_and_nested_snapshots() {
+    // Laid out the way version 1 saved them: byte-long prefab name lengths, and no script state.
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u16(&mut bytes, 1);
+    write_u32(&mut bytes, 2);
+    write_u8(&mut bytes, 6);
+    bytes.extend_from_slice(b"player");
+    write_u8(&mut bytes, 1);
+    write_u8(&mut bytes, ComponentTag::Transform as u8);
+    write_vector3(&mut bytes, Vector3::new(1.0, 2.0, 0.0));
+    write_f32(&mut bytes, 0.25);
+    write_vector2(&mut bytes, Vector2::new(1.0, 1.0));
+    write_u8(&mut bytes, 0);
+    write_u8(&mut bytes, 0);
+
+    let mut world = specs::World::new();
+    let ents = load(&mut world, &bytes).unwrap();
+    assert_eq!(ents.len(), 2);
+    assert_eq!(world.read_storage::<comp::Prefab>().get(ents[0]).unwrap().name, "player");
+    assert_eq!(world.read_storage::<comp::Transform>().get(ents[0]).unwrap().pos, Vector3::new(1.0, 2.0, 0.0));
+    assert_eq!(world.read_storage::<comp::Transform>().get(ents[0]).unwrap().rotation, 0.25);
+    assert!(world.read_storage::<comp::Prefab>().get(ents[1]).is_none());
+
+    // A state nested deeper than it's ever saved is rejected, rather than parsed recursively.
+    let mut bytes = Vec::new();
+    bytes.extend_from_slice(SNAPSHOT_MAGIC);
+    write_u16(&mut bytes, SNAPSHOT_VERSION);
+    write_u32(&mut bytes, 1);
+    write_str(&mut bytes, "");
+    write_u8(&mut bytes, 1);
+    write_u8(&mut bytes, ComponentTag::ScriptState as u8);
+    for _ in 0..100 {
+        write_u8(&mut bytes, 4);
+        write_u32(&mut bytes, 1);
+        write_u8(&mut bytes, 0);
+        write_u8(&mut bytes, 1);
+    }
+    write_u8(&mut bytes, 0);
+    write_u8(&mut bytes, 1);
+
+    match load(&mut specs::World::new(), &bytes) {
+        Err(SnapshotError::Invalid(_)) => (),
+        _ => panic!("Loaded a state nested too deeply"),
+    }
+}
@@ -1,26 +1,61 @@
-use ::utility::{Rect2, Rect3, penetration_vector, sweep_aabb};
+use ::utility::{Rect2, Rect3, project_rect, penetration_vector, penetration_polygon, penetration_polygons, sweep_aabb, sweep_polygon, sweep_polygons};
 use ::collision as coll;
 use ::component as comp;
+#[cfg(feature = "lua")]
 use ::resource as res;
-use ::script::{LuaEntity, LuaWorld};
+#[cfg(feature = "lua")]
+use ::script::{LuaEntity, Script, with_world, sandbox};
+#[cfg(feature = "lua")]
+use ::script::types::Vector3f;
 use comp::collider::*;
 
 use std::f32;
 use std::rc::Rc;
 use std::cell::RefCell;
+#[cfg(not(feature = "lua"))]
+use std::marker::PhantomData;
 use std::collections::hash_map::*;
 
 use cgmath::{InnerSpace, ApproxEq, Vector2, Vector3, Zero};
 use specs;
-use rlua::{Function as LuaFunction, LightUserData, UserData, UserDataMethods, AnyUserData, Scope as LuaScope};
+#[cfg(feature = "lua")]
+use rlua::{Function as LuaFunction, Value as LuaValue, Error as LuaError, LightUserData, UserData, UserDataMethods, AnyUserData, Scope as LuaScope};
+
+// Lua pre-solve hooks need the script to run, and somewhere to report their errors (when there is scripting).
+#[cfg(feature = "lua")]
+type ScriptData<'a> = (specs::Read<'a, res::Script>, specs::Write<'a, res::ScriptErrors>);
+#[cfg(not(feature = "lua"))]
+type ScriptData<'a> = ();
+
+// What the colliders' pre-solve hooks need to run.
+struct PreSolveHooks<'s> {
+    // Lua hooks need the script to run (they're skipped without one).
+    #[cfg(feature = "lua")]
+    script: Option<&'s Script>,
+    // The entities of Lua hooks which failed and their errors, reported once the colliders are no longer being read.
+    #[cfg(feature = "lua")]
+    errors: Vec<(specs::Entity, LuaError)>,
+    // Without scripting there are only native hooks.
+    #[cfg(not(feature = "lua"))]
+    script: PhantomData<&'s ()>,
+}
 
 pub struct CollisionSystem {
     transform_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
     transform_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
+    transform_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
+    collider_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
+    collider_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
+    collider_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
     ins_transform: specs::BitSet,
     mod_transform: specs::BitSet,
+    ins_collider: specs::BitSet,
+    mod_collider: specs::BitSet,
+    removed: specs::BitSet,
 
     broad_phase: coll::BroadPhase,
+    // Broad phase indices by entity id, so objects can be removed once their components are gone.
+    objects: HashMap<specs::world::Index, usize>,
 }
 
 impl CollisionSystem {
@@ -28,9 +63,17 @@ impl CollisionSystem {
         CollisionSystem {
             transform_ins_read: None,
             transform_mod_read: None,
+            transform_rem_read: None,
+            collider_ins_read: None,
+            collider_mod_read: None,
+            collider_rem_read: None,
             ins_transform: specs::BitSet::new(),
             mod_transform: specs::BitSet::new(),
+            ins_collider: specs::BitSet::new(),
+            mod_collider: specs::BitSet::new(),
+            removed: specs::BitSet::new(),
             broad_phase: coll::BroadPhase::new(),
+            objects: HashMap::new(),
         }
     }
 }
@@ -41,10 +84,14 @@ impl<'a> specs::System<'a> for CollisionSystem {
         specs::WriteStorage<'a, comp::Transform>, 
         specs::WriteStorage<'a, comp::Velocity>, 
         specs::WriteStorage<'a, comp::Collider>,
+        specs::ReadStorage<'a, comp::TileMap>,
+        specs::ReadStorage<'a, comp::CollisionStrip>,
+        ScriptData<'a>,
         specs::Read<'a, specs::LazyUpdate>,
     );
 
-    fn run(&mut self, (ent, mut tran, mut vel, mut coll, lazy): Self::SystemData) {
+    #[cfg_attr(not(feature = "lua"), allow(unused_variables))]
+    fn run(&mut self, (ent, mut tran, mut vel, mut coll, map, strip, script, lazy): Self::SystemData) {
         use specs::Join;
 
         /* NOTE:
@@ -52,54 +99,48 @@ impl<'a> specs::System<'a> for CollisionSystem {
             collision until it has a transform component.
         */
 
-        // Get the components in need of initialization or an update.
+        // Get the components in need of initialization, an update or removal.
         self.ins_transform.clear();
         self.mod_transform.clear();
+        self.ins_collider.clear();
+        self.mod_collider.clear();
+        self.removed.clear();
         
         tran.populate_inserted(&mut self.transform_ins_read.as_mut().unwrap(), &mut self.ins_transform);
         tran.populate_modified(&mut self.transform_mod_read.as_mut().unwrap(), &mut self.mod_transform);
+        tran.populate_removed(&mut self.transform_rem_read.as_mut().unwrap(), &mut self.removed);
+        coll.populate_inserted(&mut self.collider_ins_read.as_mut().unwrap(), &mut self.ins_collider);
+        coll.populate_modified(&mut self.collider_mod_read.as_mut().unwrap(), &mut self.mod_collider);
+        coll.populate_removed(&mut self.collider_rem_read.as_mut().unwrap(), &mut self.removed);
+
+        // Take out the colliders which lost their transform or collider (or were deleted altogether).
+        for id in (&self.removed).join() {
+            if let Some(idx) = self.objects.remove(&id) {
+                self.broad_phase.remove(idx);
+            }
+
+            // A collider still around (without a transform) gets put back when it has one again.
+            if let Some(coll) = coll.get_mut(ent.entity(id)) {
+                coll.index = None;
+            }
+        }
         
         // Initialize the collider with its transform.
-        for (ent, tran, mut coll, _) in (&*ent, &tran, &mut coll, &self.ins_transform).join() {
-            let obj = coll::Object {
-                bound: coll.shape.bound(tran.pos),
-                entity: ent,
-            };
-
-            // Insert new object into broadphase.
-            let idx = self.broad_phase.insert(obj);
-
-            // Make sure the collider knows where it is.
-            coll.index = Some(idx);
+        for (ent, tran, coll, _) in (&*ent, &tran, &mut coll, &self.ins_transform).join() {
+            insert_object(&mut self.broad_phase, &mut self.objects, ent, tran, coll);
         }
 
-        // Move the collider with its recently modified transform.
-        for (ent, tran, mut coll, _) in (&*ent, &tran, &mut coll, &self.mod_transform).join() {
-            let bound = if coll.sweep {
-                let old_bound = coll.shape.bound(tran.last_pos);
-                let new_bound = coll.shape.bound(tran.pos);
-
-                // Get a rect that encompasses both the new and old bounds (a "swept" bound).
-                comp::collider::Bound {
-                    rect: Rect3::new(
-                        Vector3::new(
-                            old_bound.rect.min.x.min(new_bound.rect.min.x),
-                            old_bound.rect.min.y.min(new_bound.rect.min.y),
-                            old_bound.rect.min.z.min(new_bound.rect.min.z),
-                        ),
-                        Vector3::new(
-                            old_bound.rect.max.x.max(new_bound.rect.max.x),
-                            old_bound.rect.max.y.max(new_bound.rect.max.y),
-                            old_bound.rect.max.z.max(new_bound.rect.max.z),
-                        )
-                    )
-                }
-            } else {
-                coll.shape.bound(tran.pos)
-            };
+        // Colliders added to entities which already have a transform.
+        for (ent, tran, coll, _) in (&*ent, &tran, &mut coll, &self.ins_collider).join() {
+            insert_object(&mut self.broad_phase, &mut self.objects, ent, tran, coll);
+        }
 
+        // Move the collider with its recently modified transform (or fit it to its recently modified shape).
+        for (tran, coll, _) in (&tran, &coll, &self.mod_transform | &self.mod_collider).join() {
             // Update the collision object on the broadphase grid.
-            self.broad_phase.update(coll.index.unwrap(), bound);
+            if let Some(idx) = coll.index {
+                self.broad_phase.update(idx, swept_bound(&coll, &tran));
+            }
         }
 
         // Maps swept entities to their (current) minimum time of impact and the index of the collision.
@@ -109,6 +150,21 @@ impl<'a> specs::System<'a> for CollisionSystem {
         // List of collisions that will be resolved.
         let mut collisions: Vec<Collision> = Vec::new();
 
+        // Pre-solve hooks written in Lua need the script to run.
+        #[cfg(feature = "lua")]
+        let (script, mut script_errors) = script;
+        #[cfg(feature = "lua")]
+        let script = script.0.as_ref().map(|x| x.lock().unwrap());
+        #[cfg(feature = "lua")]
+        let mut hooks = PreSolveHooks {
+            script: script.as_ref().map(|x| &**x),
+            errors: Vec::new(),
+        };
+        #[cfg(not(feature = "lua"))]
+        let mut hooks = PreSolveHooks {
+            script: PhantomData,
+        };
+
         // Loop through all the collision pairs that the broad phase has detected.
         // * There should be no "duplicates", as in the same pair of entities showing up but in the opposite order.
         self.broad_phase.for_each(|(e1, e2)| {
@@ -119,176 +175,104 @@ impl<'a> specs::System<'a> for CollisionSystem {
             let c2 = coll.get(e2).unwrap();
             let t1 = tran.get(e1).unwrap();
             let t2 = tran.get(e2).unwrap();
-            let disp1 = t1.pos - t1.last_pos;
-            let disp2 = t2.pos - t2.last_pos;
-
-            match (&c1.shape, &c2.shape) {
-                // Discrete AABB-AABB collision.
-                (&Shape::AABB(r1), &Shape::AABB(r2)) 
-                if !c1.sweep && !c2.sweep => {
-                    // The collider AABB in world space.
-                    let r1 = Rect3::new(
-                        t1.pos + r1.min,
-                        t1.pos + r1.max,
-                    );
 
-                    // The collider AABB in world space.
-                    let r2 = Rect3::new(
-                        t2.pos + r2.min,
-                        t2.pos + r2.max,
-                    );
+            let contact = match find_contact(c1, t1, c2, t2) {
+                Some(contact) => contact,
+                None => return,
+            };
 
-                    let pen = penetration_vector(r1, r2);
-
-                    // If the two colliders actually penetrated eachother.
-                    if relative_ne!(pen, Vector3::zero()) {
-                        use cgmath::ElementWise;
-
-                        let (abs_disp1, abs_disp2) = (disp1.map(|x| x.abs()), disp2.map(|x| x.abs()));
-                        
-                        let factor1 = abs_disp1.div_element_wise(abs_disp1 + abs_disp2).map(|x| if x.is_nan() {0.0} else {x});
-                        let factor2 = abs_disp2.div_element_wise(abs_disp1 + abs_disp2).map(|x| if x.is_nan() {0.0} else {x});
-                        
-                        let d1 = pen.mul_element_wise(factor1);
-                        let d2 = -pen.mul_element_wise(factor2);
-
-                        match max_disp.entry(e1) {
-                            Entry::Occupied(mut entry) => {
-                                // If this disp has a magnitude greater than the current one, replace it.
-                                if d1.magnitude2() > entry.get().0.magnitude2() {
-                                    collisions[entry.get().1] = 
-                                        Collision::Discrete(
-                                            e1,
-                                            e2,
-                                            d1
-                                        );
-
-                                    entry.get_mut().0 = d1;
-                                }
-                            },
-                            Entry::Vacant(entry) => {
-                                collisions.push(Collision::Discrete(
-                                    e1,
-                                    e2,
-                                    d1
-                                ));
-                                entry.insert((d1, collisions.len() - 1));
-                            }
-                        }
+            // The normal pushing e1 out of e2.
+            let norm = contact.normal();
 
-                        match max_disp.entry(e2) {
-                            Entry::Occupied(mut entry) => {
-                                // If this disp has a magnitude greater than the current one, replace it.
-                                if d2.magnitude2() > entry.get().0.magnitude2() {
-                                    collisions[entry.get().1] = 
-                                        Collision::Discrete(
-                                            e2,
-                                            e1,
-                                            d2
-                                        );
-
-                                    entry.get_mut().0 = d2;
-                                }
-                            },
-                            Entry::Vacant(entry) => {
-                                collisions.push(Collision::Discrete(
-                                    e2,
-                                    e1,
-                                    d2
-                                ));
-                                entry.insert((d2, collisions.len() - 1));
-                            }
-                        }
-                    }
-                },
-                // Discrete AABB-Circle collision.
-                (&Shape::AABB(r), &Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}) 
-                    | (&Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}, &Shape::AABB(r))
-                if !c1.sweep && !c2.sweep => {
-                    // TODO
-                },
-                // Discrete Circle-Circle collision.
-                (&Shape::Circle{offset: c1_o, radius: c1_r, depth: ref c1_d}, &Shape::Circle{offset: c2_o, radius: c2_r, depth: ref c2_d}) 
-                if !c1.sweep && !c2.sweep => {
-                    // TODO
-                },
-                // Sweep AABB-AABB collision.
-                (&Shape::AABB(r1), &Shape::AABB(r2)) 
-                if c1.sweep || c2.sweep => {
-                    if let Some((t_first, t_last, norm)) = sweep_aabb(r1, t1.last_pos, disp1, r2, t2.last_pos, disp2) {
-                        match min_sweep.entry(e1) {
-                            Entry::Occupied(mut entry) => {
-                                // If this TOI (time-of-impact) is earlier than the current one, replace it.
-                                if t_first < entry.get().0 {
-                                    collisions[entry.get().1] = 
-                                        Collision::Sweep(
-                                            e1,
-                                            e2,
-                                            t_first,
-                                            norm
-                                        );
-
-                                    entry.get_mut().0 = t_first;
-                                }
-                            },
-                            Entry::Vacant(entry) => {
-                                collisions.push(Collision::Sweep(
-                                    e1,
-                                    e2,
-                                    t_first,
-                                    norm
-                                ));
-                                entry.insert((t_first, collisions.len() - 1));
-                            }
-                        }
+            // One-way colliders only block from one side.
+            if !one_way_resolves(c1, t1, c2, t2, norm) || !one_way_resolves(c2, t2, c1, t1, -norm) {
+                return;
+            }
 
-                        match min_sweep.entry(e2) {
-                            Entry::Occupied(mut entry) => {
-                                // If this TOI (time-of-impact) is earlier than the current one, replace it.
-                                if t_first < entry.get().0 {
-                                    collisions[entry.get().1] = 
-                                        Collision::Sweep(
-                                            e2,
-                                            e1,
-                                            t_first,
-                                            norm
-                                        );
-
-                                    entry.get_mut().0 = t_first;
-                                }
-                            },
-                            Entry::Vacant(entry) => {
-                                collisions.push(Collision::Sweep(
-                                    e2,
-                                    e1,
-                                    t_first,
-                                    norm
-                                ));
-                                entry.insert((t_first, collisions.len() - 1));
-                            }
-                        }
+            // Let the colliders cancel or modify the resolution before anything is displaced.
+            let (pre1, pre2) = (
+                pre_solve(&mut hooks, c1, e1, e2, norm),
+                pre_solve(&mut hooks, c2, e2, e1, -norm),
+            );
 
-                    }
-                },
-                // Sweep AABB-Circle collision.
-                (&Shape::AABB(r), &Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}) 
-                    | (&Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}, &Shape::AABB(r))
-                if c1.sweep || c2.sweep => {
-                    // TODO
-                }, 
-                // Sweep Circle-Circle collision.
-                (&Shape::Circle{offset: c1_o, radius: c1_r, depth: ref c1_d}, &Shape::Circle{offset: c2_o, radius: c2_r, depth: ref c2_d}) 
-                if c1.sweep || c2.sweep => {
-                    // TODO
+            if pre1 == PreSolve::Ignore || pre2 == PreSolve::Ignore {
+                return;
+            }
+
+            match contact {
+                Contact::Discrete(pen) => {
+                    let (d1, d2) = discrete_displacements(pen, t1.pos - t1.last_pos, t2.pos - t2.last_pos);
+
+                    push_discrete(&mut max_disp, &mut collisions, e1, e2, pre1.displacement(d1));
+                    push_discrete(&mut max_disp, &mut collisions, e2, e1, pre2.displacement(d2));
                 },
-                _ => ()
+                Contact::Sweep(toi, norm) => {
+                    push_sweep(&mut min_sweep, &mut collisions, e1, e2, toi, pre1.normal(norm));
+                    push_sweep(&mut min_sweep, &mut collisions, e2, e1, toi, pre2.normal(-norm));
+                }
             }
         });
 
+        /* NOTE:
+            Tile maps using grid collision don't have an entity per tile in the broad phase. Instead, colliders that
+            moved look up the tiles they overlap (in their swept bound) and are resolved against those directly. The
+            other entity of these collisions is the tile map itself, which is never displaced.
+        */
+        for (e1, t1, c1, _) in (&*ent, &tran, &coll, &self.ins_transform | &self.mod_transform).join() {
+            let bound = swept_bound(c1, t1);
+
+            for (map_ent, map) in (&*ent, &map).join() {
+                if !map.grid_collision {
+                    continue;
+                }
+
+                for (t2, c2) in grid_tiles(map, &strip, bound.rect) {
+                    let contact = match find_contact(c1, t1, &c2, &t2) {
+                        Some(contact) => contact,
+                        None => continue,
+                    };
+
+                    let norm = contact.normal();
+
+                    if !one_way_resolves(c1, t1, &c2, &t2, norm) || !one_way_resolves(&c2, &t2, c1, t1, -norm) {
+                        continue;
+                    }
+
+                    let pre = pre_solve(&mut hooks, c1, e1, map_ent, norm);
+
+                    if pre == PreSolve::Ignore {
+                        continue;
+                    }
+
+                    match contact {
+                        Contact::Discrete(pen) => {
+                            let (d1, _) = discrete_displacements(pen, t1.pos - t1.last_pos, Vector3::zero());
+
+                            push_discrete(&mut max_disp, &mut collisions, e1, map_ent, pre.displacement(d1));
+                        },
+                        Contact::Sweep(toi, norm) => {
+                            push_sweep(&mut min_sweep, &mut collisions, e1, map_ent, toi, pre.normal(norm));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Hooks which keep failing are disabled, leaving their contacts to be resolved as usual.
+        #[cfg(feature = "lua")]
+        for (ent, err) in hooks.errors {
+            if script_errors.report(ent, "pre_solve", err) {
+                if let Some(PreSolveHook::Script(key)) = coll.get_mut(ent).and_then(|x| x.pre_solve.take()) {
+                    hooks.script.unwrap().remove_registry_value(key).unwrap();
+                }
+            }
+        }
+
         for collision in collisions {
             match collision {
                 Collision::Sweep(ent, other, toi, norm) => {
-                    let other_toi = min_sweep[&other].0;
+                    // Things that are never displaced (like tile maps) don't have a time of impact of their own.
+                    let other_toi = min_sweep.get(&other).map(|x| x.0).unwrap_or(toi);
 
                     // Double check if these entities actually collide. Before, for example, hitting another object.
                     if relative_eq!(toi, other_toi) {
@@ -302,51 +286,34 @@ impl<'a> specs::System<'a> for CollisionSystem {
                             in the direction that the object approached it (e.g. if going diagonally up, it slides up).
                         */
                         let time_left = 1.0 - toi;
-                        let dot = (disp.x * norm.y + disp.y * norm.x) * time_left;
-                        let slide = Vector3::new(dot * norm.y, dot * norm.x, 0.0);
+                        // The surface's direction, along which the object slides (works for slopes as well as walls).
+                        let tangent = Vector3::new(-norm.y, norm.x, 0.0);
+                        let slide = tangent * disp.dot(tangent) * time_left;
                         new_disp += slide;
 
                         t.pos = t.last_pos + new_disp;
 
-                        lazy.exec_mut(move |world| {
-                            let res = &mut world.res as *mut specs::Resources;
-
-                            if let Some(ref mutex) = world.read_resource::<res::Script>().0 {
-                                let script = mutex.lock().unwrap();
-
-                                let coll = world.read_storage::<comp::Collider>();
-                                
-                                if let Some(cb) = coll.get(ent).unwrap().on_collide.as_ref() {
-                                    if let Some(func) = script.registry_value::<LuaFunction>(&cb).ok() {
-                                        func.call::<_, ()>((LuaWorld(res), LuaEntity(ent), LuaEntity(other))).unwrap();
-                                    }
-                                }
-                            }
-                        });
+                        on_collide(&lazy, ent, other);
                     }
                 },
                 Collision::Discrete(ent, other, disp) => {
                     let t = tran.get_mut(ent).unwrap();
                     t.pos += disp;
 
-                    lazy.exec_mut(move |world| {
-                        let res = &mut world.res as *mut specs::Resources;
-
-                        if let Some(ref mutex) = world.read_resource::<res::Script>().0 {
-                            let script = mutex.lock().unwrap();
-
-                            let coll = world.read_storage::<comp::Collider>();
-                            
-                            if let Some(cb) = coll.get(ent).unwrap().on_collide.as_ref() {
-                                if let Some(func) = script.registry_value::<LuaFunction>(&cb).ok() {
-                                    func.call::<_, ()>((LuaWorld(res), LuaEntity(ent), LuaEntity(other))).unwrap();
-                                }
-                            }
-                        }
-                    });
+                    on_collide(&lazy, ent, other);
                 }
             }
         }
+
+        // Dropping through one-way colliders only lasts a tick, after which the collider is already past the platform.
+        for mut coll in (&mut coll.restrict_mut()).join() {
+            if coll.get_unchecked().drop_through {
+                coll.get_mut_unchecked().drop_through = false;
+            }
+        }
+
+        // Skip the modifications made by this system (only other systems and scripts change the shape).
+        coll.populate_modified(&mut self.collider_mod_read.as_mut().unwrap(), &mut self.mod_collider);
     }
 
     fn setup(&mut self, res: &mut specs::Resources) {
@@ -356,6 +323,428 @@ impl<'a> specs::System<'a> for CollisionSystem {
         let mut tran_storage: specs::WriteStorage<comp::Transform> = SystemData::fetch(&res);
         self.transform_ins_read = Some(tran_storage.track_inserted());        
         self.transform_mod_read = Some(tran_storage.track_modified());        
+        self.transform_rem_read = Some(tran_storage.track_removed());
+
+        let mut coll_storage: specs::WriteStorage<comp::Collider> = SystemData::fetch(&res);
+        self.collider_ins_read = Some(coll_storage.track_inserted());
+        self.collider_mod_read = Some(coll_storage.track_modified());
+        self.collider_rem_read = Some(coll_storage.track_removed());
+    }
+}
+
+// Adds a collider to the broad phase (if it isn't already in it).
+fn insert_object(broad_phase: &mut coll::BroadPhase, objects: &mut HashMap<specs::world::Index, usize>, ent: specs::Entity, tran: &comp::Transform, coll: &mut comp::Collider) {
+    if coll.index.is_some() {
+        return;
+    }
+
+    let obj = coll::Object {
+        bound: coll.shape.transformed(tran).bound(tran.pos),
+        entity: ent,
+    };
+
+    // Insert new object into broadphase.
+    let idx = broad_phase.insert(obj);
+    objects.insert(ent.id(), idx);
+
+    // Make sure the collider knows where it is.
+    coll.index = Some(idx);
+}
+
+// A rect encompassing the collider over the whole tick if it sweeps (a "swept" bound), otherwise just its current bound.
+fn swept_bound(coll: &Collider, tran: &comp::Transform) -> Bound {
+    let shape = coll.shape.transformed(tran);
+
+    if coll.sweep {
+        let old_bound = shape.bound(tran.last_pos);
+        let new_bound = shape.bound(tran.pos);
+
+        Bound {
+            rect: Rect3::new(
+                Vector3::new(
+                    old_bound.rect.min.x.min(new_bound.rect.min.x),
+                    old_bound.rect.min.y.min(new_bound.rect.min.y),
+                    old_bound.rect.min.z.min(new_bound.rect.min.z),
+                ),
+                Vector3::new(
+                    old_bound.rect.max.x.max(new_bound.rect.max.x),
+                    old_bound.rect.max.y.max(new_bound.rect.max.y),
+                    old_bound.rect.max.z.max(new_bound.rect.max.z),
+                )
+            )
+        }
+    } else {
+        shape.bound(tran.pos)
+    }
+}
+
+// Finds out whether (and how) two colliders are in contact.
+fn find_contact(c1: &Collider, t1: &comp::Transform, c2: &Collider, t2: &comp::Transform) -> Option<Contact> {
+    let disp1 = t1.pos - t1.last_pos;
+    let disp2 = t2.pos - t2.last_pos;
+
+    // Rotated and scaled shapes are collided as they are in the world.
+    let (s1, s2) = (c1.shape.transformed(t1), c2.shape.transformed(t2));
+
+    match (&*s1, &*s2) {
+        // Discrete AABB-AABB collision.
+        (&Shape::AABB(r1), &Shape::AABB(r2)) 
+        if !c1.sweep && !c2.sweep => {
+            // The collider AABB in world space.
+            let r1 = Rect3::new(
+                t1.pos + r1.min,
+                t1.pos + r1.max,
+            );
+
+            // The collider AABB in world space.
+            let r2 = Rect3::new(
+                t2.pos + r2.min,
+                t2.pos + r2.max,
+            );
+
+            Contact::discrete(penetration_vector(r1, r2))
+        },
+        // Discrete AABB-Polygon collision.
+        (&Shape::AABB(r), &Shape::Slope { .. }) | (&Shape::AABB(r), &Shape::Polygon { .. })
+        if !c1.sweep && !c2.sweep => {
+            let (poly, depth) = s2.polygon().unwrap();
+
+            Contact::discrete(penetration_polygon(
+                Rect3::new(t1.pos + r.min, t1.pos + r.max),
+                &poly.iter().map(|x| x + t2.pos.truncate()).collect::<Vec<_>>(),
+                (depth.0 + t2.pos.z, depth.1 + t2.pos.z)
+            ))
+        },
+        (&Shape::Slope { .. }, &Shape::AABB(r)) | (&Shape::Polygon { .. }, &Shape::AABB(r))
+        if !c1.sweep && !c2.sweep => {
+            let (poly, depth) = s1.polygon().unwrap();
+
+            Contact::discrete(-penetration_polygon(
+                Rect3::new(t2.pos + r.min, t2.pos + r.max),
+                &poly.iter().map(|x| x + t1.pos.truncate()).collect::<Vec<_>>(),
+                (depth.0 + t1.pos.z, depth.1 + t1.pos.z)
+            ))
+        },
+        // Discrete Polygon-Polygon collision (e.g. rotated colliders against slopes).
+        (&Shape::Slope { .. }, &Shape::Slope { .. }) | (&Shape::Slope { .. }, &Shape::Polygon { .. }) |
+            (&Shape::Polygon { .. }, &Shape::Slope { .. }) | (&Shape::Polygon { .. }, &Shape::Polygon { .. })
+        if !c1.sweep && !c2.sweep => {
+            let (poly1, depth1) = s1.polygon().unwrap();
+            let (poly2, depth2) = s2.polygon().unwrap();
+
+            Contact::discrete(penetration_polygons(
+                &poly1.iter().map(|x| x + t1.pos.truncate()).collect::<Vec<_>>(),
+                (depth1.0 + t1.pos.z, depth1.1 + t1.pos.z),
+                &poly2.iter().map(|x| x + t2.pos.truncate()).collect::<Vec<_>>(),
+                (depth2.0 + t2.pos.z, depth2.1 + t2.pos.z)
+            ))
+        },
+        // Discrete AABB-Circle collision.
+        (&Shape::AABB(r), &Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}) 
+            | (&Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}, &Shape::AABB(r))
+        if !c1.sweep && !c2.sweep => {
+            // TODO
+            None
+        },
+        // Discrete Circle-Circle collision.
+        (&Shape::Circle{offset: c1_o, radius: c1_r, depth: ref c1_d}, &Shape::Circle{offset: c2_o, radius: c2_r, depth: ref c2_d}) 
+        if !c1.sweep && !c2.sweep => {
+            // TODO
+            None
+        },
+        // Sweep AABB-AABB collision.
+        (&Shape::AABB(r1), &Shape::AABB(r2)) 
+        if c1.sweep || c2.sweep => {
+            sweep_aabb(r1, t1.last_pos, disp1, r2, t2.last_pos, disp2)
+                .map(|(t_first, _, norm)| Contact::Sweep(t_first, norm))
+        },
+        // Sweep AABB-Polygon collision.
+        (&Shape::AABB(r), &Shape::Slope { .. }) | (&Shape::AABB(r), &Shape::Polygon { .. })
+        if c1.sweep || c2.sweep => {
+            let (poly, depth) = s2.polygon().unwrap();
+
+            sweep_polygon(r, t1.last_pos, disp1, &poly, depth, t2.last_pos, disp2)
+                .map(|(t_first, _, norm)| Contact::Sweep(t_first, norm))
+        },
+        (&Shape::Slope { .. }, &Shape::AABB(r)) | (&Shape::Polygon { .. }, &Shape::AABB(r))
+        if c1.sweep || c2.sweep => {
+            let (poly, depth) = s1.polygon().unwrap();
+
+            sweep_polygon(r, t2.last_pos, disp2, &poly, depth, t1.last_pos, disp1)
+                .map(|(t_first, _, norm)| Contact::Sweep(t_first, -norm))
+        },
+        // Sweep Polygon-Polygon collision.
+        (&Shape::Slope { .. }, &Shape::Slope { .. }) | (&Shape::Slope { .. }, &Shape::Polygon { .. }) |
+            (&Shape::Polygon { .. }, &Shape::Slope { .. }) | (&Shape::Polygon { .. }, &Shape::Polygon { .. })
+        if c1.sweep || c2.sweep => {
+            let (poly1, depth1) = s1.polygon().unwrap();
+            let (poly2, depth2) = s2.polygon().unwrap();
+
+            sweep_polygons(&poly1, depth1, t1.last_pos, disp1, &poly2, depth2, t2.last_pos, disp2)
+                .map(|(t_first, _, norm)| Contact::Sweep(t_first, norm))
+        },
+        // Sweep AABB-Circle collision.
+        (&Shape::AABB(r), &Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}) 
+            | (&Shape::Circle{offset: c_o, radius: c_r, depth: ref c_d}, &Shape::AABB(r))
+        if c1.sweep || c2.sweep => {
+            // TODO
+            None
+        }, 
+        // Sweep Circle-Circle collision.
+        (&Shape::Circle{offset: c1_o, radius: c1_r, depth: ref c1_d}, &Shape::Circle{offset: c2_o, radius: c2_r, depth: ref c2_d}) 
+        if c1.sweep || c2.sweep => {
+            // TODO
+            None
+        },
+        _ => None
+    }
+}
+
+// Splits a penetration vector between two discrete colliders, based on how much each moved this tick.
+fn discrete_displacements(pen: Vector3<f32>, disp1: Vector3<f32>, disp2: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    use cgmath::ElementWise;
+
+    let (abs_disp1, abs_disp2) = (disp1.map(|x| x.abs()), disp2.map(|x| x.abs()));
+    
+    let factor1 = abs_disp1.div_element_wise(abs_disp1 + abs_disp2).map(|x| if x.is_nan() {0.0} else {x});
+    let factor2 = abs_disp2.div_element_wise(abs_disp1 + abs_disp2).map(|x| if x.is_nan() {0.0} else {x});
+    
+    (pen.mul_element_wise(factor1), -pen.mul_element_wise(factor2))
+}
+
+// Keeps the discrete collision that displaces the entity the most.
+fn push_discrete(
+    max_disp: &mut HashMap<specs::Entity, (Vector3<f32>, usize)>, collisions: &mut Vec<Collision>,
+    ent: specs::Entity, other: specs::Entity, disp: Vector3<f32>
+) {
+    match max_disp.entry(ent) {
+        Entry::Occupied(mut entry) => {
+            // If this disp has a magnitude greater than the current one, replace it.
+            if disp.magnitude2() > entry.get().0.magnitude2() {
+                collisions[entry.get().1] = 
+                    Collision::Discrete(
+                        ent,
+                        other,
+                        disp
+                    );
+
+                entry.get_mut().0 = disp;
+            }
+        },
+        Entry::Vacant(entry) => {
+            collisions.push(Collision::Discrete(
+                ent,
+                other,
+                disp
+            ));
+            entry.insert((disp, collisions.len() - 1));
+        }
+    }
+}
+
+// Keeps the sweep collision with the earliest time of impact for the entity.
+fn push_sweep(
+    min_sweep: &mut HashMap<specs::Entity, (f32, usize)>, collisions: &mut Vec<Collision>,
+    ent: specs::Entity, other: specs::Entity, toi: f32, norm: Vector3<f32>
+) {
+    match min_sweep.entry(ent) {
+        Entry::Occupied(mut entry) => {
+            // If this TOI (time-of-impact) is earlier than the current one, replace it.
+            if toi < entry.get().0 {
+                collisions[entry.get().1] = 
+                    Collision::Sweep(
+                        ent,
+                        other,
+                        toi,
+                        norm
+                    );
+
+                entry.get_mut().0 = toi;
+            }
+        },
+        Entry::Vacant(entry) => {
+            collisions.push(Collision::Sweep(
+                ent,
+                other,
+                toi,
+                norm
+            ));
+            entry.insert((toi, collisions.len() - 1));
+        }
+    }
+}
+
+// The transforms and colliders of the tiles (of a tile map using grid collision) overlapping a rect.
+fn grid_tiles(map: &comp::TileMap, strips: &specs::ReadStorage<comp::CollisionStrip>, rect: Rect3<f32>) -> Vec<(comp::Transform, Collider)> {
+    use comp::tilemap::STRIP_LENGTH;
+
+    let dims = map.tile_dims();
+    let mut tiles = Vec::new();
+
+    // The range of tiles (in tile coordinates) the rect covers, tile maps don't go into negative coordinates.
+    let min = Vector3::new(
+        (rect.min.x / dims.x).floor().max(0.0) as u32,
+        (rect.min.y / dims.y).floor().max(0.0) as u32,
+        (rect.min.z / dims.z).floor().max(0.0) as u32,
+    );
+    let max = Vector3::new(
+        (rect.max.x / dims.x).ceil().max(0.0) as u32,
+        (rect.max.y / dims.y).ceil().max(0.0) as u32,
+        (rect.max.z / dims.z).ceil().max(0.0) as u32,
+    );
+
+    for z in min.z..max.z {
+        for y in min.y..max.y {
+            for x in min.x..max.x {
+                let strip_pos = Vector3::new(x / STRIP_LENGTH as u32, y, z);
+                let idx = (x % STRIP_LENGTH as u32) as usize;
+
+                let strip = match map.collision_strips.get(&strip_pos).and_then(|x| strips.get(*x)) {
+                    Some(strip) => strip,
+                    None => continue,
+                };
+
+                if let Some(shape) = strip.tile_shape(idx) {
+                    let mut coll = Collider::new(shape.collider_shape(dims), false);
+                    coll.one_way = strip.one_way[idx];
+
+                    let tran = comp::Transform::new(Vector3::new(
+                        x as f32 * dims.x,
+                        y as f32 * dims.y,
+                        z as f32 * dims.z,
+                    ));
+
+                    tiles.push((tran, coll));
+                }
+            }
+        }
+    }
+
+    tiles
+}
+
+// How far into a one-way collider's bound another collider is allowed to have started, still counting as outside of it.
+const ONE_WAY_TOLERANCE: f32 = 0.0001;
+
+// Whether a contact with a (possibly) one-way collider should be resolved.
+// * The normal is the direction the one-way collider would be pushed out of the other collider.
+fn one_way_resolves(
+    platform: &Collider, platform_tran: &comp::Transform, 
+    other: &Collider, other_tran: &comp::Transform, 
+    norm: Vector3<f32>
+) -> bool {
+    let pass = match platform.one_way {
+        Some(pass) => pass,
+        None => return true,
+    };
+
+    if other.drop_through {
+        return false;
+    }
+
+    // Only contacts pushing the platform against its pass direction are resolved.
+    if norm.dot(pass) >= 0.0 {
+        return false;
+    }
+
+    // The other collider must have started the tick entirely on the blocking side of the platform, so that it doesn't
+    // get popped on top of the platform while passing through it.
+    let (_, platform_max) = project_rect(platform.shape.transformed(platform_tran).bound(platform_tran.last_pos).rect, pass);
+    let (other_min, _) = project_rect(other.shape.transformed(other_tran).bound(other_tran.last_pos).rect, pass);
+
+    other_min >= platform_max - ONE_WAY_TOLERANCE
+}
+
+// Asks the collider's pre-solve hook (if it has one) what to do with a contact. A failing hook resolves the contact 
+// as usual.
+#[cfg_attr(not(feature = "lua"), allow(unused_variables))]
+fn pre_solve(hooks: &mut PreSolveHooks, coll: &Collider, ent: specs::Entity, other: specs::Entity, norm: Vector3<f32>) -> PreSolve {
+    match coll.pre_solve {
+        Some(PreSolveHook::Native(ref func)) => func(ent, other, norm),
+        #[cfg(feature = "lua")]
+        Some(PreSolveHook::Script(ref key)) => {
+            if let Some((script, func)) = hooks.script.and_then(|x| x.registry_value::<LuaFunction>(key).ok().map(|f| (x, f))) {
+                let ret = sandbox::enter(script, &LuaValue::Function(func.clone()))
+                    .and_then(|_| func.call::<_, LuaValue>((LuaEntity(ent), LuaEntity(other), Vector3f(norm))))
+                    .and_then(PreSolve::from_lua);
+                let ret = sandbox::reset_budget(script).and(ret);
+
+                match ret {
+                    Ok(pre) => pre,
+                    Err(err) => {
+                        hooks.errors.push((ent, err));
+                        PreSolve::Resolve
+                    },
+                }
+            } else {
+                PreSolve::Resolve
+            }
+        },
+        None => PreSolve::Resolve,
+    }
+}
+
+// Calls the collider's Lua collision callback (if it has one), once the world is free to be used by the script.
+#[cfg(feature = "lua")]
+fn on_collide(lazy: &specs::LazyUpdate, ent: specs::Entity, other: specs::Entity) {
+    lazy.exec(move |world| {
+        if let Some(ref mutex) = world.read_resource::<res::Script>().0 {
+            let script = mutex.lock().unwrap();
+
+            // The entity (or its collider) may have been removed since the collision.
+            let func = {
+                let coll = world.read_storage::<comp::Collider>();
+
+                coll.get(ent)
+                    .and_then(|x| x.on_collide.as_ref())
+                    .and_then(|cb| script.registry_value::<LuaFunction>(cb).ok())
+            };
+
+            if let Some(func) = func {
+                let state = world.read_storage::<comp::ScriptBehavior>().get(ent).and_then(|x| ::system::script::state(&script, x));
+                let result = with_world(&script, &world.res, ent, LuaValue::Function(func.clone()), |lua_world| {
+                    func.call::<_, ()>((lua_world, LuaEntity(ent), LuaEntity(other), state))
+                });
+
+                if let Err(err) = result {
+                    if world.write_resource::<res::ScriptErrors>().report(ent, "on_collide", err) {
+                        if let Some(key) = world.write_storage::<comp::Collider>().get_mut(ent).and_then(|x| x.on_collide.take()) {
+                            script.remove_registry_value(key).unwrap();
+                        }
+                    }
+                }
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "lua"))]
+fn on_collide(_: &specs::LazyUpdate, _: specs::Entity, _: specs::Entity) {}
+
+// How two colliders are in contact.
+enum Contact {
+    // Penetration vector pushing the first collider out of the second.
+    Discrete(Vector3<f32>),
+    // Time of impact and the normal pushing the first collider out of the second.
+    Sweep(f32, Vector3<f32>),
+}
+
+impl Contact {
+    fn discrete(pen: Vector3<f32>) -> Option<Contact> {
+        // If the two colliders actually penetrated eachother.
+        if relative_ne!(pen, Vector3::zero()) {
+            Some(Contact::Discrete(pen))
+        } else {
+            None
+        }
+    }
+
+    // The normal pushing the first collider out of the second.
+    fn normal(&self) -> Vector3<f32> {
+        match *self {
+            Contact::Discrete(pen) => pen.normalize(),
+            Contact::Sweep(_, norm) => norm,
+        }
     }
 }
 
@@ -363,4 +752,70 @@ impl<'a> specs::System<'a> for CollisionSystem {
 enum Collision {
     Sweep(specs::Entity, specs::Entity, f32, Vector3<f32>),
     Discrete(specs::Entity, specs::Entity, Vector3<f32>),
-}
\ No newline at end of file
+}
+
+#[test]
+fn one_way() {
+    let shape = || Shape::AABB(Rect3::new(Vector3::zero(), Vector3::new(0.1, 0.1, 0.1)));
+
+    // A platform that can be passed through going up (-y).
+    let mut platform = Collider::new(shape(), false);
+    platform.one_way = Some(Vector3::new(0.0, -1.0, 0.0));
+    let platform_tran = comp::Transform::new(Vector3::new(0.0, 0.1, 0.0));
+
+    let mut mover = Collider::new(shape(), false);
+
+    // Landing on the platform from above.
+    let mut mover_tran = comp::Transform::new(Vector3::new(0.0, 0.0, 0.0));
+    mover_tran.pos = Vector3::new(0.0, 0.02, 0.0);
+    assert!(one_way_resolves(&platform, &platform_tran, &mover, &mover_tran, Vector3::new(0.0, 1.0, 0.0)));
+
+    // Jumping up through the platform from below.
+    let mut mover_tran = comp::Transform::new(Vector3::new(0.0, 0.2, 0.0));
+    mover_tran.pos = Vector3::new(0.0, 0.18, 0.0);
+    assert!(!one_way_resolves(&platform, &platform_tran, &mover, &mover_tran, Vector3::new(0.0, -1.0, 0.0)));
+
+    // Falling while already partway through the platform.
+    let mut mover_tran = comp::Transform::new(Vector3::new(0.0, 0.05, 0.0));
+    mover_tran.pos = Vector3::new(0.0, 0.07, 0.0);
+    assert!(!one_way_resolves(&platform, &platform_tran, &mover, &mover_tran, Vector3::new(0.0, 1.0, 0.0)));
+
+    // Dropping through the platform on purpose.
+    mover.drop_through = true;
+    let mut mover_tran = comp::Transform::new(Vector3::new(0.0, 0.0, 0.0));
+    mover_tran.pos = Vector3::new(0.0, 0.02, 0.0);
+    assert!(!one_way_resolves(&platform, &platform_tran, &mover, &mover_tran, Vector3::new(0.0, 1.0, 0.0)));
+}
+
+#[test]
+fn grid_tiles_overlapping() {
+    use specs::Builder;
+    use comp::tilemap::STRIP_LENGTH;
+
+    let mut world = specs::World::new();
+    world.register::<comp::CollisionStrip>();
+
+    let map_ent = world.create_entity().build();
+
+    // Only the second tile of the strip blocks.
+    let mut blocking = [false; STRIP_LENGTH];
+    blocking[1] = true;
+
+    let strip_ent = world.create_entity()
+        .with(comp::CollisionStrip::new(map_ent, Vector3::new(0, 0, 0), blocking))
+    .build();
+
+    let mut map = comp::TileMap::new(Vector3::new(0.1, 0.1, 0.1), Vector2::new(1, 1), 0, None);
+    map.collision_strips.insert(Vector3::new(0, 0, 0), strip_ent);
+
+    let strips = world.read_storage::<comp::CollisionStrip>();
+
+    // Overlaps the first three tiles.
+    let tiles = grid_tiles(&map, &strips, Rect3::new(Vector3::new(0.05, 0.0, 0.0), Vector3::new(0.25, 0.1, 0.1)));
+    assert_eq!(tiles.len(), 1);
+    assert_eq!(tiles[0].0.pos, Vector3::new(0.1, 0.0, 0.0));
+
+    // Doesn't overlap the blocking tile.
+    let tiles = grid_tiles(&map, &strips, Rect3::new(Vector3::new(0.25, 0.0, 0.0), Vector3::new(0.35, 0.1, 0.1)));
+    assert!(tiles.is_empty());
+}
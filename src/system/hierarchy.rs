@@ -0,0 +1,168 @@
+use ::component as comp;
+
+use cgmath::{Zero, Vector2, Vector3};
+use specs;
+
+// Keeps the transforms of child entities in sync with their parents' (and their own local transforms).
+pub struct TransformHierarchySystem;
+
+impl<'a> specs::System<'a> for TransformHierarchySystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        specs::WriteStorage<'a, comp::Transform>, 
+        specs::WriteStorage<'a, comp::LocalTransform>,
+        specs::WriteStorage<'a, comp::Parent>,
+    );
+
+    fn run(&mut self, (ents, mut tran, mut local, mut parent): Self::SystemData) {
+        use specs::Join;
+
+        // Sort the children by how deep they are in the hierarchy, so parents are always updated before their children.
+        // Children whose ancestors form a cycle lose their parent, the same as orphans.
+        let max_depth = parent.join().count();
+        let mut children = Vec::new();
+        let mut orphans = Vec::new();
+
+        for (ent, _) in (&*ents, &parent).join() {
+            match depth(ent, &parent, max_depth) {
+                Some(depth) => children.push((depth, ent)),
+                None => {
+                    warn!("Entity {} is its own ancestor, it's been removed from its parent.", ent.id());
+                    orphans.push(ent);
+                },
+            }
+        }
+        children.sort_by_key(|&(depth, _)| depth);
+
+        // Entities deleted by this system aren't dead until the world is maintained, so keep track of them.
+        let mut deleted = specs::BitSet::new();
+
+        for (_, child) in children {
+            let (parent_ent, cascade) = {
+                let p = parent.get(child).unwrap();
+                (p.entity, p.cascade)
+            };
+
+            // If the parent has been deleted, either delete its child along with it or leave the child be.
+            if !ents.is_alive(parent_ent) || deleted.contains(parent_ent.id()) {
+                if cascade {
+                    ents.delete(child).unwrap();
+                    deleted.add(child.id());
+                } else {
+                    orphans.push(child);
+                }
+
+                continue;
+            }
+
+            let parent_tran = match tran.get(parent_ent) {
+                Some(t) => t.clone(),
+                None => continue,
+            };
+
+            if local.get(child).is_none() {
+                local.insert(child, comp::LocalTransform::new(Vector3::zero())).unwrap();
+            }
+            let local = local.get_mut(child).unwrap();
+
+            // Whatever moved the child since the last tick moves it relative to its parent too.
+            if let (Some(t), Some((pos, rotation, scale))) = (tran.get(child), local.synced) {
+                let moved = t.pos - pos;
+                local.pos += parent_tran.unrotate_scale(moved.truncate()).extend(moved.z);
+                local.rotation += t.rotation - rotation;
+
+                if t.scale != scale {
+                    local.scale = comp::LocalTransform::relative_to(t, &parent_tran).scale;
+                }
+            }
+
+            let (pos, rotation, scale) = local.apply(&parent_tran);
+            local.synced = Some((pos, rotation, scale));
+
+            // Only touch the transform if it actually moved, so it isn't flagged as modified for nothing.
+            if let Some(t) = tran.get(child) {
+                if relative_eq!(t.pos, pos) && relative_eq!(t.rotation, rotation) && relative_eq!(t.scale, scale) {
+                    continue;
+                }
+            }
+
+            if tran.get(child).is_none() {
+                tran.insert(child, comp::Transform::new(pos)).unwrap();
+            }
+            let t = tran.get_mut(child).unwrap();
+            t.pos = pos;
+            t.rotation = rotation;
+            t.scale = scale;
+        }
+
+        for orphan in orphans {
+            parent.remove(orphan);
+        }
+    }
+}
+
+// How many ancestors an entity has, or none if its ancestors form a cycle (it has more than the maximum).
+fn depth(ent: specs::Entity, parent: &specs::WriteStorage<comp::Parent>, max_depth: usize) -> Option<usize> {
+    let mut depth = 0;
+    let mut current = ent;
+
+    while let Some(p) = parent.get(current) {
+        depth += 1;
+        current = p.entity;
+
+        if depth > max_depth {
+            return None;
+        }
+    }
+
+    Some(depth)
+}
+
+#[test]
+fn children_follow_parents() {
+    use specs::{Builder, RunNow};
+
+    let mut world = specs::World::new();
+    world.register::<comp::Transform>();
+    world.register::<comp::LocalTransform>();
+    world.register::<comp::Parent>();
+
+    let parent = world.create_entity().with(comp::Transform::new(Vector3::new(1.0, 0.0, 0.0))).build();
+    let child = world.create_entity()
+        .with(comp::LocalTransform::new(Vector3::new(0.5, 0.0, 0.0)))
+        .with(comp::Parent::new(parent, false))
+        .build();
+
+    // Two entities parented to each other.
+    let first = world.create_entity().with(comp::Transform::new(Vector3::zero())).build();
+    let second = world.create_entity().with(comp::Transform::new(Vector3::zero())).with(comp::Parent::new(first, false)).build();
+    world.write_storage::<comp::Parent>().insert(first, comp::Parent::new(second, false)).unwrap();
+
+    TransformHierarchySystem.run_now(&world.res);
+    assert_eq!(world.read_storage::<comp::Transform>().get(child).unwrap().pos, Vector3::new(1.5, 0.0, 0.0));
+    assert!(world.read_storage::<comp::Parent>().get(first).is_none());
+    assert!(world.read_storage::<comp::Parent>().get(second).is_none());
+
+    // The child moving itself (e.g. with its velocity) moves it relative to its parent, which it keeps following.
+    world.write_storage::<comp::Transform>().get_mut(child).unwrap().pos.y += 1.0;
+    world.write_storage::<comp::Transform>().get_mut(parent).unwrap().pos.x += 1.0;
+    TransformHierarchySystem.run_now(&world.res);
+
+    assert_eq!(world.read_storage::<comp::Transform>().get(child).unwrap().pos, Vector3::new(2.5, 1.0, 0.0));
+    assert_eq!(world.read_storage::<comp::LocalTransform>().get(child).unwrap().pos, Vector3::new(0.5, 1.0, 0.0));
+
+    // Children are rotated and scaled around their parent.
+    {
+        let mut tran = world.write_storage::<comp::Transform>();
+        let parent = tran.get_mut(parent).unwrap();
+        parent.rotation = ::std::f32::consts::FRAC_PI_2;
+        parent.scale = Vector2::new(2.0, 2.0);
+    }
+    TransformHierarchySystem.run_now(&world.res);
+
+    let tran = world.read_storage::<comp::Transform>();
+    let child = tran.get(child).unwrap();
+    assert!(relative_eq!(child.pos, Vector3::new(0.0, 1.0, 0.0), epsilon = 1e-6));
+    assert!(relative_eq!(child.rotation, ::std::f32::consts::FRAC_PI_2));
+    assert_eq!(child.scale, Vector2::new(2.0, 2.0));
+}
@@ -1,16 +1,24 @@
 mod physics;
 pub use self::physics::{VelocitySystem};
 
+mod hierarchy;
+pub use self::hierarchy::{TransformHierarchySystem};
+
 mod render;
 pub use self::render::{RenderSystem};
 
 mod tilemap;
-pub use self::tilemap::{TileMapSystem, TileMapRenderSystem, TileMapCollisionSystem};
+pub use self::tilemap::{TileMapSystem, HeadlessTileMapRenderSystem, TileMapCollisionSystem};
+#[cfg(feature = "render")]
+pub use self::tilemap::{TileMapRenderSystem};
 
 mod sprite;
+pub use self::sprite::{HeadlessSpriteSystem};
+#[cfg(feature = "render")]
 pub use self::sprite::{SpriteSystem};
 
 mod collision;
 pub use self::collision::{CollisionSystem};
 
+#[cfg(feature = "lua")]
 pub mod script;
\ No newline at end of file
@@ -1,181 +1,205 @@
-use ::{fs, vs};
 use ::component as comp;
 use ::resource as res;
+use ::render::{RenderBackend, DrawCommand, SortKey};
 
-use std::sync::{Arc, Mutex};
-use std::sync::mpsc;
+use std::cmp::Ordering;
 
-use vulkano as vk;
-use vk::command_buffer::{AutoCommandBuffer, AutoCommandBufferBuilder};
 use dmsort;
 use specs;
 use cgmath;
 
-pub struct RenderSystem<L> {
-    pipeline: Arc<L>,
-    default_instance: Arc<vk::descriptor::DescriptorSet + Send + Sync>,
-    
-    cmd_buf_tx: mpsc::Sender<AutoCommandBuffer>
+// Sorts everything that's rendered, then hands the draw list to the backend.
+pub struct RenderSystem<B> {
+    backend: B,
+    // Reused between frames.
+    commands: Vec<DrawCommand>,
 }
 
-impl<L> RenderSystem<L> 
-where
-    L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
-{
-    pub fn new(
-        pipeline: Arc<L>,
-        queue: Arc<vk::device::Queue>,
-    ) -> (RenderSystem<L>, mpsc::Receiver<AutoCommandBuffer>) {
-        use vk::descriptor::descriptor_set::*;
-        use cgmath::One;
+impl<B> RenderSystem<B> {
+    pub fn new(backend: B) -> RenderSystem<B> {
+        RenderSystem {
+            backend,
+            commands: Vec::new(),
+        }
+    }
 
-        let (tx, rx) = mpsc::channel();
-
-        let (inst_buf, future) = vk::buffer::ImmutableBuffer::from_data(
-            vs::ty::Instance {
-                transform: cgmath::Matrix4::one().into(),
-            },
-            vk::buffer::BufferUsage::uniform_buffer(),
-            queue
-        ).unwrap();
-
-        let set = PersistentDescriptorSet::start(pipeline.clone(), 0)
-            .add_buffer(inst_buf).unwrap()
-        .build().unwrap();
-
-        (RenderSystem {
-            pipeline: pipeline,
-            default_instance: Arc::new(set),
-            cmd_buf_tx: tx
-        },
-        rx)
+    pub fn backend(&self) -> &B {
+        &self.backend
     }
 }
 
-impl<'a, L> specs::System<'a> for RenderSystem<L> 
+impl<'a, B> specs::System<'a> for RenderSystem<B> 
 where 
-    L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
+    B: RenderBackend<'a>,
 {
     type SystemData = (
-        specs::Read<'a, res::Device>,
-        specs::Read<'a, res::Queue>,
-        specs::Read<'a, res::Framebuffer>,
-        specs::Read<'a, res::DynamicState>,
-        specs::Read<'a, res::ViewProjectionSet>,
-        specs::Read<'a, res::TextureSet>,
         specs::Write<'a, res::SortedRender>,
         specs::ReadStorage<'a, comp::Sprite>,
         specs::ReadStorage<'a, comp::TileMap>,
         specs::ReadStorage<'a, comp::RenderStrip>,
-        specs::ReadStorage<'a, comp::Transform>
+        specs::ReadStorage<'a, comp::Transform>,
+        B::SystemData,
     );
 
-    fn run(&mut self, (device, queue, framebuffer, state, view_proj, tex_set, mut sort_rndr, sprite, map, strip, tran): Self::SystemData) {
-        let queue = queue.0.as_ref().unwrap();
-        let device = device.0.as_ref().unwrap();
-        let framebuffer = framebuffer.0.as_ref().unwrap();
-        let state = state.0.as_ref().unwrap();
-        let view_proj = view_proj.0.as_ref().unwrap();
-        let tex_set = tex_set.0.as_ref().unwrap();
-
-        if sort_rndr.need_sort {
-            dmsort::sort_by(&mut sort_rndr.ids, |id1, id2| {
-                let get_values = |id: &res::RenderId| {
-                    match *id {
-                        res::RenderId::Sprite(e) => {
-                            let t = tran.get(e).unwrap();
-                            let s = sprite.get(e).unwrap();
-                            let b = t.pos.y + s.bounds.max.y;
-
-                            (t.pos.z, b)
-                        },
-                        res::RenderId::TileStrip(e) => {
-                            let s = strip.get(e).unwrap();
-                            let m = map.get(s.tile_map()).unwrap();
-                            let b = m.tile_dims().y * (s.pos().y + 1) as f32;
-                            let l = s.pos().z as f32 * m.tile_dims().z;
-
-                            (l, b)
-                        }
-                    }
-                };
-
-                let (l1, b1) = get_values(id1);
-                let (l2, b2) = get_values(id2);
-
-                use std::cmp::Ordering;
-                let order = l1.partial_cmp(&l2).unwrap();
-
-                match order {
-                    Ordering::Less => order,
-                    Ordering::Greater => order,
-                    Ordering::Equal => b1.partial_cmp(&b2).unwrap()
-                }
-            });
-        }
+    fn run(&mut self, (mut sort_rndr, sprite, map, strip, tran, backend_data): Self::SystemData) {
+        use cgmath::One;
 
-        // Holds the list of commands that are going to be executed.
-        // * The only queues able to execute the command buffer are the ones of the family passed to the constructor.
-        let mut builder = AutoCommandBufferBuilder::primary_one_time_submit(device.clone(), queue.family()).unwrap()
-            .begin_render_pass(
-                framebuffer.clone(), 
-                false, 
-                // Clear values for the attachments marked 'load: Clear' on the render pass.
-                vec![
-                    // The color used to clear the screen.
-                    [1.0, 1.0, 1.0, 1.0].into()
-                ]
-            ).unwrap();
-
-        // Now to render.
-        for id in sort_rndr.ids.iter() {
+        // Anything whose components are gone (until its system stops drawing it) is skipped, rather than drawn.
+        let sort_key = |id: &res::RenderId| {
             match *id {
                 res::RenderId::Sprite(e) => {
-                    let sprite = sprite.get(e).unwrap();
-
-                    let instance_set = sprite.instance_set.as_ref().unwrap();
-                    let v_buf = sprite.vertex_buf.as_ref().unwrap();
-                    let i_buf = sprite.index_buf.as_ref().unwrap();
-
-                    builder = builder.draw_indexed(
-                        self.pipeline.clone(),
-                        state.clone(),
-                        vec![v_buf.clone()],
-                        i_buf.clone(),
-                        (instance_set.clone(), view_proj.clone(), tex_set.clone()),
-                        (fs::ty::PER_OBJECT { imgIdx: sprite.image_index })
-                    ).unwrap();
+                    let t = tran.get(e)?;
+                    let s = sprite.get(e)?;
+
+                    Some(SortKey::new(t.pos.z, t.pos.y + s.bounds.max.y))
                 },
                 res::RenderId::TileStrip(e) => {
-                    let strip = strip.get(e).unwrap();
-                    let map = map.get(strip.tile_map()).unwrap();
-
-                    let v_buf = strip.vertex_buf.as_ref().unwrap();
-                    let i_buf = strip.index_buf.as_ref().unwrap();
-
-                    builder = builder.draw_indexed(
-                        self.pipeline.clone(),
-                        state.clone(),
-                        vec![v_buf.clone()],
-                        i_buf.clone(),
-                        (self.default_instance.clone(), view_proj.clone(), tex_set.clone()),
-                        (fs::ty::PER_OBJECT { imgIdx: map.image_index() })
-                    ).unwrap();
+                    let s = strip.get(e)?;
+                    let m = map.get(s.tile_map())?;
+
+                    Some(SortKey::new(
+                        s.pos().z as f32 * m.tile_dims().z, 
+                        m.tile_dims().y * (s.pos().y + 1) as f32
+                    ))
                 }
             }
+        };
+
+        if sort_rndr.need_sort {
+            dmsort::sort_by(&mut sort_rndr.ids, |id1, id2| match (sort_key(id1), sort_key(id2)) {
+                (Some(key1), Some(key2)) => key1.order(&key2),
+                _ => Ordering::Equal,
+            });
         }
 
-        let command_buffer = 
-            builder.end_render_pass().unwrap()
-            .build().unwrap();
+        self.commands.clear();
 
-        // Sends the built command buffer off for execution.
-        self.cmd_buf_tx.send(command_buffer)
-            .expect("Couldn't send the command buffer, receiving end disconnected.");
+        for id in sort_rndr.ids.iter() {
+            let drawn = match *id {
+                res::RenderId::Sprite(e) => sprite.get(e).map(|sprite| (sprite.image_index, sprite.transform)),
+                // Strip vertices are already positioned within the tile map.
+                res::RenderId::TileStrip(e) => strip.get(e)
+                    .and_then(|strip| map.get(strip.tile_map()))
+                    .map(|map| (map.image_index(), cgmath::Matrix4::one())),
+            };
+
+            let ((image_index, transform), key) = match (drawn, sort_key(id)) {
+                (Some(drawn), Some(key)) => (drawn, key),
+                _ => continue,
+            };
+
+            self.commands.push(DrawCommand {
+                mesh: *id,
+                image_index,
+                transform,
+                sort_key: key,
+            });
+        }
+
+        self.backend.draw(&self.commands, backend_data);
     }
 
     fn setup(&mut self, res: &mut specs::Resources) {
         use specs::prelude::SystemData;
         Self::SystemData::setup(res);
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn draw_order() {
+    use ::render::RecordingBackend;
+    use ::system::HeadlessSpriteSystem;
+    use ::utility::Rect2;
+    use specs::{Builder, RunNow};
+    use cgmath::{Vector2, Vector3};
+
+    let mut world = specs::World::new();
+
+    let mut sprite_sys = HeadlessSpriteSystem::new();
+    let mut render_sys = RenderSystem::new(RecordingBackend::new());
+    sprite_sys.setup(&mut world.res);
+    render_sys.setup(&mut world.res);
+    world.add_resource(res::Interpolation(1.0));
+
+    let sprite = |image_index| comp::Sprite::new(
+        Rect2::new(Vector2::new(0.0, 0.0), Vector2::new(0.1, 0.1)), 
+        Rect2::new(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)),
+        image_index
+    );
+
+    // On a higher layer than the others, so it's drawn last despite being the furthest down.
+    let top = world.create_entity()
+        .with(comp::Transform::new(Vector3::new(0.0, 1.0, 1.0)))
+        .with(sprite(0))
+    .build();
+    let lower = world.create_entity()
+        .with(comp::Transform::new(Vector3::new(0.5, 0.5, 0.0)))
+        .with(sprite(1))
+    .build();
+    let upper = world.create_entity()
+        .with(comp::Transform::new(Vector3::new(0.0, 0.2, 0.0)))
+        .with(sprite(2))
+    .build();
+
+    sprite_sys.run_now(&world.res);
+    render_sys.run_now(&world.res);
+
+    let backend = render_sys.backend();
+    assert_eq!(backend.frames, 1);
+
+    let drawn: Vec<_> = backend.commands.iter().map(|cmd| (cmd.mesh, cmd.image_index)).collect();
+    assert_eq!(drawn, vec![
+        (res::RenderId::Sprite(upper), 2),
+        (res::RenderId::Sprite(lower), 1),
+        (res::RenderId::Sprite(top), 0),
+    ]);
+
+    // Sprites are drawn where they are.
+    let lower_cmd = &backend.commands[1];
+    assert_eq!(lower_cmd.transform, comp::Transform::new(Vector3::new(0.5, 0.5, 0.0)).matrix(1.0));
+    assert_eq!(lower_cmd.sort_key, SortKey::new(0.0, 0.6));
+}
+
+#[test]
+fn removed_sprites() {
+    use ::render::RecordingBackend;
+    use ::system::HeadlessSpriteSystem;
+    use ::utility::Rect2;
+    use specs::{Builder, RunNow};
+    use cgmath::{Vector2, Vector3};
+
+    let mut world = specs::World::new();
+
+    let mut sprite_sys = HeadlessSpriteSystem::new();
+    let mut render_sys = RenderSystem::new(RecordingBackend::new());
+    sprite_sys.setup(&mut world.res);
+    render_sys.setup(&mut world.res);
+    world.add_resource(res::Interpolation(1.0));
+
+    let mut sprited = || world.create_entity()
+        .with(comp::Transform::new(Vector3::new(0.0, 0.0, 0.0)))
+        .with(comp::Sprite::new(
+            Rect2::new(Vector2::new(0.0, 0.0), Vector2::new(0.1, 0.1)), 
+            Rect2::new(Vector2::new(0.0, 0.0), Vector2::new(1.0, 1.0)),
+            0
+        ))
+    .build();
+    let (deleted, stripped, kept) = (sprited(), sprited(), sprited());
+
+    sprite_sys.run_now(&world.res);
+    render_sys.run_now(&world.res);
+    assert_eq!(render_sys.backend().commands.len(), 3);
+
+    world.delete_entity(deleted).unwrap();
+    world.write_storage::<comp::Sprite>().remove(stripped);
+    world.maintain();
+
+    // Drawn straight away (before the sprite system notices), the missing sprites are skipped.
+    render_sys.run_now(&world.res);
+    assert_eq!(render_sys.backend().commands.len(), 1);
+
+    sprite_sys.run_now(&world.res);
+    render_sys.run_now(&world.res);
+    assert_eq!(world.read_resource::<res::SortedRender>().ids, vec![res::RenderId::Sprite(kept)]);
+    assert_eq!(render_sys.backend().frames, 3);
+}
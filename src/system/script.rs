@@ -1,8 +1,8 @@
 use ::component as comp;
 use ::resource as res;
-use script::{LuaEntity, LuaWorld};
+use script::{Script, LuaEntity, ComponentFields, with_world, coroutine};
 
-use rlua::{Function as LuaFunction};
+use rlua::{Function as LuaFunction, Value as LuaValue, Table, Thread, ThreadStatus, MultiValue, Result as LuaResult};
 use cgmath::{Zero, Vector3};
 use specs;
 
@@ -10,29 +10,231 @@ pub struct OnTickEvent;
 
 impl<'a> specs::RunNow<'a> for OnTickEvent {
     fn run_now(&mut self, res: &'a specs::Resources) {
-        use specs::Join;
-
-        let (ent, behav, script, dt): (specs::Entities, specs::ReadStorage<comp::ScriptBehavior>, specs::Read<res::Script>, specs::Read<res::DeltaTime>) = specs::SystemData::fetch(&res);
+        let (script, mut errors, dt): (
+            specs::Read<res::Script>, 
+            specs::Write<res::ScriptErrors>, 
+            specs::Read<res::DeltaTime>
+        ) = specs::SystemData::fetch(&res);
 
         let dt = dt.0;
         if let Some(ref mutex) = script.0 {
             let script = mutex.lock().unwrap();
 
-            for (ent, behav) in (&*ent, &behav).join() {
-                unsafe {
-                    let res = res as *const _;
+            // Timers go first, so the ones added this tick (by any callback) only start counting down next tick.
+            run_timers(&script, res, dt, &mut errors);
+
+            run_callbacks(&script, res, "on_tick", dt, &mut errors);
+
+            resume_coroutines(&script, res, dt, &mut errors);
+        }
+    }
+    
+    fn setup(&mut self, res: &mut specs::Resources) {
+        <(
+            specs::ReadStorage<comp::ScriptBehavior>, 
+            specs::ReadStorage<comp::Timers>, 
+            specs::Read<res::Script>, 
+            specs::Write<res::ScriptErrors>
+        ) as specs::SystemData>::setup(res);
+    }
+}
+
+/* NOTE:
+    Behaviors' 'on_update' callbacks are called once every update (i.e. every frame) with the real frame time, which
+    the time scale doesn't affect. They're called even while the game is paused or single-stepping, so they're where
+    scripts are able to unpause it (e.g. from a pause menu).
+*/
+pub fn run_on_updates(res: &specs::Resources, frame_time: f32) {
+    let (script, mut errors): (specs::Read<res::Script>, specs::Write<res::ScriptErrors>) = specs::SystemData::fetch(&res);
+
+    if let Some(ref mutex) = script.0 {
+        run_callbacks(&mutex.lock().unwrap(), res, "on_update", frame_time, &mut errors);
+    }
+}
+
+// Calls one kind of the behaviors' callbacks, given the time passed.
+fn run_callbacks(script: &Script, res: &specs::Resources, name: &'static str, dt: f32, errors: &mut res::ScriptErrors) {
+    use specs::Join;
+
+    // Collected up front, so the callbacks are free to change any component (including their own behavior).
+    let callbacks: Vec<_> = {
+        let (ent, behav): (specs::Entities, specs::ReadStorage<comp::ScriptBehavior>) = specs::SystemData::fetch(&res);
+
+        (&*ent, &behav).join()
+            .filter_map(|(ent, behav)| match behav.get_field(name, script) {
+                Ok(LuaValue::Function(func)) => Some((ent, func, state(script, behav))),
+                _ => None,
+            })
+            .collect()
+    };
+
+    // A failing callback only stops its own entity's behavior (and only once it keeps failing).
+    let mut disabled = Vec::new();
+    for (ent, func, state) in callbacks {
+        let entry = LuaValue::Function(func.clone());
+        if let Err(err) = with_world(script, res, ent, entry, |world| func.call::<_, ()>((world, LuaEntity(ent), dt, state))) {
+            if errors.report(ent, name, err) {
+                disabled.push(ent);
+            }
+        }
+    }
+
+    if !disabled.is_empty() {
+        let mut behav: specs::WriteStorage<comp::ScriptBehavior> = specs::SystemData::fetch(&res);
+
+        for ent in disabled {
+            if let Some(behav) = behav.get_mut(ent) {
+                behav.set_field(name, LuaValue::Nil, script).unwrap();
+            }
+        }
+    }
+}
 
-                    let on_tick = behav.on_tick.as_ref();
+// The entity's state table (see 'script::state').
+pub fn state<'lua>(script: &'lua Script, behav: &comp::ScriptBehavior) -> Option<Table<'lua>> {
+    behav.state.as_ref().and_then(|x| script.registry_value::<Table>(x).ok())
+}
 
-                    if let Some(func) = on_tick.and_then(|x| script.registry_value::<LuaFunction>(&x).ok()) {
-                        func.call::<_, ()>((LuaWorld(res), LuaEntity(ent), dt)).unwrap();
+// Counts down the timers, calling the ones that are up.
+fn run_timers(script: &Script, res: &specs::Resources, dt: f32, errors: &mut res::ScriptErrors) {
+    use specs::Join;
+
+    let due: Vec<_> = {
+        let (ent, mut timers): (specs::Entities, specs::WriteStorage<comp::Timers>) = specs::SystemData::fetch(&res);
+        let mut due = Vec::new();
+
+        for (ent, timers) in (&*ent, &mut timers).join() {
+            for timer in &mut timers.timers {
+                timer.remaining -= dt;
+
+                if timer.remaining <= 0.0 {
+                    due.push((ent, timer.id, timer.interval.is_some(), script.registry_value::<LuaFunction>(&timer.func).unwrap()));
+
+                    // A timer only goes off once a tick, however short its interval.
+                    if let Some(interval) = timer.interval {
+                        timer.remaining = (timer.remaining + interval).max(0.0);
                     }
                 }
             }
+
+            timers.timers.retain(|x| x.interval.is_some() || x.remaining > 0.0);
+        }
+
+        due
+    };
+
+    for (ent, id, repeats, func) in due {
+        let result = with_world(script, res, ent, LuaValue::Function(func.clone()), |world| {
+            // Whether it returned false.
+            func.call::<_, LuaValue>((world, LuaEntity(ent))).map(|ret| match ret {
+                LuaValue::Boolean(false) => true,
+                _ => false,
+            })
+        });
+
+        let stop = match result {
+            Ok(returned_false) => returned_false && repeats,
+            Err(err) => errors.report(ent, "timer", err) && repeats,
+        };
+
+        if stop {
+            let mut timers: specs::WriteStorage<comp::Timers> = specs::SystemData::fetch(&res);
+            if let Some(timers) = timers.get_mut(ent) {
+                timers.remove(id);
+            }
         }
     }
-    
-    fn setup(&mut self, res: &mut specs::Resources) {
-        <specs::ReadStorage<comp::ScriptBehavior> as specs::SystemData>::setup(res);
+}
+
+enum Resumed {
+    // The condition it's waiting for hasn't been met yet.
+    Waiting,
+    Yielded(comp::Wait),
+    Finished,
+}
+
+// Resumes the behaviors' coroutines which are done waiting.
+fn resume_coroutines(script: &Script, res: &specs::Resources, dt: f32, errors: &mut res::ScriptErrors) {
+    use specs::Join;
+
+    let ready: Vec<_> = {
+        let (ent, mut behav): (specs::Entities, specs::WriteStorage<comp::ScriptBehavior>) = specs::SystemData::fetch(&res);
+        let mut ready = Vec::new();
+
+        for (ent, behav) in (&*ent, &mut behav).join() {
+            let thread = match behav.run {
+                Some(ref key) => script.registry_value::<Thread>(key).unwrap(),
+                None => continue,
+            };
+
+            let condition = match behav.wait {
+                comp::Wait::Tick => None,
+                comp::Wait::Seconds(ref mut seconds) => {
+                    *seconds -= dt;
+                    if *seconds > 0.0 {
+                        continue;
+                    }
+                    None
+                },
+                comp::Wait::Ticks(ref mut ticks) => {
+                    *ticks = ticks.saturating_sub(1);
+                    if *ticks > 0 {
+                        continue;
+                    }
+                    None
+                },
+                comp::Wait::Until(ref key) => Some(script.registry_value::<LuaFunction>(key).unwrap()),
+            };
+
+            ready.push((ent, behav.runs, thread, condition, state(script, behav)));
+        }
+
+        ready
+    };
+
+    for (ent, runs, thread, condition, state) in ready {
+        let result = with_world(script, res, ent, LuaValue::Thread(thread.clone()), |_| -> LuaResult<Resumed> {
+            if let Some(condition) = condition {
+                if !condition.call::<_, bool>(())? {
+                    return Ok(Resumed::Waiting);
+                }
+            }
+
+            let values = thread.resume::<_, MultiValue>((coroutine::world(script)?, LuaEntity(ent), dt, state))?;
+
+            match thread.status() {
+                ThreadStatus::Resumable => Ok(Resumed::Yielded(coroutine::wait_from_lua(script, values)?)),
+                _ => Ok(Resumed::Finished),
+            }
+        });
+
+        let mut behav: specs::WriteStorage<comp::ScriptBehavior> = specs::SystemData::fetch(&res);
+
+        // The coroutine may have been replaced (or its behavior removed) while it ran.
+        let behav = match behav.get_mut(ent) {
+            Some(behav) => behav,
+            None => continue,
+        };
+
+        if behav.runs != runs {
+            continue;
+        }
+
+        let stop = match result {
+            Ok(Resumed::Waiting) => false,
+            Ok(Resumed::Yielded(wait)) => {
+                behav.wait = wait;
+                false
+            },
+            Ok(Resumed::Finished) => true,
+            // A coroutine that failed can't be resumed, but a failing condition is only given up on eventually.
+            Err(err) => errors.report(ent, "run", err) || thread.status() != ThreadStatus::Resumable,
+        };
+
+        if stop {
+            if let Some(key) = behav.run.take() {
+                script.remove_registry_value(key).unwrap();
+            }
+        }
     }
 }
\ No newline at end of file
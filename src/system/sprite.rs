@@ -1,30 +1,37 @@
+#[cfg(feature = "render")]
 use ::{vs, Vertex};
 use ::component as comp;
 use ::resource as res;
 
+#[cfg(feature = "render")]
 use std::sync::Arc;
 
+#[cfg(feature = "render")]
 use vulkano as vk;
+#[cfg(feature = "render")]
 use vk::descriptor::descriptor_set::FixedSizeDescriptorSetsPool;
+#[cfg(feature = "render")]
 use vk::buffer::CpuBufferPool;
-use cgmath::{Matrix4};
 use specs;
 
+#[cfg(feature = "render")]
 pub struct SpriteSystem<L> {
     instance_sets: FixedSizeDescriptorSetsPool<Arc<L>>,
     instance_buf: CpuBufferPool<vs::ty::Instance>,
 
     sprite_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
     sprite_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
+    sprite_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
     ins_sprite: specs::BitSet,
     mod_sprite: specs::BitSet,
+    rem_sprite: specs::BitSet,
     
     transform_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
     transform_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
     updt_transform: specs::BitSet,
 }
 
-
+#[cfg(feature = "render")]
 impl<L> SpriteSystem<L>
 where
     L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
@@ -38,8 +45,10 @@ where
             instance_buf,
             sprite_ins_read: None,
             sprite_mod_read: None,
+            sprite_rem_read: None,
             ins_sprite: specs::BitSet::new(),
             mod_sprite: specs::BitSet::new(),
+            rem_sprite: specs::BitSet::new(),
             transform_ins_read: None,
             transform_mod_read: None,
             updt_transform: specs::BitSet::new(),
@@ -47,19 +56,21 @@ where
     }
 }
 
+#[cfg(feature = "render")]
 impl<'a, L> specs::System<'a> for SpriteSystem<L>
 where
     L: vk::descriptor::PipelineLayoutAbstract + vk::pipeline::GraphicsPipelineAbstract + Send + Sync + 'static,
 {
     type SystemData = (
         specs::Read<'a, res::Queue>,
+        specs::Read<'a, res::Interpolation>,
         specs::Write<'a, res::SortedRender>,
         specs::Entities<'a>, 
         specs::ReadStorage<'a, comp::Transform>, 
         specs::WriteStorage<'a, comp::Sprite>,
     );
 
-    fn run(&mut self, (queue, mut sort_rndr, ent, tran, mut spr): Self::SystemData) {
+    fn run(&mut self, (queue, alpha, mut sort_rndr, ent, tran, mut spr): Self::SystemData) {
         use specs::Join;
 
         let queue = queue.0.as_ref().unwrap();
@@ -67,13 +78,18 @@ where
         // Get the components in need of initialization or an update.
         self.ins_sprite.clear();
         self.mod_sprite.clear();
+        self.rem_sprite.clear();
         self.updt_transform.clear();
         
         spr.populate_inserted(&mut self.sprite_ins_read.as_mut().unwrap(), &mut self.ins_sprite);
         spr.populate_modified(&mut self.sprite_mod_read.as_mut().unwrap(), &mut self.mod_sprite);
+        spr.populate_removed(&mut self.sprite_rem_read.as_mut().unwrap(), &mut self.rem_sprite);
         tran.populate_inserted(&mut self.transform_ins_read.as_mut().unwrap(), &mut self.updt_transform);
         tran.populate_modified(&mut self.transform_mod_read.as_mut().unwrap(), &mut self.updt_transform);
 
+        // Stop drawing removed sprites (before any reinserted ones are added back).
+        sort_rndr.remove_sprites(&self.rem_sprite);
+
         for (ent, mut spr, _) in (&*ent, &mut spr, &self.ins_sprite | &self.mod_sprite).join() {
             let vertex_data = vec![
                 Vertex {
@@ -124,9 +140,16 @@ where
             sort_rndr.need_sort = true;
         }
 
-        for (mut spr, tran, _) in (&mut spr, &tran, &self.updt_transform).join() {
+        for (ent, mut spr, tran) in (&*ent, &mut spr, &tran).join() {
+            // Sprites that moved last tick are drawn somewhere between where they were and where they are, every frame.
+            if !self.updt_transform.contains(ent.id()) && relative_eq!(tran.last_pos, tran.pos) {
+                continue;
+            }
+
+            spr.transform = tran.matrix(alpha.0);
+
             let instance_data = vs::ty::Instance {
-                transform: Matrix4::from_translation(tran.pos).into(),
+                transform: spr.transform.into(),
             };
 
             let instance_subbuf = self.instance_buf.next(instance_data)
@@ -144,6 +167,9 @@ where
             // After updating the transform data, the sprite needs to be resorted.
             sort_rndr.need_sort = true;
         }
+
+        // Skip the modifications made by this system, so the buffers are only rebuilt when something else changes.
+        spr.populate_modified(&mut self.sprite_mod_read.as_mut().unwrap(), &mut self.mod_sprite);
     }
 
     fn setup(&mut self, res: &mut specs::Resources) {
@@ -153,9 +179,95 @@ where
         let mut spr_storage: specs::WriteStorage<comp::Sprite> = SystemData::fetch(&res);
         self.sprite_ins_read = Some(spr_storage.track_inserted());
         self.sprite_mod_read = Some(spr_storage.track_modified());
+        self.sprite_rem_read = Some(spr_storage.track_removed());
 
         let mut tran_storage: specs::WriteStorage<comp::Transform> = SystemData::fetch(&res);
         self.transform_ins_read = Some(tran_storage.track_inserted());        
         self.transform_mod_read = Some(tran_storage.track_modified());        
     }
-}
\ No newline at end of file
+}
+
+// Keeps sprites' render order and instance transforms up to date, without creating any Vulkan buffers.
+pub struct HeadlessSpriteSystem {
+    sprite_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
+    sprite_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
+    ins_sprite: specs::BitSet,
+    rem_sprite: specs::BitSet,
+    
+    transform_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
+    transform_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
+    updt_transform: specs::BitSet,
+}
+
+impl HeadlessSpriteSystem {
+    pub fn new() -> HeadlessSpriteSystem {
+        HeadlessSpriteSystem {
+            sprite_ins_read: None,
+            sprite_rem_read: None,
+            ins_sprite: specs::BitSet::new(),
+            rem_sprite: specs::BitSet::new(),
+            transform_ins_read: None,
+            transform_mod_read: None,
+            updt_transform: specs::BitSet::new(),
+        }
+    }
+}
+
+impl Default for HeadlessSpriteSystem {
+    fn default() -> Self {
+        HeadlessSpriteSystem::new()
+    }
+}
+
+impl<'a> specs::System<'a> for HeadlessSpriteSystem {
+    type SystemData = (
+        specs::Read<'a, res::Interpolation>,
+        specs::Write<'a, res::SortedRender>,
+        specs::Entities<'a>, 
+        specs::ReadStorage<'a, comp::Transform>, 
+        specs::WriteStorage<'a, comp::Sprite>,
+    );
+
+    fn run(&mut self, (alpha, mut sort_rndr, ent, tran, mut spr): Self::SystemData) {
+        use specs::Join;
+
+        self.ins_sprite.clear();
+        self.rem_sprite.clear();
+        self.updt_transform.clear();
+        
+        spr.populate_inserted(&mut self.sprite_ins_read.as_mut().unwrap(), &mut self.ins_sprite);
+        spr.populate_removed(&mut self.sprite_rem_read.as_mut().unwrap(), &mut self.rem_sprite);
+        tran.populate_inserted(&mut self.transform_ins_read.as_mut().unwrap(), &mut self.updt_transform);
+        tran.populate_modified(&mut self.transform_mod_read.as_mut().unwrap(), &mut self.updt_transform);
+
+        sort_rndr.remove_sprites(&self.rem_sprite);
+
+        // Only sprites still around (one inserted and removed since the last run isn't drawn at all).
+        for (ent, _, _) in (&*ent, &spr, &self.ins_sprite).join() {
+            sort_rndr.ids.push(res::RenderId::Sprite(ent));
+            sort_rndr.need_sort = true;
+        }
+
+        for (ent, mut spr, tran) in (&*ent, &mut spr, &tran).join() {
+            if !self.updt_transform.contains(ent.id()) && relative_eq!(tran.last_pos, tran.pos) {
+                continue;
+            }
+
+            spr.transform = tran.matrix(alpha.0);
+            sort_rndr.need_sort = true;
+        }
+    }
+
+    fn setup(&mut self, res: &mut specs::Resources) {
+        use specs::prelude::SystemData;
+        Self::SystemData::setup(res);
+
+        let mut spr_storage: specs::WriteStorage<comp::Sprite> = SystemData::fetch(&res);
+        self.sprite_ins_read = Some(spr_storage.track_inserted());
+        self.sprite_rem_read = Some(spr_storage.track_removed());
+
+        let mut tran_storage: specs::WriteStorage<comp::Transform> = SystemData::fetch(&res);
+        self.transform_ins_read = Some(tran_storage.track_inserted());        
+        self.transform_mod_read = Some(tran_storage.track_modified());        
+    }
+}
@@ -1,13 +1,18 @@
+#[cfg(feature = "render")]
 use ::{vs, Vertex};
 use ::component as comp;
 use ::resource as res;
 use ::parse;
 use ::utility::{Rect2, Rect3};
 
+#[cfg(feature = "render")]
 use std::sync::Arc;
 
+#[cfg(feature = "render")]
 use vulkano as vk;
+#[cfg(feature = "render")]
 use vk::descriptor::descriptor_set::FixedSizeDescriptorSetsPool;
+#[cfg(feature = "render")]
 use vk::buffer::CpuBufferPool;
 use cgmath::{Vector2, Vector3, Matrix4, Zero};
 use specs;
@@ -126,6 +131,66 @@ impl<'a> specs::System<'a> for TileMapSystem {
                                     }
                                 }
                             }
+
+                            // If the layer's data represents the direction a tile can be passed through (one-way collision).
+                            parse::LayerProperty::OneWay => {
+                                for (idx, strip) in layer.strips.iter().enumerate() {
+                                    let strip_pos = Vector3::new(
+                                        idx as u32 % chunk.dimensions.x,
+                                        (idx as f32 / chunk.dimensions.x as f32).floor() as u32,
+                                        chunk.pos.z
+                                    );
+
+                                    let data: Vec<Option<Vector3<f32>>> = strip.iter()
+                                        .map(|num| match *num {
+                                            1 => Some(Vector3::new(0.0, -1.0, 0.0)),
+                                            2 => Some(Vector3::new(0.0, 1.0, 0.0)),
+                                            3 => Some(Vector3::new(-1.0, 0.0, 0.0)),
+                                            4 => Some(Vector3::new(1.0, 0.0, 0.0)),
+                                            _ => None,
+                                        })
+                                    .collect();
+
+                                    let mut one_way = [None; comp::tilemap::STRIP_LENGTH];
+                                    one_way.copy_from_slice(&data[..]);
+
+                                    // Make sure the strip exists (with no blocking tiles of its own) and set its one-way data.
+                                    collision.entry(strip_pos)
+                                        .or_insert_with(|| comp::CollisionStrip::new(
+                                            ent,
+                                            strip_pos,
+                                            [false; comp::tilemap::STRIP_LENGTH],
+                                        ))
+                                        .one_way = one_way;
+                                }
+                            }
+
+                            // If the layer's data represents the collision shape of a tile.
+                            parse::LayerProperty::Shape => {
+                                for (idx, strip) in layer.strips.iter().enumerate() {
+                                    let strip_pos = Vector3::new(
+                                        idx as u32 % chunk.dimensions.x,
+                                        (idx as f32 / chunk.dimensions.x as f32).floor() as u32,
+                                        chunk.pos.z
+                                    );
+
+                                    let data: Vec<Option<comp::tilemap::TileShape>> = strip.iter()
+                                        .map(|num| comp::tilemap::TileShape::from_index(*num))
+                                    .collect();
+
+                                    let mut shapes = [None; comp::tilemap::STRIP_LENGTH];
+                                    shapes.copy_from_slice(&data[..]);
+
+                                    // Make sure the strip exists (with no blocking tiles of its own) and set its shape data.
+                                    collision.entry(strip_pos)
+                                        .or_insert_with(|| comp::CollisionStrip::new(
+                                            ent,
+                                            strip_pos,
+                                            [false; comp::tilemap::STRIP_LENGTH],
+                                        ))
+                                        .shapes = shapes;
+                                }
+                            }
                         }
                     }
                 }
@@ -184,8 +249,16 @@ impl<'a> specs::System<'a> for TileMapCollisionSystem {
         strip.populate_inserted(&mut self.collision_strip_ins_read.as_mut().unwrap(), &mut self.ins_collision_strip);
         strip.populate_modified(&mut self.collision_strip_mod_read.as_mut().unwrap(), &mut self.mod_collision_strip);
 
-        for (mut strip, _) in (&mut strip, &self.ins_collision_strip).join() {
-            let map = map.get(strip.tile_map()).unwrap();
+        for (strip_ent, mut strip, _) in (&*ents, &mut strip, &self.ins_collision_strip).join() {
+            let map = map.get_mut(strip.tile_map()).unwrap();
+
+            // Keep track of the strip, so the tile map can be used for grid collision.
+            map.collision_strips.insert(strip.pos(), strip_ent);
+
+            // Grid collision doesn't need entities for each tile.
+            if map.grid_collision {
+                continue;
+            }
             
             let strip_pos = Vector3::new(
                 strip.pos().x as f32 * comp::tilemap::STRIP_LENGTH as f32 * map.tile_dims().x,
@@ -193,11 +266,12 @@ impl<'a> specs::System<'a> for TileMapCollisionSystem {
                 strip.pos().z as f32 * map.tile_dims().z
             );
             
-            for (idx, tile) in strip.blocking.iter().enumerate() {
-                // If this tile does not block, try the next one.
-                if !*tile {
-                    continue;
-                }
+            for idx in 0..comp::tilemap::STRIP_LENGTH {
+                // If this tile does not block (not even one way), try the next one.
+                let shape = match strip.tile_shape(idx) {
+                    Some(shape) => shape,
+                    None => continue,
+                };
 
                 let tile_pos = Vector3::new(
                     idx as f32 * map.tile_dims().x,
@@ -205,16 +279,11 @@ impl<'a> specs::System<'a> for TileMapCollisionSystem {
                     0.0
                 );
 
-                let coll = comp::Collider::new(
-                    comp::collider::Shape::AABB(
-                        Rect3::new(
-                            Vector3::zero(),
-                            map.tile_dims(),
-                        )
-                    ), 
-                    false, 
-                    None
+                let mut coll = comp::Collider::new(
+                    shape.collider_shape(map.tile_dims()), 
+                    false
                 );
+                coll.one_way = strip.one_way[idx];
 
                 let tran = comp::Transform::new(
                     strip_pos + tile_pos,
@@ -243,24 +312,38 @@ impl<'a> specs::System<'a> for TileMapCollisionSystem {
 }
 
 // Creates and manages render data (vertex buffers, index buffers, etc.) of render strips.
+#[cfg(feature = "render")]
 pub struct TileMapRenderSystem {
     render_strip_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
     render_strip_mod_read: Option<specs::ReaderId<specs::ModifiedFlag>>,
+    render_strip_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
     ins_render_strip: specs::BitSet,
     mod_render_strip: specs::BitSet,
+    rem_render_strip: specs::BitSet,
 }
 
+#[cfg(feature = "render")]
 impl TileMapRenderSystem {
     pub fn new() -> TileMapRenderSystem {
         TileMapRenderSystem {
             render_strip_ins_read: None,
             render_strip_mod_read: None,
+            render_strip_rem_read: None,
             ins_render_strip: specs::BitSet::new(),
             mod_render_strip: specs::BitSet::new(),
+            rem_render_strip: specs::BitSet::new(),
         }
     }
 }
 
+#[cfg(feature = "render")]
+impl Default for TileMapRenderSystem {
+    fn default() -> Self {
+        TileMapRenderSystem::new()
+    }
+}
+
+#[cfg(feature = "render")]
 impl<'a> specs::System<'a> for TileMapRenderSystem {
     type SystemData = (
         specs::Read<'a, res::Queue>,
@@ -278,14 +361,23 @@ impl<'a> specs::System<'a> for TileMapRenderSystem {
         // Get the components in need of initialization or an update.
         self.ins_render_strip.clear();
         self.mod_render_strip.clear();
+        self.rem_render_strip.clear();
         
         strip.populate_inserted(&mut self.render_strip_ins_read.as_mut().unwrap(), &mut self.ins_render_strip);
         strip.populate_modified(&mut self.render_strip_mod_read.as_mut().unwrap(), &mut self.mod_render_strip);
+        strip.populate_removed(&mut self.render_strip_rem_read.as_mut().unwrap(), &mut self.rem_render_strip);
+
+        // Stop drawing removed strips (before any reinserted ones are added back).
+        sort_rndr.remove_strips(&self.rem_render_strip);
 
         for (ent, mut strip, _) in (&*ent, &mut strip, &self.ins_render_strip | &self.mod_render_strip).join() {
             // Create the vertex and index buffers for all the strips without them.
             if strip.vertex_buf.is_none() || strip.index_buf.is_none() {
-                let map = map.get(strip.tile_map()).unwrap();
+                // A strip whose tile map is gone has nothing to be drawn with.
+                let map = match map.get(strip.tile_map()) {
+                    Some(map) => map,
+                    None => continue,
+                };
 
                 let world_pos = Vector3::new(
                     (strip.pos().x * comp::tilemap::STRIP_LENGTH as u32) as f32 * map.tile_dims().x,
@@ -365,5 +457,64 @@ impl<'a> specs::System<'a> for TileMapRenderSystem {
         let mut rndr_strip_storage: specs::WriteStorage<comp::RenderStrip> = SystemData::fetch(&res);
         self.render_strip_ins_read = Some(rndr_strip_storage.track_inserted());
         self.render_strip_mod_read = Some(rndr_strip_storage.track_modified());
+        self.render_strip_rem_read = Some(rndr_strip_storage.track_removed());
     }
-}
\ No newline at end of file
+}
+
+// Keeps render strips in the render order, without creating any Vulkan buffers.
+pub struct HeadlessTileMapRenderSystem {
+    render_strip_ins_read: Option<specs::ReaderId<specs::InsertedFlag>>,
+    render_strip_rem_read: Option<specs::ReaderId<specs::RemovedFlag>>,
+    ins_render_strip: specs::BitSet,
+    rem_render_strip: specs::BitSet,
+}
+
+impl HeadlessTileMapRenderSystem {
+    pub fn new() -> HeadlessTileMapRenderSystem {
+        HeadlessTileMapRenderSystem {
+            render_strip_ins_read: None,
+            render_strip_rem_read: None,
+            ins_render_strip: specs::BitSet::new(),
+            rem_render_strip: specs::BitSet::new(),
+        }
+    }
+}
+
+impl Default for HeadlessTileMapRenderSystem {
+    fn default() -> Self {
+        HeadlessTileMapRenderSystem::new()
+    }
+}
+
+impl<'a> specs::System<'a> for HeadlessTileMapRenderSystem {
+    type SystemData = (
+        specs::Write<'a, res::SortedRender>,
+        specs::Entities<'a>,
+        specs::WriteStorage<'a, comp::RenderStrip>,
+    );
+
+    fn run(&mut self, (mut sort_rndr, ent, strip): Self::SystemData) {
+        use specs::Join;
+
+        self.ins_render_strip.clear();
+        self.rem_render_strip.clear();
+        strip.populate_inserted(&mut self.render_strip_ins_read.as_mut().unwrap(), &mut self.ins_render_strip);
+        strip.populate_removed(&mut self.render_strip_rem_read.as_mut().unwrap(), &mut self.rem_render_strip);
+
+        sort_rndr.remove_strips(&self.rem_render_strip);
+
+        for (ent, _, _) in (&*ent, &strip, &self.ins_render_strip).join() {
+            sort_rndr.ids.push(res::RenderId::TileStrip(ent));
+            sort_rndr.need_sort = true;
+        }
+    }
+
+    fn setup(&mut self, res: &mut specs::Resources) {
+        use specs::prelude::SystemData;
+        Self::SystemData::setup(res);
+
+        let mut rndr_strip_storage: specs::WriteStorage<comp::RenderStrip> = SystemData::fetch(&res);
+        self.render_strip_ins_read = Some(rndr_strip_storage.track_inserted());
+        self.render_strip_rem_read = Some(rndr_strip_storage.track_removed());
+    }
+}
@@ -194,6 +194,177 @@ pub fn sweep_aabb(
     Some((t_first, t_last, normal))
 }
 
+// Projects a rect onto an axis, giving the (min, max) interval it covers along it.
+pub fn project_rect(rect: Rect3<f32>, axis: Vector3<f32>) -> (f32, f32) {
+    let (mut min, mut max) = (0.0, 0.0);
+
+    for i in 0..3 {
+        let (a, b) = (rect.min[i] * axis[i], rect.max[i] * axis[i]);
+        min += a.min(b);
+        max += a.max(b);
+    }
+
+    (min, max)
+}
+
+// Projects a convex polygon onto an axis, giving the (min, max) interval it covers along it.
+pub fn project_polygon(poly: &[Vector2<f32>], axis: Vector2<f32>) -> (f32, f32) {
+    poly.iter()
+        .map(|x| x.dot(axis))
+        .fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), x| (min.min(x), max.max(x)))
+}
+
+// The axes that could separate an AABB from a convex polygon (in the XY plane).
+fn polygon_axes(poly: &[Vector2<f32>]) -> Vec<Vector2<f32>> {
+    let mut axes = vec![Vector2::unit_x(), Vector2::unit_y()];
+
+    for i in 0..poly.len() {
+        let edge = poly[(i + 1) % poly.len()] - poly[i];
+
+        // Degenerate edges (e.g. a triangle given as a quad) have no normal.
+        if relative_eq!(edge.magnitude2(), 0.0) {
+            continue;
+        }
+
+        axes.push(Vector2::new(-edge.y, edge.x).normalize());
+    }
+
+    axes
+}
+
+/* NOTE:
+    Polygon collision is done with the separating axis theorem: two convex shapes don't intersect if there's an axis
+    (AABB faces or polygon edge normals) their projections don't overlap on. The polygon is a plane, with a depth
+    range standing in for its thickness on the Z axis (just like the other collision shapes).
+*/
+
+// The displacement needed to get an AABB out of a convex polygon, zero if they don't penetrate.
+pub fn penetration_polygon(aabb: Rect3<f32>, poly: &[Vector2<f32>], depth: (f32, f32)) -> Vector3<f32> {
+    penetration_polygons(&rect_polygon(aabb), (aabb.min.z, aabb.max.z), poly, depth)
+}
+
+// The displacement needed to get the first convex polygon out of the second, zero if they don't penetrate.
+pub fn penetration_polygons(
+    poly1: &[Vector2<f32>], depth1: (f32, f32), 
+    poly2: &[Vector2<f32>], depth2: (f32, f32)
+) -> Vector3<f32> {
+    if !(depth1.0 < depth2.1 && depth1.1 > depth2.0) {
+        return Vector3::zero();
+    }
+
+    let mut pen = Vector3::zero();
+    let mut min_dist = f32::INFINITY;
+
+    for axis in polygon_axes(poly1).into_iter().chain(polygon_axes(poly2)) {
+        let (a_min, a_max) = project_polygon(poly1, axis);
+        let (b_min, b_max) = project_polygon(poly2, axis);
+
+        if a_max <= b_min || b_max <= a_min {
+            return Vector3::zero();
+        }
+
+        // Get out of the polygon through whichever side is closer.
+        let (dist, dir) = if a_max - b_min < b_max - a_min {
+            (a_max - b_min, -1.0)
+        } else {
+            (b_max - a_min, 1.0)
+        };
+
+        if dist < min_dist {
+            min_dist = dist;
+            pen = (axis * dir * dist).extend(0.0);
+        }
+    }
+
+    pen
+}
+
+// The corners of a rect in the XY plane, as a polygon.
+fn rect_polygon(rect: Rect3<f32>) -> Vec<Vector2<f32>> {
+    vec![
+        Vector2::new(rect.min.x, rect.min.y),
+        Vector2::new(rect.max.x, rect.min.y),
+        Vector2::new(rect.max.x, rect.max.y),
+        Vector2::new(rect.min.x, rect.max.y),
+    ]
+}
+
+// Times of first and last contact between two intervals (the first moving at the speed relative to the second) and
+// the direction the first interval would be pushed to get out of the second.
+fn sweep_interval(a: (f32, f32), b: (f32, f32), v: f32) -> Option<(f32, f32, f32)> {
+    if a.1 <= b.0 {
+        if v <= 0.0 { return None; } // Nonintersecting and moving apart
+        Some(((b.0 - a.1) / v, (b.1 - a.0) / v, -1.0))
+    } else if b.1 <= a.0 {
+        if v >= 0.0 { return None; } // Nonintersecting and moving apart
+        Some(((b.1 - a.0) / v, (b.0 - a.1) / v, 1.0))
+    } else {
+        // Already overlapping, so the only question is when they stop.
+        let exit = if v > 0.0 {
+            (b.1 - a.0) / v
+        } else if v < 0.0 {
+            (b.0 - a.1) / v
+        } else {
+            f32::INFINITY
+        };
+
+        Some((f32::NEG_INFINITY, exit, 0.0))
+    }
+}
+
+pub fn sweep_polygon(
+    aabb1: Rect3<f32>, pos1: Vector3<f32>, disp1: Vector3<f32>, 
+    poly2: &[Vector2<f32>], depth2: (f32, f32), pos2: Vector3<f32>, disp2: Vector3<f32>
+) -> Option<(f32, f32, Vector3<f32>)> {
+    sweep_polygons(&rect_polygon(aabb1), (aabb1.min.z, aabb1.max.z), pos1, disp1, poly2, depth2, pos2, disp2)
+}
+
+pub fn sweep_polygons(
+    poly1: &[Vector2<f32>], depth1: (f32, f32), pos1: Vector3<f32>, disp1: Vector3<f32>, 
+    poly2: &[Vector2<f32>], depth2: (f32, f32), pos2: Vector3<f32>, disp2: Vector3<f32>
+) -> Option<(f32, f32, Vector3<f32>)> {
+    let poly1: Vec<_> = poly1.iter().map(|x| x + pos1.truncate()).collect();
+    let depth1 = (depth1.0 + pos1.z, depth1.1 + pos1.z);
+    let poly2: Vec<_> = poly2.iter().map(|x| x + pos2.truncate()).collect();
+    let depth2 = (depth2.0 + pos2.z, depth2.1 + pos2.z);
+    // Use relative velocity, essentially treating the second polygon as stationary.
+    let v = disp1 - disp2;
+
+    // Initialize times of first and last contact
+    let mut t_first = 0.0;
+    let mut t_last = 1.0;
+
+    let mut entry = f32::NEG_INFINITY;
+    let mut normal = Vector3::zero();
+
+    // For each axis, determine times of first and last contact, if any
+    let axes = polygon_axes(&poly1).into_iter().chain(polygon_axes(&poly2))
+        .map(|axis| (
+            project_polygon(&poly1, axis), 
+            project_polygon(&poly2, axis), 
+            axis.extend(0.0)
+        ))
+        .chain(Some((depth1, depth2, Vector3::unit_z())));
+
+    for (a, b, axis) in axes {
+        let (t_enter, t_exit, dir) = sweep_interval(a, b, v.dot(axis))?;
+
+        // The normal is along whichever axis was the last to come into contact.
+        if t_enter > entry {
+            entry = t_enter;
+            normal = axis * dir;
+        }
+
+        t_first = t_enter.max(t_first);
+        t_last = t_exit.min(t_last);
+
+        // No overlap possible if time of first contact occurs after time of last contact
+        if t_first > t_last { return None; }
+    }
+
+    Some((t_first, t_last, normal))
+}
+
 #[test]
 fn test_sweep_aabb() {
     let aabb = Rect3::new(
@@ -211,4 +382,76 @@ fn test_sweep_aabb() {
         .expect("No hit");
 
     assert_eq!(t_first, 0.5);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_polygon() {
+    let aabb = Rect3::new(
+        Vector3::new(0.0, 0.0, 0.0),
+        Vector3::new(1.0, 1.0, 1.0),
+    );
+
+    // A 45 degree slope rising to the right (with +y being down).
+    let slope = [
+        Vector2::new(0.0, 1.0),
+        Vector2::new(1.0, 1.0),
+        Vector2::new(1.0, 0.0),
+    ];
+
+    // Sitting halfway into the slope gets pushed out along its normal.
+    let pen = penetration_polygon(
+        Rect3::new(Vector3::new(0.25, -0.25, 0.0), Vector3::new(1.25, 0.75, 1.0)), 
+        &slope, 
+        (0.0, 1.0)
+    );
+    assert!(pen.x < 0.0 && pen.y < 0.0);
+    assert!(relative_eq!(pen.x, pen.y));
+
+    // Moving right (above the slope's base) runs into the slope's face.
+    let pos1 = Vector3::new(-2.0, -0.6, 0.0);
+    let disp1 = Vector3::new(3.0, 0.0, 0.0);
+
+    let (t_first, _, norm) = sweep_polygon(aabb, pos1, disp1, &slope, (0.0, 1.0), Vector3::new(1.0, 0.0, 0.0), Vector3::zero())
+        .expect("No hit");
+
+    assert!(t_first > 0.0 && t_first < 1.0);
+    assert!(norm.x < 0.0 && norm.y < 0.0);
+
+    // Moving away from the slope never hits it.
+    assert!(sweep_polygon(aabb, pos1, -disp1, &slope, (0.0, 1.0), Vector3::new(1.0, 0.0, 0.0), Vector3::zero()).is_none());
+}
+
+#[test]
+fn test_polygons() {
+    // A square turned 45 degrees, and a 45 degree slope rising to the right (with +y being down).
+    let diamond = [
+        Vector2::new(0.0, -0.5),
+        Vector2::new(0.5, 0.0),
+        Vector2::new(0.0, 0.5),
+        Vector2::new(-0.5, 0.0),
+    ];
+    let slope = [
+        Vector2::new(0.0, 1.0),
+        Vector2::new(1.0, 1.0),
+        Vector2::new(1.0, 0.0),
+    ];
+
+    // The diamond's corner is pushed back out through the slope's face.
+    let moved: Vec<_> = diamond.iter().map(|x| x + Vector2::new(0.5, 0.25)).collect();
+    let pen = penetration_polygons(&moved, (0.0, 1.0), &slope, (0.0, 1.0));
+    assert!(pen.x < 0.0 && pen.y < 0.0);
+    assert!(relative_eq!(pen.x, pen.y));
+
+    // Far enough apart, they don't penetrate.
+    let moved: Vec<_> = diamond.iter().map(|x| x + Vector2::new(-0.5, 0.0)).collect();
+    assert_eq!(penetration_polygons(&moved, (0.0, 1.0), &slope, (0.0, 1.0)), Vector3::zero());
+
+    // Falling onto the slope's flat bottom edge from below hits it going up.
+    let (t_first, _, norm) = sweep_polygons(
+        &diamond, (0.0, 1.0), Vector3::new(0.75, 2.0, 0.0), Vector3::new(0.0, -1.0, 0.0),
+        &slope, (0.0, 1.0), Vector3::zero(), Vector3::zero()
+    ).expect("No hit");
+
+    assert!(relative_eq!(t_first, 0.5));
+    assert_eq!(norm, Vector3::new(0.0, 1.0, 0.0));
+}